@@ -0,0 +1,19 @@
+//! Echo async input back out until EOF.
+//!
+//! Run with `cargo run --example async_input --features async`.
+
+use tokio::io::BufReader;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // Read a char, and while it isn't 0 (EOF), print it and read the next one.
+    let mut prgm = rbf::Program::from_string(",[.,]");
+
+    // Any `AsyncRead` works here, e.g. a `TcpStream`. A byte slice keeps the example simple.
+    let input = BufReader::new(&b"Hello from an async reader!\n"[..]);
+    let mut output = tokio::io::stdout();
+
+    if let Err(e) = prgm.execute_async(input, &mut output).await {
+        eprintln!("\n{}", e);
+    }
+}
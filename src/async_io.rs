@@ -0,0 +1,104 @@
+//! Async execution of BF programs, enabled with the `async` feature.
+//!
+//! Only the I/O instructions actually need to await anything. Every other instruction is run
+//! through the usual synchronous `Program::step()`.
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::{cell_byte, Instruct, Program, Result};
+
+impl Program {
+    /// Execute the entire BF program, awaiting input and output through async IO.
+    ///
+    /// Each `,` awaits a single byte from `input` and each `.` writes the current cell as a
+    /// single byte to `output`. Once `input` reaches EOF, reads give a cell value of 0, the same
+    /// as `BasicInput` does for a non-interactive terminal. `output` is flushed once the program
+    /// finishes.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`, or an error with kind
+    /// `BFErrorKind::IoError` if reading or writing fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string(",+.,+.");
+    /// let mut output = Vec::new();
+    ///
+    /// prgm.execute_async(&b"ab"[..], &mut output).await.unwrap();
+    /// assert_eq!(output, b"bc");
+    /// # });
+    /// ```
+    pub async fn execute_async<R, W>(&mut self, mut input: R, mut output: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let io_error = |_| BFError {
+            kind: BFErrorKind::IoError,
+        };
+
+        loop {
+            if self.done()? {
+                break;
+            }
+
+            match self.instructions.0.get(self.instruction_ptr) {
+                Some(Instruct::Input) => {
+                    let byte = match input.read_u8().await {
+                        Ok(byte) => byte,
+                        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+                        Err(e) => return Err(io_error(e)),
+                    };
+                    self.step(|| byte as char, |_| ())?;
+                }
+                Some(Instruct::Output) => {
+                    let mut byte = 0;
+                    self.step(|| '\0', |c| byte = cell_byte(c))?;
+                    output.write_u8(byte).await.map_err(io_error)?;
+                }
+                _ => self.step(|| '\0', |_| ())?,
+            }
+        }
+
+        output.flush().await.map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a future to completion on a single-threaded runtime.
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn async_matches_sync() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        let mut output = Vec::new();
+        block_on(Program::from_string(source).execute_async(&b""[..], &mut output)).unwrap();
+
+        assert_eq!(output, b"Hello World!\n");
+    }
+
+    #[test]
+    fn async_input_eof() {
+        // Reading past the end of the input gives 0, so this outputs the input and then 1.
+        let mut prgm = Program::from_string(",.,+.");
+
+        let mut output = Vec::new();
+        block_on(prgm.execute_async(&b"x"[..], &mut output)).unwrap();
+
+        assert_eq!(output, b"x\x01");
+    }
+}
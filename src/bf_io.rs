@@ -0,0 +1,425 @@
+//! Traits for byte input and output, for running programs without closures.
+//!
+//! BF only ever reads and writes single bytes, so `BfInput` and `BfOutput` are just that. The
+//! crate's own input and output types implement them, along with byte slices for preset input,
+//! `Vec<u8>` for collecting output, and adapters over `Read` and `Write` for files and the
+//! like. Implement them for anything else to plug it into `Program::run_with()`. For input and
+//! output over a single stream, like a socket, there's `Program::run_duplex()`.
+
+use std::io::{BufReader, Read, Write};
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::{cell_byte, BasicInput, BasicOutput, ChunkedOutput, Instruct, Program, Result};
+
+/// Most output bytes `Program::run_duplex()` holds back before writing them to the stream.
+const DUPLEX_BUFFER_SIZE: usize = 8192;
+
+/// A source of input bytes for a BF program.
+pub trait BfInput {
+    /// Read the next byte, or None once the input has run out.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if reading fails, which stops the program rather than counting as
+    /// the end of the input.
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>>;
+}
+
+/// Somewhere for a BF program to write its output bytes.
+pub trait BfOutput {
+    /// Write a single byte.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if writing fails, which stops the program.
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()>;
+}
+
+/// Reads the next byte off the front of the slice, moving the slice along past it.
+impl BfInput for &[u8] {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let Some((&byte, rest)) = self.split_first() else {
+            return Ok(None);
+        };
+        *self = rest;
+        Ok(Some(byte))
+    }
+}
+
+/// Reads each char as its code point. Chars that don't fit in a byte, which only a terminal
+/// can give, are read as `?`, since there's no byte that could stand for them.
+impl BfInput for BasicInput {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Ok(self.read_char().map(|c| u8::try_from(c).unwrap_or(b'?')))
+    }
+}
+
+/// Collects every byte written.
+impl BfOutput for Vec<u8> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.push(byte);
+        Ok(())
+    }
+}
+
+/// Prints each byte as the char with that code point, the same as `BasicOutput::print_char()`.
+impl BfOutput for BasicOutput {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.print_char(byte as char);
+        Ok(())
+    }
+}
+
+/// Buffers each byte as the char with that code point, the same as
+/// `ChunkedOutput::print_char()`, but returns an error if writing out a chunk fails.
+impl<W: Write> BfOutput for ChunkedOutput<W> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.buffer_char(byte as char)
+    }
+}
+
+/// Input read a byte at a time from any `Read`, such as a file.
+///
+/// Every byte is a separate read, so wrap anything slow to read from, like a file, in a
+/// `std::io::BufReader` first. The input runs out at the end of the reader, the same as for a
+/// non-interactive terminal, and a failed read is returned as an error.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::*;
+/// let mut prgm = Program::from_string(",[.,]");
+/// let mut input = ReadInput::new(&b"hi"[..]);
+/// let mut output = vec![];
+///
+/// prgm.run_with(&mut input, &mut output).unwrap();
+/// assert_eq!(output, b"hi");
+/// ```
+#[derive(Debug)]
+pub struct ReadInput<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> ReadInput<R> {
+    /// Create new ReadInput struct reading from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Get the underlying reader back.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> BfInput for ReadInput<R> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Output written as raw bytes to any `Write`, such as a file.
+///
+/// Unlike `BasicOutput`, nothing is encoded, so every byte the program outputs ends up in the
+/// writer exactly. Every byte is a separate write, so wrap anything slow to write to in a
+/// `std::io::BufWriter` first.
+#[derive(Debug)]
+pub struct WriteOutput<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> WriteOutput<W> {
+    /// Create new WriteOutput struct writing to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Get the underlying writer back.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> BfOutput for WriteOutput<W> {
+    fn write_byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.writer.write_all(&[byte])
+    }
+}
+
+impl Program {
+    /// Execute the entire BF program, reading from `input` and writing to `output`.
+    ///
+    /// This is the same as `Program::execute()`, but with the bytes coming from and going to
+    /// types that implement `BfInput` and `BfOutput` rather than closures. Once `input` runs
+    /// out, reads give 0.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`, or an error with kind
+    /// `BFErrorKind::IoError` if reading from `input` or writing to `output` fails. The program
+    /// stops right after the step that failed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string(",[+.,]");
+    /// let mut output = vec![];
+    ///
+    /// prgm.run_with(&mut &b"abc"[..], &mut output).unwrap();
+    /// assert_eq!(output, b"bcd");
+    /// ```
+    pub fn run_with<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<()>
+    where
+        I: BfInput,
+        O: BfOutput,
+    {
+        // Each error is kept separately, since the closures can't both borrow the same one.
+        let mut read_failed = false;
+        let mut write_failed = false;
+
+        while !self.done()? {
+            self.step(
+                || match input.read_byte() {
+                    Ok(byte) => byte.map_or('\0', char::from),
+                    Err(_) => {
+                        read_failed = true;
+                        '\0'
+                    }
+                },
+                |c| write_failed = output.write_byte(cell_byte(c)).is_err(),
+            )?;
+
+            if read_failed || write_failed {
+                return Err(BFError {
+                    kind: BFErrorKind::IoError,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Execute the entire BF program, reading input from and writing output to the same
+    /// `stream`, such as a `TcpStream`, so a program can serve a network connection.
+    ///
+    /// Output is buffered, but everything output so far is written and flushed before each
+    /// read, so whatever is on the other end of the stream gets to see it before it's expected
+    /// to send more. Otherwise the two ends could deadlock, each waiting on the other. Input is
+    /// read as it arrives, and once the stream reaches EOF, reads give 0. The rest of the
+    /// output is written and flushed when the program finishes, even if it fails.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`, or an error with kind
+    /// `BFErrorKind::IoError` if reading or writing fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rbf::Program;
+    /// use std::net::TcpListener;
+    ///
+    /// // Echo back each byte sent, one connection at a time.
+    /// let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
+    /// for stream in listener.incoming() {
+    ///     let mut prgm = Program::from_string(",[.,]");
+    ///     prgm.run_duplex(stream.unwrap()).unwrap();
+    /// }
+    /// ```
+    pub fn run_duplex<S>(&mut self, stream: S) -> Result<()>
+    where
+        S: Read + Write,
+    {
+        let mut stream = BufReader::new(stream);
+        let mut pending = vec![];
+
+        let result = self.run_duplex_steps(&mut stream, &mut pending);
+        let written = write_pending(stream.get_mut(), &mut pending);
+        result.and(written)
+    }
+
+    /// Run every step for `Program::run_duplex()`, leaving any output not yet written to the
+    /// stream in `pending`.
+    fn run_duplex_steps<S>(
+        &mut self,
+        stream: &mut BufReader<S>,
+        pending: &mut Vec<u8>,
+    ) -> Result<()>
+    where
+        S: Read + Write,
+    {
+        while !self.done()? {
+            match self.instructions.0.get(self.instruction_ptr) {
+                Some(Instruct::Input) => {
+                    write_pending(stream.get_mut(), pending)?;
+                    let byte = read_byte(stream)?;
+                    self.step(|| byte as char, |_| ())?;
+                }
+                Some(Instruct::Output) => {
+                    self.step(|| '\0', |c| pending.push(cell_byte(c)))?;
+                    if pending.len() >= DUPLEX_BUFFER_SIZE {
+                        write_pending(stream.get_mut(), pending)?;
+                    }
+                }
+                _ => self.step(|| '\0', |_| ())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write and flush the `pending` output to `stream`, emptying it.
+fn write_pending<W: Write>(stream: &mut W, pending: &mut Vec<u8>) -> Result<()> {
+    stream
+        .write_all(pending)
+        .and_then(|()| stream.flush())
+        .map_err(|_| BFError {
+            kind: BFErrorKind::IoError,
+        })?;
+    pending.clear();
+    Ok(())
+}
+
+/// Read a single byte from `stream`, or 0 at EOF.
+fn read_byte<R: Read>(stream: &mut R) -> Result<u8> {
+    let mut byte = [0];
+    loop {
+        match stream.read(&mut byte) {
+            Ok(0) => return Ok(0),
+            Ok(_) => return Ok(byte[0]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => {
+                return Err(BFError {
+                    kind: BFErrorKind::IoError,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_io() {
+        let mut input: &[u8] = b"a\xff";
+        assert_eq!(input.read_byte().unwrap(), Some(b'a'));
+        assert_eq!(input.read_byte().unwrap(), Some(0xff));
+        assert_eq!(input.read_byte().unwrap(), None);
+
+        let mut input = BasicInput::from_string("\u{ff}\u{100}");
+        assert_eq!(input.read_byte().unwrap(), Some(0xff));
+        assert_eq!(input.read_byte().unwrap(), Some(b'?'));
+        assert_eq!(input.read_byte().unwrap(), None);
+
+        // Bytes past 127 go through raw, not as UTF-8.
+        let mut prgm = Program::from_string(",[.,]");
+        let mut output = WriteOutput::new(vec![]);
+        prgm.run_with(&mut ReadInput::new(&b"\x01\x80\xfe"[..]), &mut output)
+            .unwrap();
+        assert_eq!(output.into_inner(), b"\x01\x80\xfe");
+    }
+
+    /// A reader and writer where every read and write fails.
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn byte_io_errors() {
+        let mut prgm = Program::from_string(",+.");
+        let result = prgm.run_with(&mut ReadInput::new(Broken), &mut vec![]);
+        assert_eq!(result.map_err(|e| e.kind), Err(BFErrorKind::IoError));
+        assert_eq!(prgm.instruction_pointer(), 1);
+
+        let mut prgm = Program::from_string("+.+.");
+        let result = prgm.run_with(&mut &b""[..], &mut WriteOutput::new(Broken));
+        assert_eq!(result.map_err(|e| e.kind), Err(BFErrorKind::IoError));
+        assert_eq!(prgm.tape(), [1]);
+
+        let mut output = ChunkedOutput::new(Broken).chunk_size(1);
+        assert!(output.write_byte(b'a').is_err());
+    }
+
+    /// A stream that hands out its input a byte per read, remembering how much output had been
+    /// written by the time of each read.
+    struct Conversation {
+        input: Vec<u8>,
+        written: Vec<u8>,
+        written_at_reads: Vec<usize>,
+    }
+
+    impl Read for Conversation {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.written_at_reads.push(self.written.len());
+            if self.input.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.input.remove(0);
+            Ok(1)
+        }
+    }
+
+    impl Write for Conversation {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn duplex_io() {
+        // Prompt with `>`, then echo each byte back until the input runs out.
+        let mut stream = Conversation {
+            input: b"ab".to_vec(),
+            written: vec![],
+            written_at_reads: vec![],
+        };
+        let mut prgm = Program::from_string("++++++++[>++++++++<-]>--.[-],[.,]");
+        prgm.run_duplex(&mut stream).unwrap();
+
+        assert_eq!(stream.written, b">ab");
+        // The prompt and each echo were written before waiting on more input.
+        assert_eq!(stream.written_at_reads, vec![1, 2, 3]);
+
+        // Output from before a failure still gets written.
+        let mut stream = Conversation {
+            input: vec![],
+            written: vec![],
+            written_at_reads: vec![],
+        };
+        let mut prgm = Program::from_string("+.<");
+        let result = prgm.run_duplex(&mut stream).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+        assert_eq!(stream.written, b"\x01");
+    }
+}
@@ -0,0 +1,237 @@
+//! A compact binary format for caching instructions, optimized or not.
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::{Instruct, Instructions, Result};
+
+/// First byte of every encoded program, bumped whenever the format changes.
+const FORMAT_VERSION: u8 = 1;
+
+impl Instructions {
+    /// Encode the instructions into the crate's binary format.
+    ///
+    /// Like `to_rle()`, this keeps the instructions exactly as they are, but it's smaller and
+    /// faster to load, which makes it the better choice for caching parsed and optimized
+    /// programs rather than for reading them. Load the bytes again with
+    /// `Instructions::from_bytes_encoded()`.
+    ///
+    /// The first byte is the format version. After it, each instruction is its BF command as a
+    /// byte. Moving the pointer or changing a cell is written as `>` or `+` followed by the
+    /// signed amount as a zigzag LEB128 varint, and setting a cell as `=` followed by the value
+    /// as a single byte. Moving a cell onto one other cell is written as `m` followed by the
+    /// offset as a varint, and onto two as `c` followed by both.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let mut instructions = Instructions::from_string("++++++++[->+<]");
+    /// instructions.optimize();
+    ///
+    /// let bytes = instructions.to_bytes();
+    /// assert_eq!(Instructions::from_bytes_encoded(&bytes).unwrap(), instructions);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![FORMAT_VERSION];
+
+        for instruction in &self.0 {
+            match *instruction {
+                Instruct::MvPtr(n) => {
+                    bytes.push(b'>');
+                    write_varint(&mut bytes, n);
+                }
+                Instruct::MvValue(n) => {
+                    bytes.push(b'+');
+                    write_varint(&mut bytes, n);
+                }
+                Instruct::SetValue(n) => bytes.extend([b'=', n]),
+                Instruct::Move(to) => {
+                    bytes.push(b'm');
+                    write_varint(&mut bytes, to);
+                }
+                Instruct::Copy(a, b) => {
+                    bytes.push(b'c');
+                    write_varint(&mut bytes, a);
+                    write_varint(&mut bytes, b);
+                }
+                Instruct::Output => bytes.push(b'.'),
+                Instruct::Input => bytes.push(b','),
+                Instruct::InputLen => bytes.push(b'?'),
+                Instruct::OpenLoop => bytes.push(b'['),
+                Instruct::CloseLoop => bytes.push(b']'),
+            }
+        }
+
+        bytes
+    }
+
+    /// Decode instructions from the binary format made by `Instructions::to_bytes()`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::InvalidFormat` if the bytes are empty,
+    /// start with a version this crate doesn't know, contain anything other than encoded
+    /// instructions, or end partway through one.
+    pub fn from_bytes_encoded(bytes: &[u8]) -> Result<Instructions> {
+        let invalid = || BFError {
+            kind: BFErrorKind::InvalidFormat,
+        };
+
+        let (&version, mut rest) = bytes.split_first().ok_or_else(invalid)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid());
+        }
+
+        let mut instructions = vec![];
+        while let Some((&command, after)) = rest.split_first() {
+            rest = after;
+
+            let instruction = match command {
+                b'>' => Instruct::MvPtr(read_varint(&mut rest).ok_or_else(invalid)?),
+                b'+' => Instruct::MvValue(read_varint(&mut rest).ok_or_else(invalid)?),
+                b'=' => {
+                    let (&value, after) = rest.split_first().ok_or_else(invalid)?;
+                    rest = after;
+                    Instruct::SetValue(value)
+                }
+                b'm' => Instruct::Move(read_varint(&mut rest).ok_or_else(invalid)?),
+                b'c' => {
+                    let a = read_varint(&mut rest).ok_or_else(invalid)?;
+                    let b = read_varint(&mut rest).ok_or_else(invalid)?;
+                    Instruct::Copy(a, b)
+                }
+                b'.' => Instruct::Output,
+                b',' => Instruct::Input,
+                b'?' => Instruct::InputLen,
+                b'[' => Instruct::OpenLoop,
+                b']' => Instruct::CloseLoop,
+                _ => return Err(invalid()),
+            };
+            instructions.push(instruction);
+        }
+
+        Ok(Instructions(instructions, None))
+    }
+}
+
+/// Append `n` as a zigzag LEB128 varint, so small amounts either way take a single byte.
+fn write_varint(bytes: &mut Vec<u8>, n: isize) {
+    let n = n as i64;
+    let mut zigzag = ((n << 1) ^ (n >> 63)) as u64;
+
+    while zigzag >= 0x80 {
+        bytes.push(zigzag as u8 | 0x80);
+        zigzag >>= 7;
+    }
+    bytes.push(zigzag as u8);
+}
+
+/// Read a varint written by `write_varint()` off the front of `bytes`.
+///
+/// Returns `None` if the bytes end before the varint does or it doesn't fit in an `isize`.
+fn read_varint(bytes: &mut &[u8]) -> Option<isize> {
+    let mut zigzag: u64 = 0;
+    let mut shift = 0;
+
+    loop {
+        let (&byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+
+        let bits = u64::from(byte & 0x7f);
+        if shift >= 64 || (shift == 63 && bits > 1) {
+            return None;
+        }
+        zigzag |= bits << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    let n = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+    isize::try_from(n).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.,+-<>";
+
+        let plain = Instructions::from_string(source);
+        assert_eq!(
+            Instructions::from_bytes_encoded(&plain.to_bytes()).unwrap(),
+            plain
+        );
+
+        let mut combined = Instructions::from_string(source);
+        combined.optimize_combine_multiples();
+        assert_eq!(
+            Instructions::from_bytes_encoded(&combined.to_bytes()).unwrap(),
+            combined
+        );
+
+        let mut optimized = Instructions::from_string(&format!("{}[-]+++", source));
+        optimized.optimize();
+        assert!(optimized.to_rle().contains('='));
+        assert_eq!(
+            Instructions::from_bytes_encoded(&optimized.to_bytes()).unwrap(),
+            optimized
+        );
+
+        let extended = Instructions::from_string_extended("?[>+<-]");
+        assert_eq!(
+            Instructions::from_bytes_encoded(&extended.to_bytes()).unwrap(),
+            extended
+        );
+
+        // Amounts of every size survive, including ones that take the whole varint.
+        let amounts = [0, 1, -1, 63, -64, 64, 300, -300, isize::MAX, isize::MIN];
+        let extremes = Instructions(
+            amounts.iter().map(|&n| Instruct::MvValue(n)).collect(),
+            None,
+        );
+        assert_eq!(
+            Instructions::from_bytes_encoded(&extremes.to_bytes()).unwrap(),
+            extremes
+        );
+    }
+
+    #[test]
+    fn bytes_encoding() {
+        assert_eq!(Instructions::from_string("").to_bytes(), [FORMAT_VERSION]);
+        assert_eq!(
+            Instructions::from_rle("+3 <2 =7 .").unwrap().to_bytes(),
+            [FORMAT_VERSION, b'+', 6, b'>', 3, b'=', 7, b'.']
+        );
+
+        for bytes in [
+            &[][..],
+            &[FORMAT_VERSION + 1],
+            &[FORMAT_VERSION, b'x'],
+            &[FORMAT_VERSION, b'+'],
+            &[FORMAT_VERSION, b'+', 0x80],
+            &[FORMAT_VERSION, b'='],
+            &[FORMAT_VERSION, b'c', 2],
+            &[
+                FORMAT_VERSION,
+                b'>',
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0x7f,
+            ],
+        ] {
+            let result = Instructions::from_bytes_encoded(bytes).map_err(|e| e.kind);
+            assert_eq!(result, Err(BFErrorKind::InvalidFormat), "{:?}", bytes);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Generating BF programs from Rust.
+//!
+//! Writing BF by hand gets old fast, so these build known-correct programs for small tasks. They
+//! work both as references for how BF solves a problem and as examples of putting together
+//! `Instructions` from code rather than from a source file.
+
+use crate::Instructions;
+
+/// Generate a program that reads two digit characters and outputs their sum in decimal.
+///
+/// The sum of two digits is at most 18, so the output is either one or two digits. The program
+/// assumes both input characters are digits from `'0'` to `'9'`.
+///
+/// It works by reading both digits, converting them from ascii to numbers and adding them
+/// together. The sum is then split into tens and ones using the divmod algorithm from
+/// [esolangs](https://esolangs.org/wiki/Brainfuck_algorithms#Divmod_algorithm), and the tens
+/// digit is only output if it isn't zero.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::{codegen, Program};
+/// let mut prgm = Program::new(codegen::add_digits());
+/// let mut input = "75".chars();
+/// let mut output = String::new();
+///
+/// prgm.execute(|| input.next().unwrap(), |c| output.push(c)).unwrap();
+/// assert_eq!(output, "12");
+/// ```
+pub fn add_digits() -> Instructions {
+    let mut source = String::new();
+
+    // Cell 0 gets the first digit and cell 1 the second, both as numbers rather than ascii.
+    source += ",";
+    source += &"-".repeat(b'0' as usize);
+    source += ">,";
+    source += &"-".repeat(b'0' as usize);
+
+    // Add cell 1 into cell 0, then put the divisor in cell 2.
+    source += "[<+>-]>";
+    source += &"+".repeat(10);
+    source += "<<";
+
+    // Divmod leaves the ones in cell 3 and the tens in cell 4.
+    source += "[->+>-[>+>>]>[+[-<+>]>+>>]<<<<<<]";
+
+    // Output the tens only if there are any, clearing the cell so the loop runs at most once.
+    source += ">>>>[";
+    source += &"+".repeat(b'0' as usize);
+    source += ".[-]]";
+
+    // Always output the ones.
+    source += "<";
+    source += &"+".repeat(b'0' as usize);
+    source += ".";
+
+    Instructions::from_string(&source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    #[test]
+    fn add_digits_all_inputs() {
+        for a in 0..10 {
+            for b in 0..10 {
+                let mut prgm = Program::new(add_digits());
+                let mut input = [a, b].map(|d| char::from(b'0' + d)).into_iter();
+                let mut output = String::new();
+
+                prgm.execute(|| input.next().unwrap(), |c| output.push(c))
+                    .unwrap();
+
+                assert_eq!(output, (a + b).to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Keeping the comments in BF source, so tools can change the code without losing them.
+
+use std::collections::BTreeMap;
+
+use crate::{shebang_len, Instruct, Instructions};
+
+/// The comments in a piece of BF source, from `Instructions::from_string_with_comments()`.
+///
+/// Everything in the source that isn't a command is a comment, including whitespace, so this
+/// holds the exact text between commands. Each comment is keyed by how many instructions come
+/// before it, which is the index of the instruction it comes right after plus 1, so a comment
+/// at the very start of the source is keyed by 0. A tool that adds or removes instructions
+/// can move the keys along to keep each comment next to the code it describes, and
+/// `Instructions::to_string_with_comments()` puts it all back together.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::Instructions;
+/// let (_, comments) = Instructions::from_string_with_comments("add two ++ then print .");
+///
+/// assert_eq!(comments.get(0), Some("add two "));
+/// assert_eq!(comments.get(2), Some(" then print "));
+/// assert_eq!(comments.get(3), None);
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CommentMap {
+    /// The text of each comment, keyed by how many instructions come before it.
+    pub comments: BTreeMap<usize, String>,
+}
+
+impl CommentMap {
+    /// Create an empty comment map.
+    pub fn new() -> CommentMap {
+        CommentMap::default()
+    }
+
+    /// Get the comment after the first `instructions` instructions, if there is one.
+    pub fn get(&self, instructions: usize) -> Option<&str> {
+        self.comments.get(&instructions).map(String::as_str)
+    }
+}
+
+impl Instructions {
+    /// Convert a string slice of commands into Instructions, keeping the comments too.
+    ///
+    /// The instructions are exactly the same as from `Instructions::from_string()`, and the
+    /// comments are everything else, as described on `CommentMap`, including any shebang line.
+    /// Gathering comments takes extra work, so only use this when they're needed.
+    pub fn from_string_with_comments(commands: &str) -> (Instructions, CommentMap) {
+        let mut instructions = vec![];
+        let mut comments = CommentMap::new();
+
+        let (shebang, commands) = commands.split_at(shebang_len(commands));
+        if !shebang.is_empty() {
+            comments.comments.insert(0, shebang.to_string());
+        }
+
+        for c in commands.chars() {
+            match Instruct::from_char(c) {
+                Some(instruction) => instructions.push(instruction),
+                None => comments
+                    .comments
+                    .entry(instructions.len())
+                    .or_default()
+                    .push(c),
+            }
+        }
+
+        (Instructions(instructions, None), comments)
+    }
+
+    /// Serialize the instructions back into BF source with `comments` put back in place.
+    ///
+    /// Each instruction is written the same way as by `to_string()`, with each comment right
+    /// after the instructions before it. Comments keyed past the end of the instructions go at
+    /// the end. For instructions and comments straight from
+    /// `Instructions::from_string_with_comments()`, this gives back the original source
+    /// exactly.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{CommentMap, Instructions};
+    /// let source = "Clear the cell [-] and then add one +\n";
+    /// let (mut instructions, comments) = Instructions::from_string_with_comments(source);
+    /// assert_eq!(instructions.to_string_with_comments(&comments), source);
+    ///
+    /// // The clear loop becomes a single instruction, so the comments after it move back two.
+    /// instructions.optimize_clear_loops();
+    /// let mut moved = CommentMap::new();
+    /// for (key, comment) in comments.comments {
+    ///     moved.comments.insert(if key > 0 { key - 2 } else { key }, comment);
+    /// }
+    /// assert_eq!(instructions.to_string_with_comments(&moved), source);
+    /// ```
+    pub fn to_string_with_comments(&self, comments: &CommentMap) -> String {
+        let mut source = String::new();
+        let mut comments = comments.comments.iter().peekable();
+
+        for (idx, instruction) in self.0.iter().enumerate() {
+            while let Some((_, comment)) = comments.next_if(|&(&key, _)| key <= idx) {
+                source += comment;
+            }
+            source += &instruction.to_string();
+        }
+        for (_, comment) in comments {
+            source += comment;
+        }
+
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_round_trip() {
+        for source in [
+            "",
+            "no commands at all",
+            "+++",
+            "Hello\n  +[->+<] end\n",
+            "[ loop ]]] ü ,.",
+        ] {
+            let (instructions, comments) = Instructions::from_string_with_comments(source);
+            assert_eq!(instructions, Instructions::from_string(source));
+            assert_eq!(instructions.to_string_with_comments(&comments), source);
+        }
+
+        let (_, comments) = Instructions::from_string_with_comments("a+b-c");
+        let keys: Vec<usize> = comments.comments.keys().copied().collect();
+        assert_eq!(keys, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn comments_past_the_end() {
+        let instructions = Instructions::from_string("+-");
+        let mut comments = CommentMap::new();
+        comments.comments.insert(1, " between ".to_string());
+        comments.comments.insert(7, " far ".to_string());
+        comments.comments.insert(9, "away".to_string());
+
+        assert_eq!(
+            instructions.to_string_with_comments(&comments),
+            "+ between - far away"
+        );
+    }
+}
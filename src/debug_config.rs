@@ -0,0 +1,302 @@
+//! Debugger settings that can be saved to a file and loaded again in a later session.
+
+use std::fmt;
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::replay::parse_fields;
+use crate::{Program, Result};
+
+/// First line of every serialized config, naming the format and its version.
+const HEADER: &str = "rbf-debug 1";
+
+/// Where to stop a program being debugged, for `Program::run_debug()`.
+///
+/// # Format
+///
+/// Configs serialize to and from text with `to_string()` and `DebugConfig::from_string()`, the
+/// same way as `ExecutionLog`, so they can be kept in a file between debugging sessions. It's
+/// four lines:
+///
+/// ```text
+/// rbf-debug 1
+/// breakpoints 4 10
+/// watchpoints 1=6 0=0
+/// step-limit 100
+/// ```
+///
+/// The `breakpoints` line lists instruction indices, and the `watchpoints` line lists cell
+/// indices and the value to watch each one for. The `step-limit` line is `none` if there's no
+/// limit. All the lines are present even if they list nothing.
+///
+/// With the `serde` feature enabled, configs also implement `Serialize` and `Deserialize`, for
+/// keeping them in another format alongside other settings.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DebugConfig {
+    /// Instruction indices to stop at, before the instruction runs.
+    pub breakpoints: Vec<usize>,
+    /// Cells to stop at once they hold a value, as the cell index and the value.
+    pub watchpoints: Vec<(usize, u8)>,
+    /// Most steps to run before stopping anyway, or None to only stop at the points above. A
+    /// limit of 0 still runs one step, the same as a limit of 1.
+    pub step_limit: Option<usize>,
+}
+
+/// Why `Program::run_debug()` stopped.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DebugStatus {
+    /// The program ran to the end.
+    Finished,
+    /// The program reached the breakpoint at this instruction index.
+    Breakpoint(usize),
+    /// The cell at this index got the value it was being watched for.
+    Watchpoint(usize),
+    /// The program ran for the step limit without stopping otherwise.
+    StepLimit,
+}
+
+impl DebugConfig {
+    /// Create a config that never stops the program.
+    pub fn new() -> DebugConfig {
+        DebugConfig::default()
+    }
+
+    /// Parse a config from its text format.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::InvalidFormat` if the text isn't a config
+    /// in the format described on `DebugConfig`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::DebugConfig;
+    /// let text = "rbf-debug 1\nbreakpoints 3\nwatchpoints 0=5\nstep-limit none\n";
+    /// let config = DebugConfig::from_string(text).unwrap();
+    ///
+    /// assert_eq!(config.breakpoints, vec![3]);
+    /// assert_eq!(config.watchpoints, vec![(0, 5)]);
+    /// assert_eq!(config.to_string(), text);
+    /// ```
+    pub fn from_string(config: &str) -> Result<DebugConfig> {
+        let invalid = || BFError {
+            kind: BFErrorKind::InvalidFormat,
+        };
+
+        let [breakpoints, watchpoints, step_limit] =
+            parse_fields(config, HEADER, ["breakpoints", "watchpoints", "step-limit"])?;
+        let breakpoints = breakpoints
+            .map(|idx| idx.parse().map_err(|_| invalid()))
+            .collect::<Result<Vec<usize>>>()?;
+        let watchpoints = watchpoints
+            .map(|watch| {
+                let (cell, value) = watch.split_once('=').ok_or_else(invalid)?;
+                Ok((
+                    cell.parse().map_err(|_| invalid())?,
+                    value.parse().map_err(|_| invalid())?,
+                ))
+            })
+            .collect::<Result<Vec<(usize, u8)>>>()?;
+        let step_limit = match step_limit.collect::<Vec<_>>()[..] {
+            ["none"] => None,
+            [limit] => Some(limit.parse().map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        };
+
+        Ok(DebugConfig {
+            breakpoints,
+            watchpoints,
+            step_limit,
+        })
+    }
+}
+
+impl fmt::Display for DebugConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+
+        write!(f, "breakpoints")?;
+        for idx in &self.breakpoints {
+            write!(f, " {}", idx)?;
+        }
+        writeln!(f)?;
+
+        write!(f, "watchpoints")?;
+        for (cell, value) in &self.watchpoints {
+            write!(f, " {}={}", cell, value)?;
+        }
+        writeln!(f)?;
+
+        match self.step_limit {
+            Some(limit) => writeln!(f, "step-limit {}", limit),
+            None => writeln!(f, "step-limit none"),
+        }
+    }
+}
+
+impl Program {
+    /// Execute the BF program until it reaches one of the points in `config` where it should
+    /// stop.
+    ///
+    /// Breakpoints stop the program right before the instruction runs, and watchpoints stop it
+    /// right after the step that gave the cell its value, the same as
+    /// `Program::run_until_cell()`. The program isn't reset, so calling this again continues
+    /// from where it stopped. It always runs at least one step first, so it doesn't stop again
+    /// straight away at the breakpoint it's already on.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{DebugConfig, DebugStatus, Program};
+    /// let mut prgm = Program::from_string("+++[>+<-]");
+    /// let config = DebugConfig {
+    ///     breakpoints: vec![4],
+    ///     ..DebugConfig::new()
+    /// };
+    ///
+    /// let status = prgm.run_debug(&config, || '\0', |_| ()).unwrap();
+    /// assert_eq!(status, DebugStatus::Breakpoint(4));
+    /// assert_eq!(prgm.tape(), [3]);
+    ///
+    /// prgm.run_debug(&config, || '\0', |_| ()).unwrap();
+    /// assert_eq!(prgm.tape(), [2, 1]);
+    /// ```
+    pub fn run_debug<Fin, Fout>(
+        &mut self,
+        config: &DebugConfig,
+        mut input: Fin,
+        mut output: Fout,
+    ) -> Result<DebugStatus>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        let mut steps = 0;
+
+        loop {
+            if self.done()? {
+                return Ok(DebugStatus::Finished);
+            }
+            if steps > 0 && config.breakpoints.contains(&self.instruction_ptr) {
+                return Ok(DebugStatus::Breakpoint(self.instruction_ptr));
+            }
+            if config.step_limit.is_some_and(|limit| steps >= limit.max(1)) {
+                return Ok(DebugStatus::StepLimit);
+            }
+
+            self.step(&mut input, &mut output)?;
+            steps += 1;
+
+            let watched = config
+                .watchpoints
+                .iter()
+                .find(|&&(cell, value)| self.cells.get(cell).copied().unwrap_or(0) == value);
+            if let Some(&(cell, _)) = watched {
+                return Ok(DebugStatus::Watchpoint(cell));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trip() {
+        let config = DebugConfig {
+            breakpoints: vec![4, 10],
+            watchpoints: vec![(1, 6), (0, 0)],
+            step_limit: Some(100),
+        };
+
+        let text = config.to_string();
+        assert_eq!(
+            text,
+            "rbf-debug 1\nbreakpoints 4 10\nwatchpoints 1=6 0=0\nstep-limit 100\n"
+        );
+        assert_eq!(DebugConfig::from_string(&text).unwrap(), config);
+
+        let empty = DebugConfig::new();
+        assert_eq!(DebugConfig::from_string(&empty.to_string()).unwrap(), empty);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        for text in [
+            "",
+            "rbf-debug 2\nbreakpoints\nwatchpoints\nstep-limit none\n",
+            "rbf-debug 1\nwatchpoints\nbreakpoints\nstep-limit none\n",
+            "rbf-debug 1\nbreakpoints -1\nwatchpoints\nstep-limit none\n",
+            "rbf-debug 1\nbreakpoints\nwatchpoints 1\nstep-limit none\n",
+            "rbf-debug 1\nbreakpoints\nwatchpoints 1=256\nstep-limit none\n",
+            "rbf-debug 1\nbreakpoints\nwatchpoints\nstep-limit\n",
+            "rbf-debug 1\nbreakpoints\nwatchpoints\nstep-limit 1 2\n",
+            "rbf-debug 1\nbreakpoints\nwatchpoints\n",
+            "rbf-debug 1\nbreakpoints\nwatchpoints\nstep-limit none\nmore\n",
+        ] {
+            let result = DebugConfig::from_string(text).map_err(|e| e.kind);
+            assert_eq!(result, Err(BFErrorKind::InvalidFormat), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn debug_stops() {
+        let run = |config: &DebugConfig| {
+            let mut prgm = Program::from_string("++[>+<-]>.");
+            let mut statuses = vec![];
+            loop {
+                let status = prgm.run_debug(config, || '\0', |_| ()).unwrap();
+                statuses.push((status, prgm.instruction_pointer()));
+                if status == DebugStatus::Finished {
+                    return statuses;
+                }
+            }
+        };
+
+        let config = DebugConfig {
+            breakpoints: vec![8],
+            step_limit: Some(4),
+            ..DebugConfig::new()
+        };
+        assert_eq!(
+            run(&config),
+            vec![
+                (DebugStatus::StepLimit, 4),
+                (DebugStatus::StepLimit, 2),
+                (DebugStatus::StepLimit, 6),
+                (DebugStatus::Breakpoint(8), 8),
+                (DebugStatus::Finished, 10),
+            ]
+        );
+
+        // Watchpoints stop on every step while the cell holds the value.
+        let config = DebugConfig {
+            watchpoints: vec![(0, 0)],
+            ..DebugConfig::new()
+        };
+        assert_eq!(
+            run(&config),
+            vec![
+                (DebugStatus::Watchpoint(0), 7),
+                (DebugStatus::Watchpoint(0), 2),
+                (DebugStatus::Watchpoint(0), 8),
+                (DebugStatus::Watchpoint(0), 9),
+                (DebugStatus::Watchpoint(0), 10),
+                (DebugStatus::Finished, 10),
+            ]
+        );
+
+        // A limit of 0 still gets somewhere, one step at a time.
+        let config = |step_limit| DebugConfig {
+            step_limit: Some(step_limit),
+            ..DebugConfig::new()
+        };
+        assert_eq!(run(&config(0)), run(&config(1)));
+    }
+}
@@ -0,0 +1,115 @@
+//! Rendering the control flow of BF instructions as a GraphViz DOT graph.
+
+use std::fmt::Write;
+
+use crate::{Instructions, Result};
+
+/// Longest a node's label gets before the code in it is cut short.
+const MAX_LABEL_LEN: usize = 40;
+
+impl Instructions {
+    /// Render the control flow of the instructions as a GraphViz DOT graph.
+    ///
+    /// Each node is one of the blocks from `Instructions::basic_blocks()`, a straight-line
+    /// segment of the instructions between two brackets or between a bracket and the start or
+    /// end of the program, labelled with its code. Every bracket is
+    /// a branch, so the segment before it gets two edges: one labelled `nonzero` for when the
+    /// current cell isn't 0, and one labelled `zero` for when it is. At a `[`, a nonzero cell
+    /// goes into the loop body and a zero cell skips past the matching `]`. At a `]`, a
+    /// nonzero cell goes back to the start of the body and a zero cell leaves the loop. The
+    /// first node is where the program starts and the last is where it ends.
+    ///
+    /// Render it with `dot`:
+    ///
+    /// ```sh
+    /// dot -Tsvg program.dot -o program.svg
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`
+    /// if the brackets don't match.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let dot = Instructions::from_string("+++[>+<-]>.").to_dot().unwrap();
+    ///
+    /// assert!(dot.starts_with("digraph bf {"));
+    /// assert!(dot.contains("s0 [label=\"+++\"];"));
+    /// assert!(dot.contains("s1 -> s1 [label=\"nonzero\"];"));
+    /// ```
+    pub fn to_dot(&self) -> Result<String> {
+        let blocks = self.basic_blocks()?;
+
+        // Writing to a String can't fail, so the fmt::Results are ignored throughout.
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph bf {{");
+        let _ = writeln!(dot, "    node [shape=box, fontname=\"monospace\"];");
+
+        for (segment, block) in blocks.iter().enumerate() {
+            let code = Instructions(self.0[block.range.clone()].to_vec(), None).to_string();
+            let label = match code.char_indices().nth(MAX_LABEL_LEN) {
+                Some((cut, _)) => format!("{}...", &code[..cut]),
+                None => code,
+            };
+            let _ = writeln!(dot, "    s{} [label=\"{}\"];", segment, label);
+        }
+
+        for (segment, block) in blocks.iter().enumerate() {
+            if let [nonzero, zero] = block.successors[..] {
+                let _ = writeln!(dot, "    s{} -> s{} [label=\"nonzero\"];", segment, nonzero);
+                let _ = writeln!(dot, "    s{} -> s{} [label=\"zero\"];", segment, zero);
+            }
+        }
+
+        let _ = writeln!(dot, "}}");
+        Ok(dot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::BFErrorKind;
+
+    fn count(dot: &str, pattern: &str) -> usize {
+        dot.lines().filter(|line| line.contains(pattern)).count()
+    }
+
+    #[test]
+    fn dot_structure() {
+        let dot = Instructions::from_string(",[>+[-]<-]>.").to_dot().unwrap();
+
+        // Four brackets make five segments, with two branches each.
+        assert_eq!(count(&dot, "[label=") - count(&dot, " -> "), 5);
+        assert_eq!(count(&dot, " -> "), 8);
+        for edge in [
+            "s0 -> s1 [label=\"nonzero\"]",
+            "s0 -> s4 [label=\"zero\"]",
+            "s1 -> s2 [label=\"nonzero\"]",
+            "s1 -> s3 [label=\"zero\"]",
+            "s2 -> s2 [label=\"nonzero\"]",
+            "s2 -> s3 [label=\"zero\"]",
+            "s3 -> s1 [label=\"nonzero\"]",
+            "s3 -> s4 [label=\"zero\"]",
+        ] {
+            assert!(dot.contains(edge), "{}", edge);
+        }
+        assert!(dot.contains("s4 [label=\">.\"]"));
+
+        // No loops is a single segment with nowhere to go.
+        let dot = Instructions::from_string("+.").to_dot().unwrap();
+        assert_eq!(count(&dot, "[label="), 1);
+        assert_eq!(count(&dot, " -> "), 0);
+
+        let long = Instructions::from_string(&"+".repeat(100))
+            .to_dot()
+            .unwrap();
+        assert!(long.contains(&format!("[label=\"{}...\"]", "+".repeat(MAX_LABEL_LEN))));
+
+        let result = Instructions::from_string("[").to_dot().map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingClose));
+    }
+}
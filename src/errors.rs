@@ -16,6 +16,86 @@ pub enum BFErrorKind {
     CellBoundsError,
     /// When the instruction pointer is out of the bounds of the instructions vec.
     InstructionBoundsError,
+    /// When reading input or writing output failed at the I/O level.
+    IoError,
+    /// When a program didn't finish within its step budget.
+    StepLimitExceeded,
+    /// When a cell value can't be output as a char in strict output mode.
+    InvalidOutput,
+    /// When a program tried to output more than its output limit.
+    OutputLimitExceeded,
+    /// When serialized data, like an execution log, couldn't be parsed.
+    InvalidFormat,
+    /// When a replayed program didn't execute the same instructions as its log.
+    ReplayMismatch,
+    /// When a program has more instructions than it's allowed to.
+    ProgramTooLarge,
+    /// When skipping a loop would scan further than the skip limit.
+    SkipLimitExceeded,
+    /// When a program asks how much input is left but the input has no known length.
+    UnknownInputLength,
+    /// When a program keeps reading after its input has run out, without doing anything else.
+    InputExhausted,
+    /// When a program didn't finish within its time limit.
+    Timeout,
+    /// When breaking out of a loop while not inside one.
+    NotInLoop,
+}
+
+impl BFErrorKind {
+    /// Get a stable numeric code for the kind of error.
+    ///
+    /// Codes never change between versions, and new kinds always get new codes, so tools can
+    /// store or report them, or map them to their own exit codes or messages, without matching
+    /// on the enum itself.
+    ///
+    /// | Code | Kind |
+    /// | ---- | ---- |
+    /// | 1 | `MissingOpen` |
+    /// | 2 | `MissingClose` |
+    /// | 3 | `InvalidInput` |
+    /// | 4 | `CellBoundsError` |
+    /// | 5 | `InstructionBoundsError` |
+    /// | 6 | `IoError` |
+    /// | 7 | `StepLimitExceeded` |
+    /// | 8 | `InvalidOutput` |
+    /// | 9 | `OutputLimitExceeded` |
+    /// | 10 | `InvalidFormat` |
+    /// | 11 | `ReplayMismatch` |
+    /// | 12 | `ProgramTooLarge` |
+    /// | 13 | `SkipLimitExceeded` |
+    /// | 14 | `UnknownInputLength` |
+    /// | 15 | `InputExhausted` |
+    /// | 16 | `Timeout` |
+    /// | 17 | `NotInLoop` |
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::errors::BFErrorKind;
+    /// assert_eq!(BFErrorKind::CellBoundsError.code(), 4);
+    /// ```
+    pub fn code(&self) -> u16 {
+        match self {
+            BFErrorKind::MissingOpen => 1,
+            BFErrorKind::MissingClose => 2,
+            BFErrorKind::InvalidInput => 3,
+            BFErrorKind::CellBoundsError => 4,
+            BFErrorKind::InstructionBoundsError => 5,
+            BFErrorKind::IoError => 6,
+            BFErrorKind::StepLimitExceeded => 7,
+            BFErrorKind::InvalidOutput => 8,
+            BFErrorKind::OutputLimitExceeded => 9,
+            BFErrorKind::InvalidFormat => 10,
+            BFErrorKind::ReplayMismatch => 11,
+            BFErrorKind::ProgramTooLarge => 12,
+            BFErrorKind::SkipLimitExceeded => 13,
+            BFErrorKind::UnknownInputLength => 14,
+            BFErrorKind::InputExhausted => 15,
+            BFErrorKind::Timeout => 16,
+            BFErrorKind::NotInLoop => 17,
+        }
+    }
 }
 
 /// Represents a BF error.
@@ -40,9 +120,67 @@ impl fmt::Display for BFError {
                 BFErrorKind::CellBoundsError => "Tried to access cell out of bounds",
                 BFErrorKind::InstructionBoundsError =>
                     "Tried to process instruction out of bounds.",
+                BFErrorKind::IoError => "An I/O error occurred during program input or output.",
+                BFErrorKind::StepLimitExceeded =>
+                    "The program did not finish within the step limit.",
+                BFErrorKind::InvalidOutput => "Tried to output a cell value that isn't ascii.",
+                BFErrorKind::OutputLimitExceeded => "The program exceeded its output limit.",
+                BFErrorKind::InvalidFormat => "The data is not in a valid format.",
+                BFErrorKind::ReplayMismatch =>
+                    "The program did not execute the same instructions as the replayed log.",
+                BFErrorKind::ProgramTooLarge => "The program has too many instructions.",
+                BFErrorKind::SkipLimitExceeded =>
+                    "Skipping a loop went further than the skip limit.",
+                BFErrorKind::UnknownInputLength => "The length of the input isn't known.",
+                BFErrorKind::InputExhausted => "The program kept reading after its input ran out.",
+                BFErrorKind::Timeout => "The program did not finish within the time limit.",
+                BFErrorKind::NotInLoop => "The program is not inside a loop.",
             }
         )
     }
 }
 
 impl error::Error for BFError {}
+
+/// A bracket error in BF source, along with where it is.
+///
+/// Returned by `rbf::validate()` and `Instructions::check_all_brackets()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BracketError {
+    /// Either `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`.
+    pub kind: BFErrorKind,
+    /// Where the unmatched bracket is, counted as `position_kind` says.
+    pub position: usize,
+    /// Whether `position` is a byte offset in the source or an instruction index.
+    pub position_kind: PositionKind,
+}
+
+/// What the position of a `BracketError` counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionKind {
+    /// A byte offset in the source, from `rbf::validate()`.
+    Byte,
+    /// An instruction index, from `Instructions::check_all_brackets()`.
+    Instruction,
+}
+
+impl fmt::Display for BracketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let error = BFError {
+            kind: self.kind.clone(),
+        };
+        let unit = match self.position_kind {
+            PositionKind::Byte => "byte",
+            PositionKind::Instruction => "instruction",
+        };
+        write!(f, "{} (at {} {})", error, unit, self.position)
+    }
+}
+
+impl error::Error for BracketError {}
+
+impl From<BracketError> for BFError {
+    fn from(error: BracketError) -> BFError {
+        BFError { kind: error.kind }
+    }
+}
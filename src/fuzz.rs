@@ -0,0 +1,120 @@
+//! Generating random BF programs for fuzzing, enabled with the `fuzz` feature.
+//!
+//! Random programs are handy for differential testing, e.g. checking that optimized and
+//! unoptimized instructions still behave the same with `Instructions::behaviorally_equal()`,
+//! and for stress testing the interpreter with code nobody would think to write by hand.
+
+use crate::{xorshift, Instructions};
+
+/// Commands a random program is made of, other than brackets.
+const COMMANDS: [char; 6] = ['+', '-', '>', '<', '.', ','];
+
+/// Generate a random program of `length` BF commands, with balanced brackets.
+///
+/// The same seed and length always give the same program, so a failing case can be
+/// reproduced from just those two numbers. Every `[` has a matching `]`, so the program never
+/// fails on its brackets, but it can still fail at runtime, e.g. by moving off the left end of
+/// the tape, and it can loop forever, so run it with a step limit like
+/// `Program::execute_limited()`.
+///
+/// # Examples
+///
+/// ```rust
+/// use rbf::fuzz;
+///
+/// let instructions = fuzz::random_program(42, 100);
+///
+/// assert_eq!(instructions.len(), 100);
+/// assert!(instructions.cfg().is_ok());
+/// assert_eq!(instructions, fuzz::random_program(42, 100));
+/// ```
+pub fn random_program(seed: u64, length: usize) -> Instructions {
+    // Xorshift gets stuck on a state of 0, so swap it for some other fixed value.
+    let mut state = if seed == 0 {
+        0x9E37_79B9_7F4A_7C15
+    } else {
+        seed
+    };
+    let mut next = || {
+        state = xorshift(state);
+        // The high bits of xorshift are the better quality ones.
+        (state >> 32) as usize
+    };
+
+    let mut source = String::with_capacity(length);
+    let mut depth = 0;
+
+    for i in 0..length {
+        let remaining = length - i;
+        // Every open loop needs room left to close it.
+        let c = if remaining == depth {
+            ']'
+        } else {
+            match next() % 10 {
+                8 if remaining > depth + 1 => '[',
+                9 if depth > 0 => ']',
+                n => COMMANDS[n % COMMANDS.len()],
+            }
+        };
+
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => (),
+        }
+        source.push(c);
+    }
+
+    Instructions::from_string(&source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+
+    #[test]
+    fn random_programs_are_balanced() {
+        for seed in 0..200 {
+            for length in [0, 1, 2, 10, 75] {
+                let instructions = random_program(seed, length);
+                assert_eq!(instructions.len(), length);
+                assert!(instructions.cfg().is_ok(), "{} {}", seed, length);
+            }
+        }
+
+        assert_ne!(random_program(1, 50), random_program(2, 50));
+    }
+
+    #[test]
+    fn optimizing_random_programs() {
+        let samples: [&[u8]; 3] = [b"", b"\x01\x02", b"hello"];
+        let runs_cleanly = |instructions: &Instructions| {
+            samples.iter().all(|sample| {
+                let mut input = sample.iter();
+                Program::new(instructions.clone())
+                    .execute_limited(
+                        100_000,
+                        || input.next().map_or('\0', |&b| b as char),
+                        |_| (),
+                    )
+                    .is_ok()
+            })
+        };
+
+        let mut tested = 0;
+        for seed in 0..500 {
+            // Optimizing can cancel out moves off the tape, so programs that error are skipped.
+            let plain = random_program(seed, 60);
+            if !runs_cleanly(&plain) {
+                continue;
+            }
+
+            let mut optimized = plain.clone();
+            optimized.optimize();
+            assert!(plain.behaviorally_equal(&optimized, &samples), "{}", seed);
+            tested += 1;
+        }
+        assert!(tested > 0);
+    }
+}
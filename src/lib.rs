@@ -15,11 +15,17 @@
 //!
 //! One neat feature is that I included an optimization for the BF interpreter. Passing the -o flag
 //! enables optimization. This works by optimizing the internal BF instructions before executing
-//! them. The only optimization currently implemented is instruction collapsing, where repeated
-//! operations are collapsed into a single operation. Additional optimizations were originally
-//! planned but I decided they wouldn't have a big enough impact to be worth implementing. With
-//! only the code collapse optimization, it still yields a whopping ~60% performance increase when
-//! tested with the examples/mandelbrot.bf example!
+//! them. The main optimization is instruction collapsing, where repeated operations are collapsed
+//! into a single operation. Loops at the start of a program, which can never run, are removed as
+//! well, and changes to cells between loops are folded together even across pointer moves, into
+//! the cell's value when it was set earlier on. Loops like `[-]`, which just clear a cell, become
+//! a single instruction, and so do loops like `[->+<]`, which move a cell onto others.
+//! Additional optimizations were originally planned but I decided they wouldn't have a big enough
+//! impact to be worth implementing. With only the code collapse optimization, it still yields a
+//! whopping ~60% performance increase when tested with the examples/mandelbrot.bf example!
+//!
+//! As a library, the optimizations can also be run one at a time, or alongside passes of your
+//! own, with the `passes` module and `Instructions::optimize_with()`.
 //!
 //! For better performance, build/run in release mode. Add `--release` before the `--` in the
 //! `cargo run` command.
@@ -50,8 +56,21 @@
 //! cargo run -- -c ',>,<.>.'
 //! ```
 //!
+//! Run a program several times and time it. The old `--repititions` spelling still works too.
+//!
+//! ```sh
+//! cargo run --release -- -p ./examples/mandelbrot.bf -b --repetitions 3
+//! ```
+//!
 //! Use the `-h` flag to see all options.
 //!
+//! ## Exit codes
+//!
+//! The CLI exits with 0 if everything went fine and 1 if it couldn't get the program to run, e.g.
+//! when the file can't be read. If the BF program itself fails, the exit code is 10 plus the
+//! error's code from `BFErrorKind::code()`, so scripts can tell what went wrong. For example, a
+//! missing `]` exits with 12 and moving the cell pointer off the tape exits with 14.
+//!
 //! # Library Usage
 //!
 //! ```rust
@@ -92,32 +111,345 @@
 //! let charin = || 'a'; // always read input as 'a'
 //! let charout = |c| output.push(c);
 //! ```
+//!
+//! ## Async
+//!
+//! With the `async` feature enabled, `Program::execute_async()` runs a program with input and
+//! output going through tokio's `AsyncRead` and `AsyncWrite`, so it can cooperate with an async
+//! runtime rather than blocking on the terminal. See `examples/async_input.rs`.
+//!
+//! ## Logging
+//!
+//! With the `log` feature enabled, `LogOutput` sends a program's output to the `log` crate one
+//! line at a time, for when BF runs inside a bigger system and its output belongs in the logs.
+//!
+//! ## Compiling
+//!
+//! BF can also be compiled rather than interpreted. `Instructions::to_llvm_ir()` turns a program
+//! into textual LLVM IR, which `clang` or `llc` can build into a native binary. Optimize the
+//! instructions first for smaller IR. From the command line, `--emit llvm` prints the IR instead
+//! of running the program, and `-o` optimizes it first.
+//!
+//! ## Testing
+//!
+//! With the `testing` feature enabled, the `testing` module has fixture programs that fail
+//! with each kind of error, for testing code that handles errors from this crate. With the
+//! `fuzz` feature enabled, `fuzz::random_program()` generates random programs with balanced
+//! brackets, for differential testing and stress testing.
 
 #![warn(missing_docs)]
 
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::io::Write;
+use std::sync::Arc;
 
 use console::Term;
 
+pub mod codegen;
 pub mod errors;
-use errors::{BFError, BFErrorKind};
+pub mod passes;
+use errors::{BFError, BFErrorKind, BracketError, PositionKind};
+
+#[cfg(feature = "async")]
+mod async_io;
+mod bf_io;
+mod binary;
+mod comments;
+mod debug_config;
+mod dot;
+#[cfg(any(test, feature = "fuzz"))]
+pub mod fuzz;
+mod llvm;
+#[cfg(feature = "log")]
+mod log_output;
+mod replay;
+mod rle;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub use bf_io::{BfInput, BfOutput, ReadInput, WriteOutput};
+pub use comments::CommentMap;
+pub use debug_config::{DebugConfig, DebugStatus};
+#[cfg(feature = "log")]
+pub use log_output::LogOutput;
+pub use replay::ExecutionLog;
 
 type Result<T> = std::result::Result<T, BFError>;
 
+/// Split source into the program and the input after its first `!`, for
+/// `Instructions::from_source_with_input()`. The shebang line is left out, since its `!`
+/// doesn't start the input.
+fn split_bang_input(src: &str) -> (&str, &str) {
+    let src = strip_shebang(src);
+    src.split_once('!').unwrap_or((src, ""))
+}
+
+/// Get BF source without its shebang line, if it starts with one.
+///
+/// BF scripts on Unix can start with a line like `#!/usr/bin/env rbf` to make them executable.
+/// Commands like `-` and `.` in that line aren't part of the program, so everything that
+/// parses source skips it. The newline ending the shebang is kept, so lines in what's left
+/// still count from the same place. Source that doesn't start with `#!` is returned as is.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(rbf::strip_shebang("#!/usr/bin/env -S rbf -o\n+."), "\n+.");
+/// assert_eq!(rbf::strip_shebang("+. #!"), "+. #!");
+/// ```
+pub fn strip_shebang(src: &str) -> &str {
+    if !src.starts_with("#!") {
+        return src;
+    }
+
+    match src.find('\n') {
+        Some(end) => &src[end..],
+        None => "",
+    }
+}
+
+/// Get the value of a field in the metadata header of BF source, if the header has it.
+///
+/// The header is the lines at the very start of the source, after any shebang line, that
+/// start with `@`. Each one is a field name, then whitespace, then its value, like `@tape 100`
+/// for a program that promises to use at most 100 cells, which
+/// `Program::check_declared_tape_budget()` can hold it to. Header lines are still parsed like
+/// the rest of the source, so BF commands don't belong in them. If a field is there more than
+/// once, the first one counts.
+///
+/// # Examples
+///
+/// ```rust
+/// let src = "#!/usr/bin/env rbf\n@name Counter\n@tape 10\n+[>+]";
+///
+/// assert_eq!(rbf::header_field(src, "name"), Some("Counter"));
+/// assert_eq!(rbf::header_field(src, "tape"), Some("10"));
+/// assert_eq!(rbf::header_field(src, "author"), None);
+/// ```
+pub fn header_field<'a>(src: &'a str, name: &str) -> Option<&'a str> {
+    let mut lines = strip_shebang(src).lines();
+    // The shebang's newline is left behind as an empty first line.
+    if shebang_len(src) > 0 {
+        lines.next();
+    }
+
+    lines
+        .map_while(|line| line.strip_prefix('@'))
+        .find_map(|field| {
+            let (field, value) = field.split_once(char::is_whitespace).unwrap_or((field, ""));
+            (field == name).then(|| value.trim())
+        })
+}
+
+/// Get how many bytes of `src` are its shebang line, if it has one.
+fn shebang_len(src: &str) -> usize {
+    src.len() - strip_shebang(src).len()
+}
+
+/// How far a stream of BF source is through a possible leading shebang line.
+#[derive(Clone, Copy)]
+enum Shebang {
+    /// At the very start of the source.
+    Start,
+    /// Just past a leading `#`.
+    Hash,
+    /// In a leading shebang line.
+    Line,
+    /// Past where a shebang line could be.
+    Past,
+}
+
+impl Shebang {
+    /// Check if `byte`, the next byte of the source, is part of a leading shebang line.
+    ///
+    /// A leading `#` is skipped before it's known to start a shebang, which is fine since `#`
+    /// isn't a command anyway.
+    fn skips(&mut self, byte: u8) -> bool {
+        let (next, skip) = match (*self, byte) {
+            (Shebang::Start, b'#') => (Shebang::Hash, true),
+            (Shebang::Hash, b'!') => (Shebang::Line, true),
+            (Shebang::Line, b'\n') => (Shebang::Past, false),
+            (Shebang::Line, _) => (Shebang::Line, true),
+            _ => (Shebang::Past, false),
+        };
+        *self = next;
+        skip
+    }
+}
+
+/// Check if BF source has balanced brackets, without parsing it into `Instructions`.
+///
+/// This is a cheap precheck that scans the source once and allocates nothing. Use
+/// `rbf::validate()` to find out where the problem is.
+///
+/// # Examples
+///
+/// ```rust
+/// assert!(rbf::is_valid("+[->+<] with a comment"));
+/// assert!(!rbf::is_valid("+[->+<"));
+/// assert!(!rbf::is_valid("]["));
+/// ```
+pub fn is_valid(src: &str) -> bool {
+    validate(src).is_ok()
+}
+
+/// Check if BF source has balanced brackets, giving the location of an unmatched bracket if not.
+///
+/// Like `rbf::is_valid()`, nothing is allocated, and a shebang line is skipped the same as
+/// when parsing. Unmatched `]` are found first, so if there
+/// are several problems, the position is that of the first unmatched `]`, or otherwise the last
+/// unmatched `[`.
+///
+/// # Errors
+///
+/// Will return a `BracketError` with kind `BFErrorKind::MissingOpen` or
+/// `BFErrorKind::MissingClose` and the byte offset of the unmatched bracket.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::errors::{BFErrorKind, BracketError, PositionKind};
+/// assert_eq!(rbf::validate("[-]"), Ok(()));
+/// assert_eq!(
+///     rbf::validate("+[[-]"),
+///     Err(BracketError {
+///         kind: BFErrorKind::MissingClose,
+///         position: 1,
+///         position_kind: PositionKind::Byte,
+///     })
+/// );
+/// ```
+pub fn validate(src: &str) -> std::result::Result<(), BracketError> {
+    let skip = shebang_len(src);
+    let chars = || src.char_indices().filter(|&(position, _)| position >= skip);
+
+    let mut depth = 0usize;
+    for (position, c) in chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => {
+                return Err(BracketError {
+                    kind: BFErrorKind::MissingOpen,
+                    position,
+                    position_kind: PositionKind::Byte,
+                })
+            }
+            ']' => depth -= 1,
+            _ => (),
+        }
+    }
+
+    if depth == 0 {
+        return Ok(());
+    }
+
+    // Scanning backwards, the first `[` that isn't closed by a `]` after it is unmatched.
+    let mut closes = 0usize;
+    for (position, c) in chars().rev() {
+        match c {
+            ']' => closes += 1,
+            '[' if closes == 0 => {
+                return Err(BracketError {
+                    kind: BFErrorKind::MissingClose,
+                    position,
+                    position_kind: PositionKind::Byte,
+                })
+            }
+            '[' => closes -= 1,
+            _ => (),
+        }
+    }
+
+    unreachable!("Unclosed brackets were already counted.")
+}
+
 /// Represents a BF instruction.
 ///
 /// The `isize` values for MvPtr and MvValue are for future optimization purposes, representing
-/// multiple of a single command.
+/// multiple of a single command. SetValue has no command of its own and only comes from
+/// optimizing loops like `[-]`. Move and Copy come from optimizing loops like `[->+<]` and
+/// `[->+>+<<]`, adding the current cell onto the cell or cells at the given offsets and then
+/// clearing it. InputLen is the extended `?` command.
 #[derive(Debug, PartialEq, Clone)]
 enum Instruct {
     MvPtr(isize),
     MvValue(isize),
+    SetValue(u8),
+    Move(isize),
+    Copy(isize, isize),
     Output,
     Input,
+    InputLen,
     OpenLoop,
     CloseLoop,
 }
 
+impl Instruct {
+    /// Get the instruction for a single BF command, or None if `c` isn't a command.
+    fn from_char(c: char) -> Option<Instruct> {
+        match c {
+            '>' => Some(Instruct::MvPtr(1)),
+            '<' => Some(Instruct::MvPtr(-1)),
+            '-' => Some(Instruct::MvValue(-1)),
+            '+' => Some(Instruct::MvValue(1)),
+            '.' => Some(Instruct::Output),
+            ',' => Some(Instruct::Input),
+            '[' => Some(Instruct::OpenLoop),
+            ']' => Some(Instruct::CloseLoop),
+            _ => None, // Anything other than valid commands is simply a comment! :)
+        }
+    }
+}
+
+/// A single instruction, as seen from outside the crate.
+///
+/// This is the public view of what the interpreter runs, for tools like editors and debuggers
+/// that want to show or analyze instructions one at a time. Get them with
+/// `Instructions::op_at()` or `Instructions::ops()`. Unoptimized code only has the ops for the
+/// eight BF commands, each with an amount of 1 or -1, and the rest come from optimizing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    /// Move the cell pointer by the amount, `>` and `<`.
+    MovePtr(isize),
+    /// Add the amount to the current cell, `+` and `-`.
+    Add(isize),
+    /// Set the current cell to the value, from loops like `[-]`.
+    Set(u8),
+    /// Add the current cell onto the cell at the offset and clear it, from loops like `[->+<]`.
+    Move(isize),
+    /// Add the current cell onto the cells at both offsets and clear it, from loops like
+    /// `[->+>+<<]`.
+    Copy(isize, isize),
+    /// Output the current cell, `.`.
+    Output,
+    /// Read input into the current cell, `,`.
+    Input,
+    /// Set the current cell to how much input is left, the extended `?`.
+    InputLen,
+    /// Start a loop, `[`.
+    LoopStart,
+    /// End a loop, `]`.
+    LoopEnd,
+}
+
+impl From<&Instruct> for Op {
+    fn from(instruction: &Instruct) -> Op {
+        match *instruction {
+            Instruct::MvPtr(n) => Op::MovePtr(n),
+            Instruct::MvValue(n) => Op::Add(n),
+            Instruct::SetValue(n) => Op::Set(n),
+            Instruct::Move(to) => Op::Move(to),
+            Instruct::Copy(a, b) => Op::Copy(a, b),
+            Instruct::Output => Op::Output,
+            Instruct::Input => Op::Input,
+            Instruct::InputLen => Op::InputLen,
+            Instruct::OpenLoop => Op::LoopStart,
+            Instruct::CloseLoop => Op::LoopEnd,
+        }
+    }
+}
+
 /// Holds each converted BF Instruct in a Vec to be interpretted.
 ///
 /// `rbf::Instructions` contains a `Vec<Instruct>`. The `rbf::Instruct` enum, which is private, is an
@@ -133,12 +465,26 @@ enum Instruct {
 /// let instructions = Instructions::from_string(",>,<.>.");
 /// let prgm = Program::new(instructions);
 /// ```
-#[derive(Debug, PartialEq, Clone)]
-pub struct Instructions(Vec<Instruct>);
+#[derive(Debug, Clone)]
+pub struct Instructions(Vec<Instruct>, Option<Vec<SourcePosition>>);
+
+/// Line and column of a command in BF source, both counting from 1.
+type SourcePosition = (usize, usize);
+
+/// Instructions are equal if they're the same instructions, whether or not they know where
+/// they came from in the source.
+impl PartialEq for Instructions {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
 
 impl Instructions {
     /// Convert a string slice of commands into an Instructions struct containing the converted instructions.
     ///
+    /// A shebang line at the start is skipped, as described on `rbf::strip_shebang()`, and so
+    /// it is by every other way of parsing source.
+    ///
     /// # Arguments
     ///
     /// * `commands` - A string slice holding the raw BrainF*** instructions.
@@ -150,553 +496,7462 @@ impl Instructions {
     /// let instructions = Instructions::from_string(",>,<.>.");
     /// ```
     pub fn from_string(commands: &str) -> Instructions {
-        Instructions(commands.chars().fold(Vec::new(), |mut acc, c| {
-            let instruction = match c {
-                '>' => Some(Instruct::MvPtr(1)),
-                '<' => Some(Instruct::MvPtr(-1)),
-                '-' => Some(Instruct::MvValue(-1)),
-                '+' => Some(Instruct::MvValue(1)),
-                '.' => Some(Instruct::Output),
-                ',' => Some(Instruct::Input),
-                '[' => Some(Instruct::OpenLoop),
-                ']' => Some(Instruct::CloseLoop),
-                _ => None, // Anything other than valid commands is simply a comment! :)
-            };
-            if let Some(i) = instruction {
-                acc.push(i);
-            }
-            acc
-        }))
+        Instructions(
+            strip_shebang(commands)
+                .chars()
+                .filter_map(Instruct::from_char)
+                .collect(),
+            None,
+        )
     }
 
-    /// Perform optimizations on the BF code.
-    pub fn optimize(&mut self) {
-        self.optimize_combine_multiples();
-    }
-
-    /// Optimize the code by combining MvValue and MvPtr instructions to greatly reduce the
-    /// overall number of instructions.
+    /// Convert a string slice of commands into Instructions, recording where each one is in
+    /// the source.
     ///
-    /// This optimization alone resulted in a 59.98% performance increase in the
-    /// `examples/mandelbrot.bf` program with blank io enabled. (from 69.82s to 27.94s).
-    pub fn optimize_combine_multiples(&mut self) {
-        let mut holding_instruction: Option<Instruct> = None;
-        let mut new_instructions = vec![];
-
-        let mut new_matches_holding;
-
-        for instruction in &self.0 {
-            new_matches_holding = false;
+    /// The instructions are the same as from `Instructions::from_string()`, but
+    /// `Instructions::source_position()` can then tell where each one came from, which is what
+    /// a debugger needs to show the source of the instruction being run. Recording positions
+    /// takes extra time and memory, so only use this when they're needed.
+    pub fn from_string_with_positions(commands: &str) -> Instructions {
+        let mut instructions = vec![];
+        let mut positions = vec![];
 
-            // Check if the current instruction is the same as the one we are holding.
-            // If they are the same, combine the values and keep holding.
-            // If they are different, the holding gets added to the instructions and we now
-            // hold the new one.
-            match instruction {
-                Instruct::MvPtr(n) => {
-                    if let Some(ref mut instruct) = holding_instruction {
-                        if let Instruct::MvPtr(m) = *instruct {
-                            *instruct = Instruct::MvPtr(m + n);
-                            new_matches_holding = true;
-                        }
-                    }
-                }
-                Instruct::MvValue(n) => {
-                    if let Some(ref mut instruct) = holding_instruction {
-                        if let Instruct::MvValue(m) = *instruct {
-                            *instruct = Instruct::MvValue(m + n);
-                            new_matches_holding = true;
-                        }
-                    }
-                }
-                _ => (),
-            }
-            if !new_matches_holding {
-                if let Some(instruct) = holding_instruction.take() {
-                    new_instructions.push(instruct);
+        for (line, text) in strip_shebang(commands).split('\n').enumerate() {
+            for (column, c) in text.chars().enumerate() {
+                if let Some(instruction) = Instruct::from_char(c) {
+                    instructions.push(instruction);
+                    positions.push((line + 1, column + 1));
                 }
-                holding_instruction = Some(instruction.clone());
             }
         }
 
-        // We can't forget to put the holding one onto the new instructions.
-        if let Some(instruct) = holding_instruction.take() {
-            new_instructions.push(instruct);
-        }
-
-        self.0 = new_instructions;
+        Instructions(instructions, Some(positions))
     }
-}
-
-/// Holds the BF program's functionality.
-///
-/// It contains methods for execution such as stepwise executing and full-program executing.
-///
-/// # Examples
-///
-/// Create Program struct directly from a string of BF code, and execute it.
-///
-/// ```rust
-/// # use rbf::*;
-/// let mut prgm = Program::from_string(",>,<.>.");
-///
-/// # let basic_input = BasicInput::new();
-/// # let mut basic_output = BasicOutput::new();
-/// # let input_closure = || basic_input.input_char();
-/// # let output_closure = |c| basic_output.print_char(c);
-/// prgm.execute(input_closure, output_closure).expect("Error in BF instructions.");
-/// ```
-#[derive(Debug, PartialEq)]
-pub struct Program {
-    /// Instructions to execute.
-    instructions: Instructions,
-    /// Pointer to where in the instructions we are currently looking.
-    instruction_ptr: usize,
 
-    /// Program's memory -- Vec of cells in the ascii range of numbers.
-    cells: Vec<u8>,
-    /// Current location in memory.
-    cell_ptr: usize,
+    /// Get the line and column in the source of the instruction at index `idx`, both counting
+    /// from 1.
+    ///
+    /// Columns count chars, not bytes. Returns None if `idx` is past the end, or if the
+    /// instructions don't know their positions, either because they weren't parsed with
+    /// `Instructions::from_string_with_positions()` or because they've since been changed by
+    /// optimizing or normalizing, which leaves instructions that don't match the source.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let instructions = Instructions::from_string_with_positions("++\n  [-]");
+    ///
+    /// assert_eq!(instructions.source_position(1), Some((1, 2)));
+    /// assert_eq!(instructions.source_position(2), Some((2, 3)));
+    /// assert_eq!(instructions.source_position(5), None);
+    /// assert_eq!(Instructions::from_string("++").source_position(0), None);
+    /// ```
+    pub fn source_position(&self, idx: usize) -> Option<(usize, usize)> {
+        self.1.as_ref()?.get(idx).copied()
+    }
 
-    /// Simple var to manage loops.
-    loop_stack: Vec<usize>,
-}
+    /// Swap in changed instructions, forgetting source positions since they no longer match.
+    fn set_instructions(&mut self, instructions: Vec<Instruct>) {
+        self.0 = instructions;
+        self.1 = None;
+    }
 
-impl Program {
-    /// Clear and reset the program state.
+    /// Convert a string slice of commands in the extended dialect into Instructions.
     ///
-    /// Clears the cells, instruction pointer, cell pointer, and loop stack. Subsequently
-    /// calling `Program::execute()` or `Program::step()` will begin the program from the
-    /// beginning.
-    pub fn reset(&mut self) {
-        self.instruction_ptr = 0;
-        self.cells.clear();
-        self.cell_ptr = 0;
-        self.loop_stack.clear();
+    /// The extended dialect is standard BF plus `?`, which sets the current cell to how many
+    /// bytes of input are left, or 255 if there are more than that. Only the program knows how
+    /// much input it has been given, so it has to be told with `Program::set_input_len()`,
+    /// which `Program::run_bytes()` does automatically. Everywhere else, `?` is a comment like
+    /// any other character.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{Instructions, Program};
+    /// // Outputs how much input there is, then echoes it.
+    /// let mut prgm = Program::new(Instructions::from_string_extended("?.[,.?]"));
+    ///
+    /// assert_eq!(prgm.run_bytes(b"hi").unwrap(), b"\x02hi");
+    /// ```
+    pub fn from_string_extended(commands: &str) -> Instructions {
+        Instructions(
+            strip_shebang(commands)
+                .chars()
+                .filter_map(|c| match c {
+                    '?' => Some(Instruct::InputLen),
+                    c => Instruct::from_char(c),
+                })
+                .collect(),
+            None,
+        )
     }
 
-    /// Create a new program struct.
+    /// Convert a string slice of commands into Instructions, also collecting any characters
+    /// that aren't commands or whitespace.
     ///
-    /// This constructor requires the instructions to already be represented by an `Instructions`
-    /// struct.
-    pub fn new(instructions: Instructions) -> Program {
-        Program {
-            instructions,
-            instruction_ptr: 0,
-            cells: vec![],
-            cell_ptr: 0,
-            loop_stack: vec![],
+    /// Those characters are comments, so the instructions are the same as from
+    /// `Instructions::from_string()`. But a linter might want to point out e.g. a stray `{` that
+    /// might have been a typo, so each one is returned as a `Diagnostic` with its location.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let (instructions, diagnostics) = Instructions::from_string_with_diagnostics("+ {-}");
+    ///
+    /// assert_eq!(instructions, Instructions::from_string("+-"));
+    /// assert_eq!(
+    ///     diagnostics,
+    ///     vec![
+    ///         Diagnostic { position: 2, character: '{' },
+    ///         Diagnostic { position: 4, character: '}' },
+    ///     ]
+    /// );
+    /// ```
+    pub fn from_string_with_diagnostics(commands: &str) -> (Instructions, Vec<Diagnostic>) {
+        let mut instructions = vec![];
+        let mut diagnostics = vec![];
+
+        let skip = shebang_len(commands);
+        for (position, c) in commands.char_indices().skip_while(|&(p, _)| p < skip) {
+            match Instruct::from_char(c) {
+                Some(instruction) => instructions.push(instruction),
+                None if c.is_whitespace() => (),
+                None => diagnostics.push(Diagnostic {
+                    position,
+                    character: c,
+                }),
+            }
         }
-    }
 
-    /// Create a new program directly from a string of BF code.
-    ///
-    /// This method is a wrapper of the Program::new() method, creating a new Instructions
-    /// struct from the instructions string first.
-    pub fn from_string(instructions: &str) -> Program {
-        Self::new(Instructions::from_string(instructions))
+        (Instructions(instructions, None), diagnostics)
     }
 
-    /// Execute the entire BF program.
+    /// Convert a string slice of commands into Instructions, unless there are more than
+    /// `max_instructions` of them.
     ///
-    /// Step-by-step interprets the entire BF program according to its Instructions.
+    /// Parsing stops as soon as the limit is passed, so adversarial input can't make this build
+    /// a huge Vec of instructions first. Only actual commands count towards the limit, not
+    /// comments. This is meant for accepting untrusted programs, alongside the runtime limits
+    /// on steps and output.
     ///
     /// # Errors
     ///
-    /// Will return an error if the instructions are invalid. These errors are runtime BF
-    /// errors.
-    pub fn execute<Fin, Fout>(&mut self, mut input: Fin, mut output: Fout) -> Result<()>
-    where
-        Fin: FnMut() -> char,
-        Fout: FnMut(char),
-    {
-        loop {
-            if self.done()? {
-                break;
+    /// Will return an error with kind `BFErrorKind::ProgramTooLarge` if the program has more
+    /// than `max_instructions` instructions.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// assert!(Instructions::from_string_limited("+++ lots of comments", 3).is_ok());
+    /// assert!(Instructions::from_string_limited("++++", 3).is_err());
+    /// ```
+    pub fn from_string_limited(commands: &str, max_instructions: usize) -> Result<Instructions> {
+        let mut instructions = vec![];
+
+        for instruction in strip_shebang(commands)
+            .chars()
+            .filter_map(Instruct::from_char)
+        {
+            if instructions.len() == max_instructions {
+                return Err(BFError {
+                    kind: BFErrorKind::ProgramTooLarge,
+                });
             }
-            self.step(|| input(), |c| output(c))?;
+            instructions.push(instruction);
         }
 
-        Ok(())
+        Ok(Instructions(instructions, None))
     }
 
-    /// Execute the next step in the BF program.
-    pub fn step<Fin, Fout>(&mut self, input: Fin, output: Fout) -> Result<()>
-    where
-        Fin: FnOnce() -> char,
-        Fout: FnOnce(char),
+    /// Parse instructions from BF source read from `reader`.
+    ///
+    /// The source is parsed as it's read, a chunk at a time, so it never has to all be in
+    /// memory at once. It's read as bytes rather than text, which parses the same as
+    /// `Instructions::from_string()` since every command is ascii, but means the source doesn't
+    /// have to be valid UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::IoError` if reading fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let source: &[u8] = b"++ add two >.";
+    /// let instructions = Instructions::from_reader(source).unwrap();
+    ///
+    /// assert_eq!(instructions, Instructions::from_string("++>."));
+    /// ```
+    pub fn from_reader<R>(reader: R) -> Result<Instructions>
+    where
+        R: std::io::Read,
     {
-        // Make sure cells length is good so any possible operations we do work.
-        self.validate_cells_length();
+        Instructions::from_reader_with_progress(reader, |_| ())
+    }
 
-        let instruction = match self.instructions.0.get(self.instruction_ptr) {
-            Some(i) => i,
-            None => {
-                return Err(BFError {
-                    kind: BFErrorKind::InstructionBoundsError,
-                })
+    /// Parse instructions from BF source read from `reader`, the same as
+    /// `Instructions::from_reader()`, calling `progress` with how many bytes have been read
+    /// so far after each chunk.
+    ///
+    /// This is for showing progress while parsing a huge program, like with a progress bar
+    /// when the size of the file is known. `progress` is called once for every chunk read, not
+    /// for every byte, so a slow callback doesn't slow parsing down much.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::IoError` if reading fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let source = "+".repeat(20000);
+    /// let mut read = 0;
+    ///
+    /// let instructions =
+    ///     Instructions::from_reader_with_progress(source.as_bytes(), |bytes| read = bytes)
+    ///         .unwrap();
+    ///
+    /// assert_eq!(instructions.len(), 20000);
+    /// assert_eq!(read, 20000);
+    /// ```
+    pub fn from_reader_with_progress<R, F>(mut reader: R, mut progress: F) -> Result<Instructions>
+    where
+        R: std::io::Read,
+        F: FnMut(usize),
+    {
+        /// Bytes read from the reader at a time.
+        const CHUNK_SIZE: usize = 8192;
+
+        let mut instructions = vec![];
+        let mut chunk = [0; CHUNK_SIZE];
+        let mut read = 0;
+        let mut shebang = Shebang::Start;
+
+        loop {
+            let len = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    return Err(BFError {
+                        kind: BFErrorKind::IoError,
+                    })
+                }
+            };
+
+            instructions.extend(
+                chunk[..len]
+                    .iter()
+                    .filter(|&&byte| !shebang.skips(byte))
+                    .filter_map(|&byte| Instruct::from_char(byte as char)),
+            );
+            read += len;
+            progress(read);
+        }
+
+        Ok(Instructions(instructions, None))
+    }
+
+    /// Create Instructions from source that has the program's input after the first `!`.
+    ///
+    /// Some BF environments, including several online judges, take the program and its input as
+    /// a single stream, with everything after the first `!` being input rather than code. This
+    /// splits the source there, giving the parsed program and the input. If there's no `!`, the
+    /// input is empty. `Instructions::from_string()` still treats `!` as a comment.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let (instructions, input) = Instructions::from_source_with_input(",.,.!hi!");
+    ///
+    /// assert_eq!(instructions, Instructions::from_string(",.,."));
+    /// assert_eq!(input, "hi!");
+    /// ```
+    pub fn from_source_with_input(src: &str) -> (Instructions, String) {
+        let (program, input) = split_bang_input(src);
+        (Instructions::from_string(program), input.to_string())
+    }
+
+    /// Create Instructions from source that has the program's input after the first `!`, with
+    /// the extended commands from `Instructions::from_string_extended()`.
+    ///
+    /// The source is split the same way as in `Instructions::from_source_with_input()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let (instructions, input) = Instructions::from_source_with_input_extended("?.!hi");
+    ///
+    /// assert_eq!(instructions, Instructions::from_string_extended("?."));
+    /// assert_eq!(input, "hi");
+    /// ```
+    pub fn from_source_with_input_extended(src: &str) -> (Instructions, String) {
+        let (program, input) = split_bang_input(src);
+        (
+            Instructions::from_string_extended(program),
+            input.to_string(),
+        )
+    }
+
+    /// Parse source holding several programs, each separated from the next by a line of just
+    /// `separator`, into the instructions of each program.
+    ///
+    /// This is for keeping a lot of small programs, like a test corpus, in a single file. A
+    /// separator line can have whitespace around `separator`, but nothing else. Each program is
+    /// parsed on its own with `Instructions::from_string()`, so a loop can't carry on past a
+    /// separator, and n separators always give n + 1 programs, even if some are empty.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`
+    /// if the brackets of any program don't match.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let programs = Instructions::parse_many("+[-]\n===\n,.\n", "===").unwrap();
+    ///
+    /// assert_eq!(programs.len(), 2);
+    /// assert_eq!(programs[1], Instructions::from_string(",."));
+    /// ```
+    pub fn parse_many(src: &str, separator: &str) -> Result<Vec<Instructions>> {
+        let mut programs = vec![];
+        // Where the program being read starts, and where the line being looked at starts.
+        let mut start = 0;
+        let mut line_start = 0;
+
+        for line in src.split_inclusive('\n') {
+            if line.trim() == separator {
+                programs.push(Instructions::from_string(&src[start..line_start]));
+                start = line_start + line.len();
             }
+            line_start += line.len();
+        }
+        programs.push(Instructions::from_string(&src[start..]));
+
+        for program in &programs {
+            program.cfg()?;
+        }
+
+        Ok(programs)
+    }
+
+    /// Get the number of instructions.
+    ///
+    /// Comments aren't instructions, and after optimizing, a collapsed run of commands counts as
+    /// a single instruction.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let mut instructions = Instructions::from_string("+++ add three >");
+    /// assert_eq!(instructions.len(), 4);
+    ///
+    /// instructions.optimize();
+    /// assert_eq!(instructions.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if there are no instructions at all.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the instruction at index `idx` as an `Op`, or None if `idx` is past the end.
+    ///
+    /// Indices are the same as everywhere else instructions are counted, like
+    /// `Instructions::matching_bracket()` and coverage, so this is handy for looking at the
+    /// instruction a program is stopped on.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{Instructions, Op};
+    /// let mut instructions = Instructions::from_string("+++[->+<]");
+    /// instructions.optimize();
+    ///
+    /// assert_eq!(instructions.op_at(0), Some(Op::Add(3)));
+    /// assert_eq!(instructions.op_at(1), Some(Op::Move(1)));
+    /// assert_eq!(instructions.op_at(2), None);
+    /// ```
+    pub fn op_at(&self, idx: usize) -> Option<Op> {
+        self.0.get(idx).map(Op::from)
+    }
+
+    /// Get every instruction, in order, as an `Op`.
+    pub fn ops(&self) -> impl Iterator<Item = Op> + '_ {
+        self.0.iter().map(Op::from)
+    }
+
+    /// Get a copy of just the instructions in `range`, for looking at part of a program on its
+    /// own, like a single loop.
+    ///
+    /// Source positions are kept, so the slice still points back at where its instructions
+    /// came from.
+    ///
+    /// Returns None if the range goes past the end, or if the brackets in it don't match,
+    /// since that wouldn't be a program that can be run.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let instructions = Instructions::from_string("++[->+<]>.");
+    ///
+    /// assert_eq!(instructions.slice(2..8).unwrap().to_string(), "[->+<]");
+    /// assert_eq!(instructions.slice(2..7), None);
+    /// ```
+    pub fn slice(&self, range: std::ops::Range<usize>) -> Option<Instructions> {
+        let instructions = self.0.get(range.clone())?.to_vec();
+        let positions = match &self.1 {
+            Some(positions) => Some(positions.get(range)?.to_vec()),
+            None => None,
         };
 
-        // println!( // Dirty debugging
-        //     "i: {:?}; iptr: {}; cptr: {}; cv: {};",
-        //     instruction, self.instruction_ptr, self.cell_ptr, self.cells[self.cell_ptr]
-        // );
+        let slice = Instructions(instructions, positions);
+        slice.cfg().ok()?;
+        Some(slice)
+    }
 
-        match *instruction {
-            Instruct::MvPtr(n) => self.move_cell_pointer(&n)?,
-            Instruct::MvValue(n) => self.move_cell_value(&n),
-            Instruct::Input => self.input_cell(input)?,
-            Instruct::Output => self.output_cell(output),
-            Instruct::OpenLoop => self.open_loop()?,
-            Instruct::CloseLoop => self.close_loop()?,
+    /// Check if the instructions contain any `,`, which is whether running them could ever wait
+    /// for input.
+    ///
+    /// This doesn't look at whether the `,` is ever reached, just whether it's there, so a
+    /// program that's false here can safely be run without any input set up at all.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// assert!(Instructions::from_string("+[,.]").uses_input());
+    /// assert!(!Instructions::from_string("+. no commas here").uses_input());
+    /// ```
+    pub fn uses_input(&self) -> bool {
+        self.0.iter().any(|i| matches!(i, Instruct::Input))
+    }
+
+    /// Check if the instructions contain any `.`, the same way as `Instructions::uses_input()`.
+    pub fn uses_output(&self) -> bool {
+        self.0.iter().any(|i| matches!(i, Instruct::Output))
+    }
+
+    /// Get exactly how many bytes the program outputs, if that can be known statically.
+    ///
+    /// That's the case when no loop contains a `.`, since then every `.` runs exactly once.
+    /// If a `.` is inside a loop, how many times it runs depends on the data, so this
+    /// conservatively returns None, as it also does if the brackets don't match. The count
+    /// assumes the program runs to completion without errors.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let instructions = Instructions::from_string("++++++++[>++++++++<-]>+.+.");
+    /// assert_eq!(instructions.static_output_len(), Some(2));
+    ///
+    /// let instructions = Instructions::from_string(",[.,]");
+    /// assert_eq!(instructions.static_output_len(), None);
+    /// ```
+    pub fn static_output_len(&self) -> Option<usize> {
+        let mut depth = 0usize;
+        let mut outputs = 0;
+
+        for instruction in &self.0 {
+            match instruction {
+                Instruct::OpenLoop => depth += 1,
+                Instruct::CloseLoop => depth = depth.checked_sub(1)?,
+                Instruct::Output if depth > 0 => return None,
+                Instruct::Output => outputs += 1,
+                _ => (),
+            }
         }
 
-        self.instruction_ptr += 1;
+        if depth == 0 {
+            Some(outputs)
+        } else {
+            None
+        }
+    }
 
-        Ok(())
+    /// Get the total net pointer movement of the instructions, if it can be known statically.
+    ///
+    /// A loop only has a known effect on the pointer if its body is balanced (moves the pointer
+    /// a net of zero), since otherwise the movement depends on how many times the loop runs. If
+    /// any loop is unbalanced, or the brackets don't match, this returns None.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// assert_eq!(Instructions::from_string(">>[->+<]<").net_pointer_shift(), Some(1));
+    /// assert_eq!(Instructions::from_string(">>[<]").net_pointer_shift(), None);
+    /// ```
+    pub fn net_pointer_shift(&self) -> Option<isize> {
+        // Net shift of the top level, then of each loop we're currently inside.
+        let mut shifts = vec![0];
+
+        for instruction in &self.0 {
+            match instruction {
+                Instruct::MvPtr(n) => *shifts.last_mut()? += n,
+                Instruct::OpenLoop => shifts.push(0),
+                Instruct::CloseLoop => {
+                    let body_shift = shifts.pop()?;
+                    if body_shift != 0 || shifts.is_empty() {
+                        return None;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        match shifts[..] {
+            [shift] => Some(shift),
+            _ => None,
+        }
     }
 
-    /// Check if the program has finished executing.
-    pub fn done(&self) -> Result<bool> {
-        if self.instruction_ptr >= self.instructions.0.len() {
-            if self.loop_stack.len() > 0 {
-                Err(BFError {
-                    kind: BFErrorKind::MissingClose,
-                })
-            } else {
-                Ok(true)
+    /// Get the cyclomatic complexity of the instructions, as a rough measure of how branchy the
+    /// program is.
+    ///
+    /// Each loop is one decision point, whether to run the body or skip past it, so the
+    /// complexity is the number of loops plus 1. A program without loops has a complexity of 1.
+    /// Nesting doesn't add anything on top of that, since a nested loop is still only one more
+    /// decision. Only the loops as they are now count, so optimizing loops like `[-]` away into
+    /// single instructions lowers the complexity.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// assert_eq!(Instructions::from_string("+++.").complexity(), 1);
+    /// assert_eq!(Instructions::from_string("++[>+[-]<-][-]").complexity(), 4);
+    /// ```
+    pub fn complexity(&self) -> usize {
+        let loops = self
+            .0
+            .iter()
+            .filter(|&instruction| *instruction == Instruct::OpenLoop)
+            .count();
+        loops + 1
+    }
+
+    /// Get a short human readable summary of the instructions.
+    ///
+    /// The summary is one `name: value` line for each of the instruction count, the number of
+    /// loops, how deeply they nest, whether there's any input or output, the output length
+    /// and net pointer shift from `Instructions::static_output_len()` and
+    /// `Instructions::net_pointer_shift()`, which are `unknown` when they can't be known
+    /// statically, and the complexity from `Instructions::complexity()`. The names and their
+    /// order won't change, so the summary can be parsed by splitting each line on the first
+    /// `: `.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let summary = Instructions::from_string("++[>+[-]<-]>.").describe();
+    ///
+    /// assert_eq!(
+    ///     summary,
+    ///     "instructions: 13\n\
+    ///      loops: 2\n\
+    ///      max nesting: 2\n\
+    ///      uses input: no\n\
+    ///      uses output: yes\n\
+    ///      output length: 1\n\
+    ///      pointer shift: 1\n\
+    ///      complexity: 3\n"
+    /// );
+    /// ```
+    pub fn describe(&self) -> String {
+        let mut loops = 0;
+        let mut depth = 0usize;
+        let mut max_depth = 0;
+
+        for instruction in &self.0 {
+            match instruction {
+                Instruct::OpenLoop => {
+                    loops += 1;
+                    depth += 1;
+                    max_depth = max_depth.max(depth);
+                }
+                Instruct::CloseLoop => depth = depth.saturating_sub(1),
+                _ => (),
             }
-        } else {
-            Ok(false)
         }
+
+        let yes_no = |b| if b { "yes" } else { "no" };
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+
+        format!(
+            "instructions: {}\n\
+             loops: {}\n\
+             max nesting: {}\n\
+             uses input: {}\n\
+             uses output: {}\n\
+             output length: {}\n\
+             pointer shift: {}\n\
+             complexity: {}\n",
+            self.len(),
+            loops,
+            max_depth,
+            yes_no(self.uses_input()),
+            yes_no(self.uses_output()),
+            or_unknown(self.static_output_len().map(|len| len.to_string())),
+            or_unknown(self.net_pointer_shift().map(|shift| shift.to_string())),
+            self.complexity(),
+        )
     }
 
-    /// Move the cell pointer either right or left. BF instructions ">" and "<" respectively.
+    /// Get the nested structure of the loops in the instructions.
     ///
-    /// Note that it takes an amount. If there are repeating ">" or "<" instructions, rather
-    /// than move multiple times in a row, it can be optimized and moved only once, x spaces.
-    fn move_cell_pointer(&mut self, amount: &isize) -> Result<()> {
-        self.cell_ptr = match self.cell_ptr.checked_add_signed(*amount) {
-            Some(val) => val,
-            None => {
-                return Err(BFError {
-                    kind: BFErrorKind::CellBoundsError,
-                })
+    /// Each loop in the returned tree knows the indices of its brackets and the loops nested
+    /// directly inside of it, which is handy for things like rendering indented source.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`
+    /// if the brackets don't match.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let tree = Instructions::from_string("[[]][]").cfg().unwrap();
+    ///
+    /// assert_eq!(tree.roots.len(), 2);
+    /// assert_eq!(tree.roots[0].children[0].open, 1);
+    /// ```
+    pub fn cfg(&self) -> Result<LoopTree> {
+        // Each loop we're in, along with the loops already found inside it.
+        let mut open_loops: Vec<(usize, Vec<LoopNode>)> = vec![];
+        let mut roots = vec![];
+
+        for (i, instruction) in self.0.iter().enumerate() {
+            match instruction {
+                Instruct::OpenLoop => open_loops.push((i, vec![])),
+                Instruct::CloseLoop => {
+                    let (open, children) = open_loops.pop().ok_or(BFError {
+                        kind: BFErrorKind::MissingOpen,
+                    })?;
+                    let node = LoopNode {
+                        open,
+                        close: i,
+                        children,
+                    };
+
+                    match open_loops.last_mut() {
+                        Some((_, siblings)) => siblings.push(node),
+                        None => roots.push(node),
+                    }
+                }
+                _ => (),
             }
-        };
+        }
+
+        if open_loops.is_empty() {
+            Ok(LoopTree { roots })
+        } else {
+            Err(BFError {
+                kind: BFErrorKind::MissingClose,
+            })
+        }
+    }
+
+    /// Split the instructions into basic blocks, the straight-line runs of code between loop
+    /// boundaries.
+    ///
+    /// Each bracket ends a block, so a program with n brackets has n + 1 blocks, in program
+    /// order. A block's range covers the instructions between two brackets, or between a
+    /// bracket and the start or end of the program, and doesn't include the brackets
+    /// themselves. Blocks can be empty, like the body of `[]`.
+    ///
+    /// The bracket right after a block is a branch on the current cell, so every block but the
+    /// last has two successors: first the block run when the cell isn't 0, then the one run
+    /// when it is. At a `[`, a nonzero cell goes into the loop body and a zero cell skips past
+    /// the matching `]`. At a `]`, a nonzero cell goes back to the start of the body and a zero
+    /// cell leaves the loop. The first block is where the program starts and the last, which
+    /// has no successors, is where it ends.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`
+    /// if the brackets don't match.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let blocks = Instructions::from_string("++[>+<-]>.").basic_blocks().unwrap();
+    ///
+    /// assert_eq!(blocks.len(), 3);
+    /// assert_eq!(blocks[1].range, 3..7);
+    /// assert_eq!(blocks[1].successors, vec![1, 2]);
+    /// assert_eq!(blocks[1].predecessors, vec![0, 1]);
+    /// ```
+    pub fn basic_blocks(&self) -> Result<Vec<BasicBlock>> {
+        // Makes sure every bracket has a partner to branch to.
+        self.cfg()?;
+
+        let brackets: Vec<usize> = self
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| matches!(i, Instruct::OpenLoop | Instruct::CloseLoop))
+            .map(|(idx, _)| idx)
+            .collect();
+        let table = self.bracket_table();
+        // Block n comes right after the nth bracket, with block 0 at the start.
+        let block_after = |idx: usize| brackets.partition_point(|&b| b <= idx);
+
+        let mut blocks = vec![];
+        let mut start = 0;
+        for end in brackets.iter().copied().chain([self.0.len()]) {
+            blocks.push(BasicBlock {
+                range: start..end,
+                predecessors: vec![],
+                successors: vec![],
+            });
+            start = end + 1;
+        }
+
+        for (block, &bracket) in brackets.iter().enumerate() {
+            // Already checked by cfg() that every bracket has a partner.
+            let partner = table[bracket].expect("Brackets were already matched.");
+            let (nonzero, zero) = match self.0[bracket] {
+                Instruct::OpenLoop => (block + 1, block_after(partner)),
+                _ => (block_after(partner), block + 1),
+            };
+            blocks[block].successors = vec![nonzero, zero];
+        }
+
+        for block in 0..blocks.len() {
+            for successor in blocks[block].successors.clone() {
+                blocks[successor].predecessors.push(block);
+            }
+        }
+        for block in &mut blocks {
+            block.predecessors.sort_unstable();
+        }
+
+        Ok(blocks)
+    }
+
+    /// Find the partner of the bracket at instruction index `idx`.
+    ///
+    /// Works in both directions, so giving the index of a `[` returns the index of its `]` and
+    /// the other way around. Returns None if `idx` isn't a bracket or the bracket is unmatched.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let instructions = Instructions::from_string("+[-[>]]");
+    ///
+    /// assert_eq!(instructions.matching_bracket(1), Some(6));
+    /// assert_eq!(instructions.matching_bracket(5), Some(3));
+    /// assert_eq!(instructions.matching_bracket(0), None);
+    /// ```
+    pub fn matching_bracket(&self, idx: usize) -> Option<usize> {
+        self.bracket_table().get(idx).copied().flatten()
+    }
+
+    /// Find every unmatched bracket, rather than stopping at the first like `rbf::validate()`
+    /// and `Instructions::cfg()` do.
+    ///
+    /// Each error's position is the instruction index of the unmatched bracket, and they're in
+    /// order of position. Brackets are matched up the same way as when running the program,
+    /// so a `]` is unmatched if every `[` before it is already closed, and a `[` is unmatched
+    /// if it's still open at the end. Nothing is returned if the brackets are balanced.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::errors::BFErrorKind;
+    /// # use rbf::Instructions;
+    /// let instructions = Instructions::from_string("][-]][");
+    /// let errors = instructions.check_all_brackets();
+    ///
+    /// assert_eq!(
+    ///     errors.iter().map(|e| (e.kind.clone(), e.position)).collect::<Vec<_>>(),
+    ///     vec![
+    ///         (BFErrorKind::MissingOpen, 0),
+    ///         (BFErrorKind::MissingOpen, 4),
+    ///         (BFErrorKind::MissingClose, 5),
+    ///     ]
+    /// );
+    /// assert!(errors[0].to_string().ends_with("(at instruction 0)"));
+    /// ```
+    pub fn check_all_brackets(&self) -> Vec<BracketError> {
+        let mut errors = vec![];
+        let mut open_loops = vec![];
+
+        for (position, instruction) in self.0.iter().enumerate() {
+            match instruction {
+                Instruct::OpenLoop => open_loops.push(position),
+                Instruct::CloseLoop if open_loops.pop().is_none() => errors.push(BracketError {
+                    kind: BFErrorKind::MissingOpen,
+                    position,
+                    position_kind: PositionKind::Instruction,
+                }),
+                _ => (),
+            }
+        }
+
+        // Every `[` left open comes after every unmatched `]`, so the errors stay in order.
+        errors.extend(open_loops.into_iter().map(|position| BracketError {
+            kind: BFErrorKind::MissingClose,
+            position,
+            position_kind: PositionKind::Instruction,
+        }));
+        errors
+    }
+
+    /// Check if two sets of instructions behave the same on every one of the sample inputs.
+    ///
+    /// Each program is run on each sample, with reads past the end of the sample giving 0, and
+    /// they're equal if they always produce the same output and end with the same cells. If
+    /// one errors, the other has to error the same way after the same output. This is a handy
+    /// way to check that optimized code, or a hand-optimized version of a program, still does
+    /// the same thing.
+    ///
+    /// It's only best-effort. Two programs that agree on every sample can still differ on some
+    /// other input, so more and more varied samples give a better answer. Trailing zero cells
+    /// aren't compared, since optimized code can skip cells without ever touching them. A
+    /// sample is skipped if either program hasn't finished on it within a million steps, so
+    /// programs that never finish on any sample are always equal.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let plain = Instructions::from_string(",[->++<]>.");
+    /// let mut optimized = plain.clone();
+    /// optimized.optimize();
+    /// let samples: [&[u8]; 3] = [b"", b"\x01", b"abc"];
+    ///
+    /// assert!(plain.behaviorally_equal(&optimized, &samples));
+    /// assert!(!plain.behaviorally_equal(&Instructions::from_string(",[->+<]>."), &samples));
+    /// ```
+    pub fn behaviorally_equal(&self, other: &Instructions, samples: &[&[u8]]) -> bool {
+        /// Steps each program gets on each sample before it's given up on.
+        const MAX_STEPS: usize = 1_000_000;
+
+        let (ours, theirs) = (Arc::new(self.clone()), Arc::new(other.clone()));
+
+        samples.iter().all(|sample| {
+            let (our_result, our_run) = sample_run(&ours, sample, MAX_STEPS);
+            let (their_result, their_run) = sample_run(&theirs, sample, MAX_STEPS);
+
+            let step_limit = Err(BFErrorKind::StepLimitExceeded);
+            our_result == step_limit
+                || their_result == step_limit
+                || (our_result == their_result && our_run == their_run)
+        })
+    }
+
+    /// Build a table of the partner of each bracket, indexed by instruction.
+    ///
+    /// Entries are None for anything that isn't a bracket, and for unmatched brackets.
+    fn bracket_table(&self) -> Vec<Option<usize>> {
+        let mut table = vec![None; self.0.len()];
+        let mut open_loops = vec![];
+
+        for (i, instruction) in self.0.iter().enumerate() {
+            match instruction {
+                Instruct::OpenLoop => open_loops.push(i),
+                Instruct::CloseLoop => {
+                    if let Some(open) = open_loops.pop() {
+                        table[open] = Some(i);
+                        table[i] = Some(open);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        table
+    }
+
+    /// Perform optimizations on the BF code.
+    ///
+    /// This runs the standard passes from `passes::STANDARD`, the same as
+    /// `Instructions::optimize_with()`, but repeats them until they stop changing anything. One
+    /// pass can open up more work for another, e.g. `[[-]]` only becomes a single set once the
+    /// inner loop has been, so repeating makes sure everything is fully simplified. The passes
+    /// run at most `MAX_OPTIMIZE_ROUNDS` times.
+    ///
+    /// Optimized code assumes every pointer move can be undone by moving back, so it's only
+    /// the same program for some `PointerUnderflow` policies. See there for which.
+    pub fn optimize(&mut self) {
+        /// Every round should make the code smaller, so this is only a guard against passes
+        /// undoing each other forever.
+        const MAX_OPTIMIZE_ROUNDS: usize = 16;
+
+        self.checked_optimize(|instructions| {
+            for _ in 0..MAX_OPTIMIZE_ROUNDS {
+                let before = instructions.clone();
+                for pass in passes::STANDARD {
+                    pass.run(instructions);
+                }
+                if *instructions == before {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Run the given optimization passes over the instructions, in order.
+    ///
+    /// In debug builds with the `log` feature enabled, the optimized code is checked against the
+    /// original as a safety net for bugs in the passes. Both are run with blank input for a
+    /// limited number of steps, and a warning is logged if they finish with different output or
+    /// cells. Other builds skip the check, so they don't pay for the extra runs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{passes, Instructions};
+    /// let mut instructions = Instructions::from_string("[-]+++>>");
+    /// instructions.optimize_with(&[&passes::CombineMultiples]);
+    ///
+    /// // The leading loop is only removed by passes::RemoveLeadingLoops.
+    /// assert_eq!(instructions.to_string(), "[-]+++>>");
+    /// ```
+    pub fn optimize_with(&mut self, passes: &[&dyn passes::OptimizationPass]) {
+        self.checked_optimize(|instructions| {
+            for pass in passes {
+                pass.run(instructions);
+            }
+        });
+    }
+
+    /// Run `optimize` on the instructions, checking the result in debug builds as described on
+    /// `Instructions::optimize_with()`.
+    fn checked_optimize<F>(&mut self, optimize: F)
+    where
+        F: FnOnce(&mut Instructions),
+    {
+        #[cfg(any(test, all(debug_assertions, feature = "log")))]
+        let original = self.clone();
+
+        optimize(self);
+
+        // The crate's own tests treat a buggy pass as a failure rather than a warning.
+        #[cfg(test)]
+        assert!(
+            !optimization_diverges(&original, self),
+            "optimizing {} changed what it does",
+            original
+        );
+        #[cfg(all(not(test), debug_assertions, feature = "log"))]
+        if optimization_diverges(&original, self) {
+            log::warn!("optimizing the BF code changed what it does");
+        }
+    }
+
+    /// Remove operations that do nothing, without otherwise changing the code.
+    ///
+    /// A `+` next to a `-` and a `>` next to a `<` cancel out, and so does anything that
+    /// cancels once those are gone, like `++--`. An empty loop, `[]`, is removed when the cell
+    /// is sure to be 0 when it's reached, which is at the start of the program and right after
+    /// another loop or a clear. Unlike `Instructions::optimize()`, nothing is combined or
+    /// replaced, so normalized code is still the same BF, just tidier. That makes it handy for
+    /// putting programs into a canonical form before comparing or hashing them.
+    ///
+    /// Cancelling `<>` and `><` assumes moving the pointer and straight back always leaves it
+    /// where it was, which isn't true for every `Program` config. Under
+    /// `PointerUnderflow::Clamp`, `<>` on cell 0 ends up on cell 1. Under
+    /// `PointerUnderflow::Error` or a strict origin, `<>` on the first cell is an error, and so
+    /// is `><` on the last cell of a tape with a fixed length. Normalized code only runs the same
+    /// when the pointer never gets to those places, the same as with
+    /// `Instructions::optimize()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let mut instructions = Instructions::from_string("+>+-<-+[-]><[]+");
+    /// instructions.normalize();
+    ///
+    /// assert_eq!(instructions.to_string(), "+[-]+");
+    /// ```
+    pub fn normalize(&mut self) {
+        let mut new_instructions: Vec<Instruct> = vec![];
+
+        for instruction in &self.0 {
+            match (instruction, &new_instructions[..]) {
+                (Instruct::MvValue(n), [.., Instruct::MvValue(m)])
+                | (Instruct::MvPtr(n), [.., Instruct::MvPtr(m)])
+                    if n + m == 0 =>
+                {
+                    new_instructions.pop();
+                }
+                (
+                    Instruct::CloseLoop,
+                    [Instruct::OpenLoop]
+                    | [.., Instruct::CloseLoop | Instruct::SetValue(0), Instruct::OpenLoop],
+                ) => {
+                    new_instructions.pop();
+                }
+                _ => new_instructions.push(instruction.clone()),
+            }
+        }
+
+        self.set_instructions(new_instructions);
+    }
+
+    /// Optimize the code by removing loops at the start of the program that can never run.
+    ///
+    /// Every cell starts at 0, so until something changes a cell value, any loop that's reached
+    /// is skipped. That covers loops at the very start of the program, loops after only pointer
+    /// moves, and loops right after one of those, since a loop always ends on a 0 cell. This
+    /// assumes the program starts on a zeroed tape, which is always true for a `Program`.
+    pub fn optimize_remove_leading_loops(&mut self) {
+        let table = self.bracket_table();
+        let mut new_instructions = vec![];
+
+        let mut i = 0;
+        while i < self.0.len() {
+            match (&self.0[i], table[i]) {
+                (Instruct::MvPtr(_), _) => new_instructions.push(self.0[i].clone()),
+                // Unmatched brackets are left alone so they still error when run.
+                (Instruct::OpenLoop, Some(close)) => i = close,
+                _ => break,
+            }
+            i += 1;
+        }
+        new_instructions.extend_from_slice(&self.0[i..]);
+
+        self.set_instructions(new_instructions);
+    }
+
+    /// Optimize the code by combining MvValue and MvPtr instructions to greatly reduce the
+    /// overall number of instructions.
+    ///
+    /// This optimization alone resulted in a 59.98% performance increase in the
+    /// `examples/mandelbrot.bf` program with blank io enabled. (from 69.82s to 27.94s).
+    pub fn optimize_combine_multiples(&mut self) {
+        let mut holding_instruction: Option<Instruct> = None;
+        let mut new_instructions = vec![];
+
+        let mut new_matches_holding;
+
+        for instruction in &self.0 {
+            new_matches_holding = false;
+
+            // Check if the current instruction is the same as the one we are holding.
+            // If they are the same, combine the values and keep holding.
+            // If they are different, the holding gets added to the instructions and we now
+            // hold the new one.
+            match instruction {
+                Instruct::MvPtr(n) => {
+                    if let Some(ref mut instruct) = holding_instruction {
+                        if let Instruct::MvPtr(m) = *instruct {
+                            *instruct = Instruct::MvPtr(m + n);
+                            new_matches_holding = true;
+                        }
+                    }
+                }
+                Instruct::MvValue(n) => {
+                    if let Some(ref mut instruct) = holding_instruction {
+                        if let Instruct::MvValue(m) = *instruct {
+                            *instruct = Instruct::MvValue(m + n);
+                            new_matches_holding = true;
+                        }
+                    }
+                }
+                _ => (),
+            }
+            if !new_matches_holding {
+                if let Some(instruct) = holding_instruction.take() {
+                    new_instructions.push(instruct);
+                }
+                holding_instruction = Some(instruction.clone());
+            }
+        }
+
+        // We can't forget to put the holding one onto the new instructions.
+        if let Some(instruct) = holding_instruction.take() {
+            new_instructions.push(instruct);
+        }
+
+        self.set_instructions(new_instructions);
+    }
+
+    /// Optimize the code by working out the net change to each cell in straight-line code.
+    ///
+    /// A segment is the code between two loop brackets, moves or copies, or between one of
+    /// those and the start or end of the program. Within a segment, every `+` and `-` is folded
+    /// into a single change per cell, even when other cells are changed in between, e.g.
+    /// `+>+<+` becomes `++>+<`. Changes that cancel out are removed entirely. Pointer moves only
+    /// happen as needed to reach the cells that change, and the pointer still ends up in the
+    /// same place at the end of the segment. Code with unmatched brackets is left as it is.
+    ///
+    /// I/O stays in order. A cell's pending change is made right before it's output, and a
+    /// pending change to a cell that's about to be overwritten by input or a set is dropped.
+    /// A change right after a set goes into the set instead.
+    pub fn optimize_fold_constants(&mut self) {
+        self.fold_blocks(false);
+    }
+
+    /// Optimize the code by tracking the value of each cell through straight-line code.
+    ///
+    /// This folds changes the same way as `Instructions::optimize_fold_constants()`, but also
+    /// keeps track of cells once they're set, so later changes go into the set even after
+    /// moving away to other cells and coming back, e.g. `[-]>[-]<+` sets the first cell to 1 and
+    /// the second to 0. A set that's overwritten by a later set is removed, and a cell's value
+    /// is written right before it's output.
+    ///
+    /// Cells are told apart by their offset from where the block starts, which only works if
+    /// the pointer never goes left of there, since what happens past the start of the tape
+    /// depends on the `PointerUnderflow` policy. Blocks that do go left are only folded.
+    pub fn optimize_propagate_values(&mut self) {
+        self.fold_blocks(true);
+    }
+
+    /// Fold each basic block for `Instructions::optimize_fold_constants()`, also tracking set
+    /// values for `Instructions::optimize_propagate_values()` if `track_values` is true.
+    fn fold_blocks(&mut self, track_values: bool) {
+        let Ok(blocks) = self.basic_blocks() else {
+            return;
+        };
+        let mut new_instructions = vec![];
+
+        for block in &blocks {
+            let code = &self.0[block.range.clone()];
+            let track_values = track_values && stays_right(code);
+            let mut segment = FoldedSegment::default();
+
+            for instruction in code {
+                match *instruction {
+                    Instruct::MvPtr(n) => segment.offset += n,
+                    Instruct::MvValue(n) => match new_instructions.last_mut() {
+                        // The cell was just set, so the change can go straight into the set.
+                        Some(Instruct::SetValue(value))
+                            if segment.offset == segment.emitted_offset =>
+                        {
+                            *value = value.wrapping_add_signed(n as i8);
+                        }
+                        _ => segment.change(n),
+                    },
+                    Instruct::SetValue(value) if track_values => segment.set(value),
+                    Instruct::Output => {
+                        segment.write(&mut new_instructions, segment.offset);
+                        segment.move_to(&mut new_instructions, segment.offset);
+                        new_instructions.push(Instruct::Output);
+                    }
+                    // These overwrite the cell, so anything still pending on it is pointless.
+                    Instruct::Input | Instruct::InputLen | Instruct::SetValue(_) => {
+                        segment.forget(segment.offset);
+                        segment.move_to(&mut new_instructions, segment.offset);
+                        new_instructions.push(instruction.clone());
+                    }
+                    // Moves reach cells by value, so they end the segment just like loops do,
+                    // though loops only ever come between blocks.
+                    Instruct::Move(_)
+                    | Instruct::Copy(..)
+                    | Instruct::OpenLoop
+                    | Instruct::CloseLoop => {
+                        segment.finish(&mut new_instructions);
+                        new_instructions.push(instruction.clone());
+                    }
+                }
+            }
+            segment.finish(&mut new_instructions);
+
+            // Every block but the last is followed by a bracket.
+            if let Some(bracket) = self.0.get(block.range.end) {
+                new_instructions.push(bracket.clone());
+            }
+        }
+
+        self.set_instructions(new_instructions);
+    }
+
+    /// Optimize the code by replacing loops that just count the current cell down to 0, like
+    /// `[-]` and `[+]`, with a single instruction that clears the cell.
+    ///
+    /// Any odd change works, since adding an odd amount over and over reaches 0 no matter what
+    /// the cell started at. A loop around nothing but a clear, like `[[-]]`, is a clear too.
+    pub fn optimize_clear_loops(&mut self) {
+        let mut new_instructions: Vec<Instruct> = vec![];
+
+        for instruction in &self.0 {
+            if let (Instruct::CloseLoop, [.., Instruct::OpenLoop, body]) =
+                (instruction, &new_instructions[..])
+            {
+                let clears = match *body {
+                    Instruct::MvValue(n) => n % 2 != 0,
+                    Instruct::SetValue(n) => n == 0,
+                    _ => false,
+                };
+                if clears {
+                    new_instructions.truncate(new_instructions.len() - 2);
+                    new_instructions.push(Instruct::SetValue(0));
+                    continue;
+                }
+            }
+            new_instructions.push(instruction.clone());
+        }
+
+        self.set_instructions(new_instructions);
+    }
+
+    /// Optimize the code by replacing loops that move the current cell onto other cells with a
+    /// single instruction that does it all at once.
+    ///
+    /// A move loop takes 1 off the current cell and adds 1 to another cell each time around,
+    /// like `[->+<]` or `[<<+>>-]`, so when it's done the other cell has had the current cell
+    /// added to it and the current cell is 0. A loop adding to two cells, like `[->+>+<<]`, is
+    /// how BF copies a cell, moving it into the copy and a temporary cell that's then moved
+    /// back, and it's replaced the same way. Loops that add more than 1 at a time aren't
+    /// replaced.
+    pub fn optimize_move_loops(&mut self) {
+        let mut new_instructions: Vec<Instruct> = vec![];
+        // Where each loop we're currently inside starts in the new instructions.
+        let mut open_loops = vec![];
+
+        for instruction in &self.0 {
+            match instruction {
+                Instruct::OpenLoop => open_loops.push(new_instructions.len()),
+                Instruct::CloseLoop => {
+                    let open = open_loops.pop();
+                    let moved = open.and_then(|open| move_loop(&new_instructions[open + 1..]));
+                    if let (Some(open), Some(moved)) = (open, moved) {
+                        new_instructions.truncate(open);
+                        new_instructions.push(moved);
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+            new_instructions.push(instruction.clone());
+        }
+
+        self.set_instructions(new_instructions);
+    }
+
+    /// Optimize the code by working out loops that run a known number of times ahead of time.
+    ///
+    /// A multiply loop takes 1 off the current cell and adds a constant to other cells each
+    /// time around, like `[->+++<]`, so it runs exactly as many times as the current cell's
+    /// value. When the cell is set right before the loop, that value is known, so the loop is
+    /// replaced with a clear and a single change to each of the other cells, e.g. `[-]++[->+++<]`
+    /// adds 6 to the next cell. Moves and copies right after a set are replaced the same way.
+    ///
+    /// Only loops right after a set are replaced, since that's the only time the value is
+    /// certain. Loops that move left of the current cell are left alone too, since what
+    /// happens past the start of the tape depends on the `PointerUnderflow` policy, and the
+    /// replacement can't clamp or wrap the way each time around the loop would.
+    pub fn optimize_fold_known_loops(&mut self) {
+        let mut new_instructions: Vec<Instruct> = vec![];
+        // Indices into new_instructions of the loops still open.
+        let mut open_loops = vec![];
+
+        for instruction in &self.0 {
+            // Where the set starts, the value it sets, and the change each time around to each
+            // other cell, by offset.
+            let known = match *instruction {
+                Instruct::OpenLoop => {
+                    open_loops.push(new_instructions.len());
+                    None
+                }
+                Instruct::CloseLoop => open_loops
+                    .pop()
+                    .filter(|&open| open > 0 && stays_right(&new_instructions[open + 1..]))
+                    .and_then(|open| {
+                        let targets = multiply_loop(&new_instructions[open + 1..])?;
+                        match new_instructions[open - 1] {
+                            Instruct::SetValue(value) => Some((open - 1, value, targets)),
+                            _ => None,
+                        }
+                    }),
+                Instruct::Move(to) => match new_instructions.last() {
+                    Some(&Instruct::SetValue(value)) if to > 0 => {
+                        Some((new_instructions.len() - 1, value, vec![(to, 1)]))
+                    }
+                    _ => None,
+                },
+                Instruct::Copy(a, b) => match new_instructions.last() {
+                    Some(&Instruct::SetValue(value)) if a > 0 && b > 0 => {
+                        Some((new_instructions.len() - 1, value, vec![(a, 1), (b, 1)]))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            let Some((start, value, targets)) = known else {
+                new_instructions.push(instruction.clone());
+                continue;
+            };
+            new_instructions.truncate(start);
+            new_instructions.push(Instruct::SetValue(0));
+            // A loop on a 0 cell never runs, so it never reaches the other cells either.
+            if value != 0 {
+                let mut offset = 0;
+                for (to, change) in targets {
+                    new_instructions.push(Instruct::MvPtr(to - offset));
+                    // Cells wrap, so only the total change mod 256 matters.
+                    let total = (change as i8).wrapping_mul(value as i8);
+                    new_instructions.push(Instruct::MvValue(total as isize));
+                    offset = to;
+                }
+                new_instructions.push(Instruct::MvPtr(-offset));
+            }
+        }
+
+        self.set_instructions(new_instructions);
+    }
+
+    /// Optimize the code by folding changes to the current cell into the set right before or
+    /// after them.
+    ///
+    /// A change right after a set just changes the value it sets, e.g. `[-]+++` sets the cell
+    /// to 3. A change or set right before a set is overwritten anyway, so it's removed.
+    pub fn optimize_fold_set_values(&mut self) {
+        let mut new_instructions: Vec<Instruct> = vec![];
+
+        for instruction in &self.0 {
+            match (instruction, new_instructions.last_mut()) {
+                (Instruct::MvValue(n), Some(Instruct::SetValue(value))) => {
+                    *value = value.wrapping_add_signed(*n as i8);
+                }
+                (Instruct::SetValue(_), Some(Instruct::MvValue(_) | Instruct::SetValue(_))) => {
+                    new_instructions.pop();
+                    new_instructions.push(instruction.clone());
+                }
+                _ => new_instructions.push(instruction.clone()),
+            }
+        }
+
+        self.set_instructions(new_instructions);
+    }
+}
+
+/// Get the single instruction a loop with `body` moves cells with, if it's a move loop as
+/// described on `Instructions::optimize_move_loops()`.
+fn move_loop(body: &[Instruct]) -> Option<Instruct> {
+    let targets = multiply_loop(body)?;
+    if targets.iter().any(|&(_, n)| n as i8 != 1) {
+        return None;
+    }
+
+    match targets[..] {
+        [(to, _)] => Some(Instruct::Move(to)),
+        [(a, _), (b, _)] => Some(Instruct::Copy(a, b)),
+        _ => None,
+    }
+}
+
+/// Check whether `code` never moves the cell pointer left of where it starts.
+///
+/// Moving right again only undoes moving left under some `PointerUnderflow` policies, so
+/// offsets can only be worked out ahead of time for code that stays on this side.
+fn stays_right(code: &[Instruct]) -> bool {
+    let mut offset = 0;
+    code.iter().all(|instruction| {
+        if let Instruct::MvPtr(n) = *instruction {
+            offset += n;
+        }
+        offset >= 0
+    })
+}
+
+/// Get the change to each other cell per time around a loop with `body`, sorted by offset, if
+/// it's a multiply loop as described on `Instructions::optimize_fold_known_loops()`.
+fn multiply_loop(body: &[Instruct]) -> Option<Vec<(isize, isize)>> {
+    let mut offset = 0;
+    // Change to each cell per time around the loop, by offset.
+    let mut changes: Vec<(isize, isize)> = vec![];
+
+    for instruction in body {
+        match *instruction {
+            Instruct::MvPtr(n) => offset += n,
+            Instruct::MvValue(n) => match changes.iter_mut().find(|(o, _)| *o == offset) {
+                Some((_, change)) => *change += n,
+                None => changes.push((offset, n)),
+            },
+            _ => return None,
+        }
+    }
+    if offset != 0 {
+        return None;
+    }
+
+    // Cells wrap, so only the change mod 256 matters.
+    changes.retain(|&(_, n)| n as i8 != 0);
+    let (source, mut targets): (Vec<_>, Vec<_>) = changes.into_iter().partition(|&(o, _)| o == 0);
+    if source.iter().map(|&(_, n)| n as i8).ne([-1]) {
+        return None;
+    }
+    targets.sort();
+
+    Some(targets)
+}
+
+/// What a `FoldedSegment` knows about a cell in the segment so far.
+#[derive(Clone, Copy)]
+enum TrackedValue {
+    /// The cell was set in the segment, so its value is known.
+    Known(u8),
+    /// The cell's value from before the segment isn't known, but it's had this much added.
+    Changed(isize),
+}
+
+/// State of the straight-line segment being folded by `Instructions::optimize_fold_constants()`
+/// and `Instructions::optimize_propagate_values()`.
+///
+/// All offsets are relative to where the cell pointer was at the start of the segment.
+#[derive(Default)]
+struct FoldedSegment {
+    /// What's known about each cell touched so far, by offset, and whether the folded code
+    /// already leaves the cell that way.
+    cells: Vec<(isize, TrackedValue, bool)>,
+    /// Where the cell pointer is in the original code.
+    offset: isize,
+    /// Where the cell pointer is in the folded code so far.
+    emitted_offset: isize,
+}
+
+impl FoldedSegment {
+    /// Get what's known about the current cell, starting with nothing if it's not been touched.
+    fn current(&mut self) -> &mut (isize, TrackedValue, bool) {
+        let idx = match self.cells.iter().position(|&(o, ..)| o == self.offset) {
+            Some(idx) => idx,
+            None => {
+                self.cells
+                    .push((self.offset, TrackedValue::Changed(0), true));
+                self.cells.len() - 1
+            }
+        };
+        &mut self.cells[idx]
+    }
+
+    /// Add `amount` to the current cell.
+    fn change(&mut self, amount: isize) {
+        let (_, value, written) = self.current();
+        *value = match *value {
+            TrackedValue::Known(n) => TrackedValue::Known(n.wrapping_add_signed(amount as i8)),
+            TrackedValue::Changed(n) => TrackedValue::Changed(n + amount),
+        };
+        *written = false;
+    }
+
+    /// Set the current cell to `value`, without emitting the set yet.
+    fn set(&mut self, value: u8) {
+        let (_, tracked, written) = self.current();
+        *tracked = TrackedValue::Known(value);
+        *written = false;
+    }
+
+    /// Forget everything about the cell at `offset`, including anything pending for it.
+    fn forget(&mut self, offset: isize) {
+        self.cells.retain(|&(o, ..)| o != offset);
+    }
+
+    /// Emit a pointer move to `offset`, if the pointer isn't already there.
+    fn move_to(&mut self, out: &mut Vec<Instruct>, offset: isize) {
+        if offset != self.emitted_offset {
+            out.push(Instruct::MvPtr(offset - self.emitted_offset));
+            self.emitted_offset = offset;
+        }
+    }
+
+    /// Emit whatever the folded code still needs to give the cell at `offset` its value,
+    /// moving the pointer there only if there's anything to emit.
+    fn write(&mut self, out: &mut Vec<Instruct>, offset: isize) {
+        let Some(idx) = self.cells.iter().position(|&(o, ..)| o == offset) else {
+            return;
+        };
+        let (_, value, written) = self.cells[idx];
+        if written {
+            return;
+        }
+
+        let instruction = match value {
+            TrackedValue::Known(n) => Some(Instruct::SetValue(n)),
+            // Cells wrap, so e.g. 255 `+` can be a single `-` instead.
+            TrackedValue::Changed(n) if n as i8 != 0 => Some(Instruct::MvValue(n as i8 as isize)),
+            TrackedValue::Changed(_) => None,
+        };
+        if let Some(instruction) = instruction {
+            self.move_to(out, offset);
+            out.push(instruction);
+        }
+
+        // A change has been made now, so there's nothing left of it to make.
+        if let TrackedValue::Changed(_) = value {
+            self.cells[idx].1 = TrackedValue::Changed(0);
+        }
+        self.cells[idx].2 = true;
+    }
+
+    /// Emit everything still to be written and the final pointer move, then start a new
+    /// segment.
+    fn finish(&mut self, out: &mut Vec<Instruct>) {
+        // Start with the cell the pointer is already on and end with the one the segment ends
+        // on, going from left to right in between, to keep the pointer moves short. A set of
+        // the last cell always goes last, so it stays right before a loop after the segment.
+        let end = self.offset;
+        let start = self.emitted_offset;
+        let mut offsets: Vec<(u8, isize)> = self
+            .cells
+            .iter()
+            .map(|&(o, value, _)| match value {
+                TrackedValue::Known(_) if o == end => (2, o),
+                _ if o == start => (0, o),
+                _ if o == end => (2, o),
+                _ => (1, o),
+            })
+            .collect();
+        offsets.sort();
+        for (_, offset) in offsets {
+            self.write(out, offset);
+        }
+        self.move_to(out, end);
+
+        *self = FoldedSegment::default();
+    }
+}
+
+/// Check if optimized instructions behave differently from the original ones.
+///
+/// Both are run with blank input, and they diverge if both finish but with different output or
+/// cells. A program that doesn't finish quickly, or that errors, is only compared if both do
+/// the same, since the optimizer is allowed to remove work and errors like moving off the tape.
+#[cfg(any(test, all(debug_assertions, feature = "log")))]
+fn optimization_diverges(original: &Instructions, optimized: &Instructions) -> bool {
+    /// Steps each version gets. Optimized code needs fewer steps, so this is only a rough limit.
+    const MAX_STEPS: usize = 100_000;
+
+    let run =
+        |instructions: &Instructions| sample_run(&Arc::new(instructions.clone()), &[], MAX_STEPS);
+
+    match (run(original), run(optimized)) {
+        ((Ok(()), run_a), (Ok(()), run_b)) => run_a != run_b,
+        _ => false,
+    }
+}
+
+/// Run instructions on `input` for at most `max_steps` steps, with reads past its end giving 0.
+///
+/// Returns how the run ended, along with the output and the cells with trailing zeroes trimmed
+/// off, since those are the same as cells that were never reached.
+fn sample_run(
+    instructions: &Arc<Instructions>,
+    input: &[u8],
+    max_steps: usize,
+) -> (std::result::Result<(), BFErrorKind>, (Vec<u8>, Vec<u8>)) {
+    let mut prgm = Program::new(Arc::clone(instructions));
+    let mut input = input.iter();
+    let mut output = vec![];
+
+    let result = prgm.execute_limited(
+        max_steps,
+        || input.next().map_or('\0', |&byte| byte as char),
+        |c| output.push(cell_byte(c)),
+    );
+
+    while prgm.cells.last() == Some(&0) {
+        prgm.cells.pop();
+    }
+    (result.map_err(|e| e.kind), (output, prgm.cells))
+}
+
+/// A character in BF source that isn't a command or whitespace, from
+/// `Instructions::from_string_with_diagnostics()`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// Byte offset of the character in the source.
+    pub position: usize,
+    /// The character itself.
+    pub character: char,
+}
+
+/// Nested structure of the loops in a program, from `Instructions::cfg()`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct LoopTree {
+    /// The top level loops, in program order.
+    pub roots: Vec<LoopNode>,
+}
+
+/// A single loop in a `LoopTree`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LoopNode {
+    /// Instruction index of the loop's `[`.
+    pub open: usize,
+    /// Instruction index of the loop's `]`.
+    pub close: usize,
+    /// Loops nested directly inside this one, in program order.
+    pub children: Vec<LoopNode>,
+}
+
+/// A straight-line run of instructions, from `Instructions::basic_blocks()`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BasicBlock {
+    /// Indices of the instructions in the block, not including the brackets around it.
+    pub range: std::ops::Range<usize>,
+    /// Indices of the blocks that can run right before this one, in order.
+    pub predecessors: Vec<usize>,
+    /// Indices of the blocks that can run right after this one: the block for a nonzero cell,
+    /// then the block for a zero cell. Empty for the last block.
+    pub successors: Vec<usize>,
+}
+
+/// Serializes the instructions back into canonical BF source.
+///
+/// Comments are not kept, and collapsed instructions are expanded back into repeated commands, so
+/// optimized instructions print as plain BF with any cancelled out commands removed.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::Instructions;
+/// let mut instructions = Instructions::from_string("++ comment +-- >><");
+/// instructions.optimize();
+/// assert_eq!(instructions.to_string(), "+>");
+/// ```
+impl fmt::Display for Instructions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in &self.0 {
+            write!(f, "{}", instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses BF source the same way as `Instructions::from_string()`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::Instructions;
+/// let instructions: Instructions = "+[-]".into();
+/// assert_eq!(instructions, Instructions::from_string("+[-]"));
+/// ```
+impl From<&str> for Instructions {
+    fn from(src: &str) -> Self {
+        Instructions::from_string(src)
+    }
+}
+
+/// Parses BF source the same way as `Instructions::from_string()`.
+impl From<String> for Instructions {
+    fn from(src: String) -> Self {
+        Instructions::from_string(&src)
+    }
+}
+
+/// Serializes a single instruction into BF source, the same way as for `Instructions`.
+impl fmt::Display for Instruct {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruct::MvPtr(n) => write!(f, "{}", pointer_moves(n)),
+            Instruct::MvValue(n) => {
+                let c = if n < 0 { "-" } else { "+" };
+                write!(f, "{}", c.repeat(n.unsigned_abs()))
+            }
+            Instruct::SetValue(n) => {
+                // Clear the cell, then count to the value whichever way is shorter.
+                let n = n as i8;
+                let c = if n < 0 { "-" } else { "+" };
+                write!(f, "[-]{}", c.repeat(n.unsigned_abs() as usize))
+            }
+            Instruct::Move(to) => write!(f, "[-{}+{}]", pointer_moves(to), pointer_moves(-to)),
+            Instruct::Copy(a, b) => write!(
+                f,
+                "[-{}+{}+{}]",
+                pointer_moves(a),
+                pointer_moves(b - a),
+                pointer_moves(-b)
+            ),
+            Instruct::Output => write!(f, "."),
+            Instruct::Input => write!(f, ","),
+            Instruct::InputLen => write!(f, "?"),
+            Instruct::OpenLoop => write!(f, "["),
+            Instruct::CloseLoop => write!(f, "]"),
+        }
+    }
+}
+
+/// Get the `>` or `<` commands that move the pointer by `n`.
+fn pointer_moves(n: isize) -> String {
+    let c = if n < 0 { "<" } else { ">" };
+    c.repeat(n.unsigned_abs())
+}
+
+/// Builds `Instructions` one operation at a time, without going through BF source.
+///
+/// Each operation becomes a single instruction, so `add(5)` is one instruction rather than
+/// five `+`s, the same as after `Instructions::optimize_combine_multiples()`. This is handier
+/// than putting together a string of BF when generating programs from code.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::*;
+/// let instructions = InstructionsBuilder::new()
+///     .add(5)
+///     .loop_start()
+///     .move_ptr(1)
+///     .add(13)
+///     .move_ptr(-1)
+///     .add(-1)
+///     .loop_end()
+///     .move_ptr(1)
+///     .output()
+///     .build()
+///     .unwrap();
+///
+/// let mut output = String::new();
+/// Program::new(instructions).execute(|| ' ', |c| output.push(c)).unwrap();
+/// assert_eq!(output, "A");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InstructionsBuilder {
+    instructions: Vec<Instruct>,
+}
+
+impl InstructionsBuilder {
+    /// Start building with no instructions.
+    pub fn new() -> InstructionsBuilder {
+        InstructionsBuilder::default()
+    }
+
+    /// Add `amount` to the current cell, or subtract if it's negative. Cells wrap, the same as
+    /// with `+` and `-`.
+    // This is BF's `+`, not addition of builders, so it shouldn't be `std::ops::Add`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, amount: isize) -> InstructionsBuilder {
+        self.instructions.push(Instruct::MvValue(amount));
+        self
+    }
+
+    /// Move the cell pointer `amount` cells right, or left if it's negative.
+    pub fn move_ptr(mut self, amount: isize) -> InstructionsBuilder {
+        self.instructions.push(Instruct::MvPtr(amount));
+        self
+    }
+
+    /// Output the current cell, like `.`.
+    pub fn output(mut self) -> InstructionsBuilder {
+        self.instructions.push(Instruct::Output);
+        self
+    }
+
+    /// Read input into the current cell, like `,`.
+    pub fn input(mut self) -> InstructionsBuilder {
+        self.instructions.push(Instruct::Input);
+        self
+    }
+
+    /// Start a loop, like `[`.
+    pub fn loop_start(mut self) -> InstructionsBuilder {
+        self.instructions.push(Instruct::OpenLoop);
+        self
+    }
+
+    /// End the innermost loop, like `]`.
+    pub fn loop_end(mut self) -> InstructionsBuilder {
+        self.instructions.push(Instruct::CloseLoop);
+        self
+    }
+
+    /// Finish building the instructions.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`
+    /// if the loops don't match up.
+    pub fn build(self) -> Result<Instructions> {
+        let instructions = Instructions(self.instructions, None);
+        instructions.cfg()?;
+        Ok(instructions)
+    }
+}
+
+/// Holds the BF program's functionality.
+///
+/// It contains methods for execution such as stepwise executing and full-program executing.
+///
+/// # Examples
+///
+/// Create Program struct directly from a string of BF code, and execute it.
+///
+/// ```rust
+/// # use rbf::*;
+/// let mut prgm = Program::from_string(",>,<.>.");
+///
+/// # let basic_input = BasicInput::new();
+/// # let mut basic_output = BasicOutput::new();
+/// # let input_closure = || basic_input.input_char();
+/// # let output_closure = |c| basic_output.print_char(c);
+/// prgm.execute(input_closure, output_closure).expect("Error in BF instructions.");
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    /// Instructions to execute, shared with any other programs made from the same `Arc`.
+    instructions: Arc<Instructions>,
+    /// Pointer to where in the instructions we are currently looking.
+    instruction_ptr: usize,
+
+    /// Program's memory -- Vec of cells in the ascii range of numbers.
+    cells: Vec<u8>,
+    /// Current location in memory.
+    cell_ptr: usize,
+    /// Cell the program started on. Moves along with the cells if the tape grows to the left.
+    origin: usize,
+
+    /// Simple var to manage loops.
+    loop_stack: Vec<usize>,
+
+    /// Number of bytes output since the program was last reset.
+    output_len: usize,
+
+    /// Whether the instructions contain any brackets. Programs without loops run through a
+    /// faster path in `Program::execute()`.
+    has_loops: bool,
+
+    /// Execution counts, only gathered once profiling is enabled.
+    profile: Option<Profile>,
+
+    /// Options set through `ProgramBuilder`.
+    config: Config,
+
+    /// Called whenever a cell changes, if set with `Program::on_cell_change()`.
+    cell_hook: Option<CellHook>,
+
+    /// Called whenever the tape grows, if set with `Program::on_tape_growth()`.
+    growth_hook: Option<GrowthHook>,
+
+    /// Steps left during a call to `Program::execute_limited()`, or None when unlimited.
+    step_budget: Option<usize>,
+
+    /// Bytes of input left for the extended `?` command, if known.
+    input_len: Option<usize>,
+
+    /// Reads of 0 since the last output or nonzero read, from a state already seen at one.
+    eof_reads: usize,
+
+    /// Hashes of the states the program has read 0 in since the last output or nonzero read.
+    eof_states: HashSet<u64>,
+
+    /// States from before recent steps, only recorded once history is enabled.
+    history: Option<History>,
+}
+
+/// Options that change how a `Program` executes. All off by default.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Config {
+    /// Treat a `]` with no matching `[` as a no-op instead of an error.
+    lenient_brackets: bool,
+    /// Error on output of cell values that aren't ascii instead of converting them as-is.
+    strict_output: bool,
+    /// Cell the cell pointer starts at.
+    start_pointer: usize,
+    /// Most bytes the program may output, if limited.
+    max_output: Option<usize>,
+    /// Number of cells on the tape, if it's fixed rather than growing as needed.
+    tape_len: Option<usize>,
+    /// What happens when the cell pointer moves left of cell 0.
+    pointer_underflow: PointerUnderflow,
+    /// Furthest a skipped loop's `]` may be from its `[`, if limited.
+    max_skip: Option<usize>,
+    /// Whether moving left of the cell the program started on is an error.
+    strict_origin: bool,
+    /// Cells to allocate up front whenever the tape is reset.
+    tape_capacity: usize,
+    /// Most reads of 0 without progress, as described on `ProgramBuilder::max_eof_reads()`,
+    /// before giving up on the program.
+    max_eof_reads: Option<usize>,
+}
+
+/// What happens when a program moves the cell pointer left of cell 0.
+///
+/// Set with `ProgramBuilder::pointer_underflow()`.
+///
+/// Optimized instructions from `Instructions::optimize()` only behave the same as the
+/// originals under `Error` and `Grow`. The passes combine and reorder pointer moves on the
+/// assumption that moving back always undoes a move, and that different offsets are different
+/// cells. Under `Clamp`, a move that stays on cell 0 can't be undone, and under `Wrap`, offsets
+/// a tape length apart are the same cell. Run unoptimized instructions with those two.
+/// `Instructions::normalize()` makes the same assumption about moves that cancel out.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum PointerUnderflow {
+    /// Stop with a `BFErrorKind::CellBoundsError` error.
+    #[default]
+    Error,
+    /// Wrap around to the right end of the tape. This needs a fixed tape length from
+    /// `ProgramBuilder::tape_len()`, since a growing tape has no right end. Without one, this
+    /// is the same as `PointerUnderflow::Error`.
+    Wrap,
+    /// Stay on cell 0.
+    Clamp,
+    /// Grow the tape to the left, so the new cell becomes cell 0 and every other cell's index
+    /// goes up to make room. Indices reported afterwards, like to `Program::on_cell_change()`
+    /// callbacks, are for the grown tape. With a fixed tape length from
+    /// `ProgramBuilder::tape_len()`, this is the same as `PointerUnderflow::Error`.
+    Grow,
+}
+
+/// Whether a program finished during a call to `Program::execute_chunked()`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExecutionStatus {
+    /// The program ran to the end.
+    Finished,
+    /// The program used up its steps for the chunk and has more to do.
+    Running,
+}
+
+/// Why `Program::run_until_cell()` stopped.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WatchStatus {
+    /// The cell got the value being watched for.
+    Reached,
+    /// The program ran to the end without the cell getting the value.
+    Finished,
+    /// The program used up its steps without the cell getting the value.
+    StepLimit,
+}
+
+/// How a program's output differed from what was expected, from `Program::expect()`.
+///
+/// Displays as a message meant for test failures, showing both outputs and where they first
+/// differ.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Mismatch {
+    /// Index of the first char where the outputs differ, or the length of the shorter output
+    /// if one is the start of the other.
+    pub position: usize,
+    /// What the program actually output.
+    pub actual: String,
+    /// What the program was expected to output.
+    pub expected: String,
+    /// The error the program stopped with, if it didn't run to the end.
+    pub error: Option<BFErrorKind>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(kind) = &self.error {
+            let error = BFError { kind: kind.clone() };
+            writeln!(f, "The program stopped with an error: {}", error)?;
+        }
+        if self.actual != self.expected {
+            writeln!(f, "The output differs at char {}.", self.position)?;
+        }
+        writeln!(f, "Expected output: {:?}", self.expected)?;
+        write!(f, "Actual output:   {:?}", self.actual)
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Where a program first went over its tape budget, from `Program::check_tape_budget()`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BudgetExceeded {
+    /// How many cells the program was allowed to use.
+    pub budget: usize,
+    /// How many steps had run when the program went over, counting the one that did it.
+    pub step: usize,
+    /// Index of the cell the cell pointer was on when the program went over.
+    pub cell: usize,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The program went over its budget of {} cells at step {}, on cell {}.",
+            self.budget, self.step, self.cell
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Callback run with the index and new value of a cell whenever it changes.
+///
+/// Closures can't be compared or printed, so this wrapper lets `Program` keep deriving
+/// `Debug` and `PartialEq`. Two programs are equal regardless of their callbacks.
+struct CellHook(Box<dyn FnMut(usize, u8) + Send>);
+
+impl fmt::Debug for CellHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CellHook")
+    }
+}
+
+impl PartialEq for CellHook {
+    fn eq(&self, _other: &CellHook) -> bool {
+        true
+    }
+}
+
+/// Callback run with the new length of the tape whenever it grows, wrapped for the same reason
+/// as `CellHook`.
+struct GrowthHook(Box<dyn FnMut(usize) + Send>);
+
+impl fmt::Debug for GrowthHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("GrowthHook")
+    }
+}
+
+impl PartialEq for GrowthHook {
+    fn eq(&self, _other: &GrowthHook) -> bool {
+        true
+    }
+}
+
+/// Builds a `Program` with non-default options.
+///
+/// `Program::new()` is the same as building with every option left at its default.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::*;
+/// let instructions = Instructions::from_string("+]+.");
+/// let mut prgm = ProgramBuilder::new(instructions)
+///     .lenient_brackets(true)
+///     .build();
+///
+/// prgm.execute(|| ' ', |_| ()).expect("Unmatched ] is ignored.");
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProgramBuilder {
+    instructions: Arc<Instructions>,
+    config: Config,
+}
+
+impl ProgramBuilder {
+    /// Start building a program from the given instructions, either owned or shared through an
+    /// `Arc`. See `Program::new()`.
+    pub fn new<I>(instructions: I) -> ProgramBuilder
+    where
+        I: Into<Arc<Instructions>>,
+    {
+        ProgramBuilder {
+            instructions: instructions.into(),
+            config: Config::default(),
+        }
+    }
+
+    /// Treat a `]` with no matching `[` as a no-op rather than a `BFErrorKind::MissingOpen`
+    /// error.
+    ///
+    /// Some lenient interpreters silently ignore unmatched close brackets, so programs written
+    /// against them might rely on it. An unmatched `[` is still an error.
+    pub fn lenient_brackets(mut self, lenient: bool) -> ProgramBuilder {
+        self.config.lenient_brackets = lenient;
+        self
+    }
+
+    /// Stop with a `BFErrorKind::InvalidOutput` error when a program outputs a cell value that
+    /// isn't ascii, rather than silently converting it.
+    ///
+    /// Output closures get each cell as a `char`, and by default values above 127 are converted
+    /// straight to the char with that code point. That's rarely what the program meant, e.g. a
+    /// program outputting UTF-8 bytes will end up with garbled text, so strict output catches
+    /// programs emitting unexpected bytes when ascii was assumed.
+    pub fn strict_output(mut self, strict: bool) -> ProgramBuilder {
+        self.config.strict_output = strict;
+        self
+    }
+
+    /// Stop with a `BFErrorKind::OutputLimitExceeded` error if the program tries to output more
+    /// than `bytes` bytes since it was last reset.
+    ///
+    /// Unbounded output from untrusted programs can be a problem in the same way endless
+    /// execution can, so this puts a bound on it. There's no limit by default.
+    pub fn max_output(mut self, bytes: usize) -> ProgramBuilder {
+        self.config.max_output = Some(bytes);
+        self
+    }
+
+    /// Start the cell pointer at cell `idx` instead of cell 0.
+    ///
+    /// The tape is grown to include the starting cell, and `Program::reset()` puts the cell
+    /// pointer back here rather than at 0. This is useful when the program expects data at a
+    /// known offset in memory.
+    pub fn start_pointer(mut self, idx: usize) -> ProgramBuilder {
+        self.config.start_pointer = idx;
+        self
+    }
+
+    /// Give the program a fixed tape of `cells` cells, rather than one that grows as needed.
+    ///
+    /// Moving the cell pointer past the right end of a fixed tape is a
+    /// `BFErrorKind::CellBoundsError` error, like moving left of cell 0 is by default. The tape
+    /// always has at least one cell, so a length of 0 is treated as 1. The start pointer should
+    /// be on the tape.
+    pub fn tape_len(mut self, cells: usize) -> ProgramBuilder {
+        self.config.tape_len = Some(cells.max(1));
+        self
+    }
+
+    /// Choose what happens when the cell pointer moves left of cell 0. By default it's a
+    /// `BFErrorKind::CellBoundsError` error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let mut prgm = ProgramBuilder::new(Instructions::from_string("<+"))
+    ///     .tape_len(8)
+    ///     .pointer_underflow(PointerUnderflow::Wrap)
+    ///     .build();
+    ///
+    /// // The `<` wraps around to the last cell.
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    /// ```
+    pub fn pointer_underflow(mut self, policy: PointerUnderflow) -> ProgramBuilder {
+        self.config.pointer_underflow = policy;
+        self
+    }
+
+    /// Stop with a `BFErrorKind::CellBoundsError` error if the cell pointer moves left of the
+    /// cell the program started on.
+    ///
+    /// This is separate from `ProgramBuilder::pointer_underflow()`, which is about what the
+    /// machine supports. Strict origin is about what a particular program should do, so it
+    /// catches a program going somewhere it isn't meant to even when the tape could handle it,
+    /// e.g. to check that generated code stays within its expected layout. It applies with any
+    /// underflow policy, and with `ProgramBuilder::start_pointer()` the origin is the start
+    /// pointer rather than cell 0.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let mut prgm = ProgramBuilder::new(Instructions::from_string(">+<<"))
+    ///     .pointer_underflow(PointerUnderflow::Grow)
+    ///     .strict_origin(true)
+    ///     .build();
+    ///
+    /// assert!(prgm.execute(|| ' ', |_| ()).is_err());
+    /// ```
+    pub fn strict_origin(mut self, strict: bool) -> ProgramBuilder {
+        self.config.strict_origin = strict;
+        self
+    }
+
+    /// Stop with a `BFErrorKind::SkipLimitExceeded` error if skipping a loop means scanning
+    /// over more than `instructions` instructions to find its `]`.
+    ///
+    /// Skipping a loop whose cell is 0 scans forward for the matching `]`, so a `[` with a
+    /// huge region after it, and no `]` anywhere nearby, can take a lot of work before it's
+    /// found to be unmatched. This bounds that work, which is useful for servers running
+    /// untrusted programs. There's no limit by default.
+    pub fn max_skip_distance(mut self, instructions: usize) -> ProgramBuilder {
+        self.config.max_skip = Some(instructions);
+        self
+    }
+
+    /// Allocate room for `cells` cells up front, rather than growing the tape as it's used.
+    ///
+    /// The tape still starts out empty and grows one cell at a time, but it won't need to
+    /// reallocate until it grows past `cells`. For programs whose tape size is roughly known,
+    /// e.g. from `Profile::cell_range`, this saves the reallocations and copying as the
+    /// pointer moves out. Unlike `ProgramBuilder::tape_len()`, it's only a hint, so going past
+    /// it is fine.
+    pub fn tape_capacity_hint(mut self, cells: usize) -> ProgramBuilder {
+        self.config.tape_capacity = cells;
+        self
+    }
+
+    /// Stop with a `BFErrorKind::InputExhausted` error once a program reads 0 more than
+    /// `reads` times without making any progress.
+    ///
+    /// Reads give 0 once input has run out, so a program that loops until it gets some
+    /// particular input, like a menu waiting for a valid choice, would spin forever reading 0.
+    /// This catches that, which is handy for running interactive programs with piped input
+    /// they read too much of. A read of 0 only counts if the program is in exactly the same
+    /// state as at an earlier read of 0, with the same instruction, cell pointer, cells and
+    /// loops, since that means it's going round in circles. Any nonzero read or any output
+    /// is progress and starts the count over. So programs that actually do something with
+    /// zeroes in their input, like counting them, are never cut off. There's no limit by
+    /// default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{errors::BFErrorKind, Instructions, ProgramBuilder};
+    /// // Waits for someone to type a nonzero byte, which never happens.
+    /// let mut prgm = ProgramBuilder::new(Instructions::from_string(",[-]+[,[-]+]"))
+    ///     .max_eof_reads(100)
+    ///     .build();
+    ///
+    /// let error = prgm.execute(|| '\0', |_| ()).unwrap_err();
+    /// assert_eq!(error.kind, BFErrorKind::InputExhausted);
+    /// ```
+    pub fn max_eof_reads(mut self, reads: usize) -> ProgramBuilder {
+        self.config.max_eof_reads = Some(reads);
+        self
+    }
+
+    /// Create the program.
+    pub fn build(self) -> Program {
+        let mut prgm = Program::new(self.instructions);
+        prgm.config = self.config;
+        prgm.reset();
+        prgm
+    }
+}
+
+/// Iterator over the bytes a program outputs, made by `Program::output_iter()`.
+///
+/// The program only runs while `next()` is looking for the next byte, so output can be handled
+/// as it's produced at whatever pace suits the consumer. An error is returned as the last
+/// item, after which the iterator always gives None.
+pub struct OutputIter<'a, Fin>
+where
+    Fin: FnMut() -> char,
+{
+    prgm: &'a mut Program,
+    input: Fin,
+    finished: bool,
+}
+
+impl<Fin> Iterator for OutputIter<'_, Fin>
+where
+    Fin: FnMut() -> char,
+{
+    type Item = Result<u8>;
+
+    fn next(&mut self) -> Option<Result<u8>> {
+        while !self.finished {
+            let mut byte = None;
+            let stepped = match self.prgm.done() {
+                Ok(true) => Ok(false),
+                Ok(false) => self
+                    .prgm
+                    .step(&mut self.input, |c| byte = Some(cell_byte(c)))
+                    .map(|()| true),
+                Err(e) => Err(e),
+            };
+
+            match stepped {
+                Ok(true) => {
+                    if let Some(byte) = byte {
+                        return Some(Ok(byte));
+                    }
+                }
+                Ok(false) => self.finished = true,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<Fin> std::iter::FusedIterator for OutputIter<'_, Fin> where Fin: FnMut() -> char {}
+
+/// Execution statistics gathered by a `Program` with profiling enabled.
+///
+/// See `Program::enable_profiling()`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Profile {
+    /// How many times each instruction was executed, indexed by instruction.
+    pub instruction_counts: Vec<u64>,
+    /// How many times the body of each loop ran, keyed by the index of the loop's `[`. Loops
+    /// whose body never ran aren't included.
+    pub loop_iterations: BTreeMap<usize, u64>,
+    /// The lowest and highest cell index the cell pointer has been on, which is how much of
+    /// the tape the program actually uses. Handy for picking a size for `--tape-len`.
+    pub cell_range: (usize, usize),
+}
+
+impl Profile {
+    /// Widen the cell range to include `cell`.
+    fn visit_cell(&mut self, cell: usize) {
+        let (min, max) = &mut self.cell_range;
+        *min = (*min).min(cell);
+        *max = (*max).max(cell);
+    }
+}
+
+/// The most recent steps of a program, for stepping backwards. See
+/// `Program::enable_history()`.
+#[derive(Debug, PartialEq)]
+struct History {
+    /// Most steps kept before the oldest are dropped.
+    limit: usize,
+    /// How to undo each of the most recent steps, oldest first.
+    steps: std::collections::VecDeque<StepUndo>,
+    /// How to undo the step that's running, if there is one, for the cells to add to.
+    running: Option<StepUndo>,
+}
+
+/// Everything needed to undo a step, apart from profiling counts.
+///
+/// Only what the step changed is kept, rather than a copy of the whole tape, so recording a
+/// step costs about the same however big the tape is.
+#[derive(Debug, PartialEq)]
+struct StepUndo {
+    instruction_ptr: usize,
+    cell_ptr: usize,
+    origin: usize,
+    output_len: usize,
+    input_len: Option<usize>,
+    eof_reads: usize,
+    /// The reads of 0 being tracked, only kept for I/O steps since nothing else changes them.
+    eof_states: Option<HashSet<u64>>,
+    /// The length of the loop stack and its last loop. A step only ever pushes or pops one
+    /// loop, so that's enough to put it back.
+    loop_depth: usize,
+    loop_top: Option<usize>,
+    /// How long the tape was, since it only grows to the right by adding zeroed cells.
+    tape_len: usize,
+    /// Each change the step made to the tape, in order.
+    changes: Vec<CellUndo>,
+}
+
+/// A single change a step made to the tape, for `StepUndo`.
+#[derive(Debug, PartialEq)]
+enum CellUndo {
+    /// The cell at this index had this value before it was written.
+    Write(usize, u8),
+    /// This many cells were added to the left end of the tape.
+    GrowLeft(usize),
+}
+
+/// Creates a program from BF source the same way as `Program::from_string()`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::Program;
+/// let programs: Vec<Program> = ["+.", ",."].into_iter().map(Program::from).collect();
+/// assert_eq!(programs[0], Program::from_string("+."));
+/// ```
+impl From<&str> for Program {
+    fn from(src: &str) -> Self {
+        Program::from_string(src)
+    }
+}
+
+/// Creates a program from BF source the same way as `Program::from_string()`.
+impl From<String> for Program {
+    fn from(src: String) -> Self {
+        Program::from_string(&src)
+    }
+}
+
+impl Program {
+    /// Clear and reset the program state.
+    ///
+    /// Clears the cells, instruction pointer, cell pointer, and loop stack. Subsequently
+    /// calling `Program::execute()` or `Program::step()` will begin the program from the
+    /// beginning. The cell pointer goes back to the start pointer the program was built with,
+    /// which is 0 unless set with `ProgramBuilder::start_pointer()`.
+    pub fn reset(&mut self) {
+        self.instruction_ptr = 0;
+        self.cells.clear();
+        self.cells.reserve(self.config.tape_capacity);
+        self.cell_ptr = self.config.start_pointer;
+        self.origin = self.config.start_pointer;
+        self.loop_stack.clear();
+        self.output_len = 0;
+        self.clear_eof_reads();
+        if let Some(history) = self.history.as_mut() {
+            history.steps.clear();
+        }
+
+        // A start pointer of 0 leaves the tape empty until the first step, same as a new program.
+        if self.cell_ptr > 0 {
+            self.validate_cells_length();
+        }
+    }
+
+    /// Create a new program struct.
+    ///
+    /// This constructor requires the instructions to already be represented by an `Instructions`
+    /// struct. The instructions can be passed by value, or as an `Arc<Instructions>` to share
+    /// them between many programs without cloning. Each program still has its own cells and
+    /// state, so programs sharing instructions can run in parallel on different threads.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// let instructions = Arc::new(Instructions::from_string(",+."));
+    ///
+    /// let workers: Vec<_> = ['a', 'b', 'c']
+    ///     .into_iter()
+    ///     .map(|c| {
+    ///         let mut prgm = Program::new(Arc::clone(&instructions));
+    ///         thread::spawn(move || {
+    ///             let mut output = String::new();
+    ///             prgm.execute(|| c, |c| output.push(c)).unwrap();
+    ///             output
+    ///         })
+    ///     })
+    ///     .collect();
+    ///
+    /// let outputs: Vec<String> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+    /// assert_eq!(outputs, ["b", "c", "d"]);
+    /// ```
+    pub fn new<I>(instructions: I) -> Program
+    where
+        I: Into<Arc<Instructions>>,
+    {
+        let instructions = instructions.into();
+        let has_loops = instructions
+            .0
+            .iter()
+            .any(|i| matches!(i, Instruct::OpenLoop | Instruct::CloseLoop));
+
+        Program {
+            instructions,
+            instruction_ptr: 0,
+            cells: vec![],
+            cell_ptr: 0,
+            origin: 0,
+            loop_stack: vec![],
+            output_len: 0,
+            has_loops,
+            profile: None,
+            config: Config::default(),
+            cell_hook: None,
+            growth_hook: None,
+            step_budget: None,
+            input_len: None,
+            eof_reads: 0,
+            eof_states: HashSet::new(),
+            history: None,
+        }
+    }
+
+    /// Create a new program directly from a string of BF code.
+    ///
+    /// This method is a wrapper of the Program::new() method, creating a new Instructions
+    /// struct from the instructions string first.
+    pub fn from_string(instructions: &str) -> Program {
+        Self::new(Instructions::from_string(instructions))
+    }
+
+    /// Check if two programs have the same instructions, ignoring how far along they are.
+    ///
+    /// The derived `PartialEq` for `Program` compares execution state too, so two programs
+    /// running the same code only compare equal if they're at exactly the same point. This only
+    /// compares the code itself.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("+.");
+    /// let other = Program::from_string("+.");
+    /// prgm.step(|| ' ', |_| ()).unwrap();
+    ///
+    /// assert!(prgm.same_program(&other));
+    /// assert_ne!(prgm, other);
+    /// ```
+    pub fn same_program(&self, other: &Program) -> bool {
+        self.instructions == other.instructions
+    }
+
+    /// Hash the current machine state, for quickly comparing states.
+    ///
+    /// The hash covers the cells, the cell pointer, the instruction pointer, and the loops the
+    /// program is inside of. Trailing zero cells are ignored, since the tape is only grown as
+    /// it's used and a cell that hasn't been reached yet is the same as one that's 0. So two
+    /// programs in the same state always hash the same, which makes this handy for things like
+    /// detecting a program stuck in a cycle. Instructions aren't part of the hash.
+    ///
+    /// This uses FNV-1a, which is fast but not collision resistant, so equal hashes only mean
+    /// the states are very likely the same.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("+[>+<]");
+    /// prgm.step(|| ' ', |_| ()).unwrap();
+    /// prgm.step(|| ' ', |_| ()).unwrap();
+    /// let start = prgm.state_hash();
+    ///
+    /// // The loop goes back to the same spot, but the cell to the right keeps changing.
+    /// for _ in 0..4 {
+    ///     prgm.step(|| ' ', |_| ()).unwrap();
+    /// }
+    /// assert_ne!(prgm.state_hash(), start);
+    /// ```
+    pub fn state_hash(&self) -> u64 {
+        self.state_hash_with(&self.cells)
+    }
+
+    /// Hash the state the same as `Program::state_hash()`, but with `cells` as the tape, for
+    /// when the program is running on a tape of its own, like in `Program::execute_in()`.
+    fn state_hash_with(&self, cells: &[u8]) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let used_cells = cells
+            .iter()
+            .rposition(|&c| c != 0)
+            .map_or(0, |last| last + 1);
+
+        let mut hash = FNV_OFFSET;
+        let mut write = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+
+        // Lengths go in first so the variable-length parts can't run into each other.
+        write(&used_cells.to_le_bytes());
+        write(&cells[..used_cells]);
+        write(&self.cell_ptr.to_le_bytes());
+        write(&self.instruction_ptr.to_le_bytes());
+        write(&self.loop_stack.len().to_le_bytes());
+        for open in &self.loop_stack {
+            write(&open.to_le_bytes());
+        }
+
+        hash
+    }
+
+    /// Get the cells of the tape that have been reached so far.
+    ///
+    /// The tape only grows as the cell pointer moves onto new cells, so any cell past the end
+    /// of the slice is 0.
+    pub fn tape(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Save a copy of the tape, and nothing else about the program.
+    ///
+    /// This is cheaper than cloning the whole `Program` when only the memory matters. Put it
+    /// back with `Program::restore_tape()`.
+    pub fn snapshot_tape(&self) -> Vec<u8> {
+        self.cells.clone()
+    }
+
+    /// Replace the tape with a snapshot from `Program::snapshot_tape()`, or any other cells.
+    ///
+    /// Only the tape changes. The instruction pointer, cell pointer and loops are left as they
+    /// are, and the tape's existing allocation is reused where it's big enough. If the tape
+    /// has a fixed length, cells past the end of it are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("+>++");
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    /// let snapshot = prgm.snapshot_tape();
+    ///
+    /// prgm.reset();
+    /// prgm.restore_tape(&snapshot);
+    /// assert_eq!(prgm.tape(), &[1, 2]);
+    /// ```
+    pub fn restore_tape(&mut self, tape: &[u8]) {
+        let len = self
+            .config
+            .tape_len
+            .map_or(tape.len(), |len| len.min(tape.len()));
+
+        self.cells.clear();
+        self.cells.extend_from_slice(&tape[..len]);
+        // The cell pointer might be past the end of a shorter tape.
+        self.validate_cells_length();
+    }
+
+    /// Get the index of the cell the cell pointer is on.
+    pub fn cell_pointer(&self) -> usize {
+        self.cell_ptr
+    }
+
+    /// Get the index of the instruction the next `Program::step()` will run.
+    ///
+    /// This is past the end of the instructions once the program is done.
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_ptr
+    }
+
+    /// Get the instruction the next `Program::step()` will run as an `Op`, or None if the
+    /// program is done.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{Op, Program};
+    /// let mut prgm = Program::from_string("+.");
+    /// prgm.step(|| ' ', |_| ()).unwrap();
+    /// assert_eq!(prgm.current_op(), Some(Op::Output));
+    ///
+    /// prgm.step(|| ' ', |_| ()).unwrap();
+    /// assert_eq!(prgm.current_op(), None);
+    /// ```
+    pub fn current_op(&self) -> Option<Op> {
+        self.instructions.op_at(self.instruction_ptr)
+    }
+
+    /// Get the instruction index of the `[` of each loop the program is currently inside,
+    /// from the outermost loop in.
+    pub fn loop_stack(&self) -> &[usize] {
+        &self.loop_stack
+    }
+
+    /// Break out of the innermost loop the program is currently inside.
+    ///
+    /// The program carries on from the instruction after the loop's `]`, as if the loop had
+    /// just ended, whatever the current cell is. This is for debuggers that want to let the
+    /// user skip the rest of a loop. Nothing else about the program changes, so the loops
+    /// around this one are still running.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::NotInLoop` if the program isn't inside a
+    /// loop, or `BFErrorKind::MissingClose` if the loop has no `]`. Either way, nothing
+    /// changes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("+[+]+");
+    /// for _ in 0..3 {
+    ///     prgm.step(|| ' ', |_| ()).unwrap();
+    /// }
+    /// assert_eq!(prgm.loop_stack(), &[1]);
+    ///
+    /// prgm.break_loop().unwrap();
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    /// assert!(prgm.loop_stack().is_empty());
+    /// assert_eq!(prgm.tape(), &[3]);
+    /// ```
+    pub fn break_loop(&mut self) -> Result<()> {
+        let open = *self.loop_stack.last().ok_or(BFError {
+            kind: BFErrorKind::NotInLoop,
+        })?;
+        let close = self.instructions.matching_bracket(open).ok_or(BFError {
+            kind: BFErrorKind::MissingClose,
+        })?;
+
+        self.loop_stack.pop();
+        self.instruction_ptr = close + 1;
+        Ok(())
+    }
+
+    /// Start recording the program's state before each step, so the program can be stepped
+    /// backwards with `Program::step_back()` and `Program::step_back_iteration()`.
+    ///
+    /// Only the last `limit` steps are kept. Each one only keeps what the step changed, so
+    /// history works on big tapes too, but it still slows every step down, so this is meant
+    /// for debuggers rather than for running programs fast. Steps are recorded by
+    /// `Program::step()`, and so by `Program::execute()` and the other methods built on it.
+    /// Steps that fail aren't recorded, since there's nothing to step back to after them.
+    /// `Program::reset()` forgets everything recorded, and calling this again starts over too.
+    pub fn enable_history(&mut self, limit: usize) {
+        self.history = Some(History {
+            limit,
+            steps: std::collections::VecDeque::new(),
+            running: None,
+        });
+    }
+
+    /// Start recording how to undo the step about to run, if history is enabled.
+    fn start_history_step(&mut self) {
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+        if history.limit == 0 {
+            return;
+        }
+
+        let io = matches!(
+            self.instructions.0.get(self.instruction_ptr),
+            Some(Instruct::Input | Instruct::InputLen | Instruct::Output)
+        );
+        history.running = Some(StepUndo {
+            instruction_ptr: self.instruction_ptr,
+            cell_ptr: self.cell_ptr,
+            origin: self.origin,
+            output_len: self.output_len,
+            input_len: self.input_len,
+            eof_reads: self.eof_reads,
+            eof_states: io.then(|| self.eof_states.clone()),
+            loop_depth: self.loop_stack.len(),
+            loop_top: self.loop_stack.last().copied(),
+            tape_len: self.cells.len(),
+            changes: vec![],
+        });
+    }
+
+    /// Stop recording the step that was running, keeping it in the history if it `succeeded`.
+    fn finish_history_step(&mut self, succeeded: bool) {
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+        let Some(step) = history.running.take() else {
+            return;
+        };
+        if !succeeded {
+            return;
+        }
+
+        if history.steps.len() == history.limit {
+            history.steps.pop_front();
+        }
+        history.steps.push_back(step);
+    }
+
+    /// Note a change to the tape in the step being recorded, if there is one.
+    fn record_cell_change(&mut self, change: CellUndo) {
+        if let Some(step) = self.history.as_mut().and_then(|h| h.running.as_mut()) {
+            step.changes.push(change);
+        }
+    }
+
+    /// Undo everything `step` recorded.
+    fn undo_step(&mut self, step: StepUndo) {
+        for change in step.changes.into_iter().rev() {
+            match change {
+                CellUndo::Write(idx, value) => self.cells[idx] = value,
+                CellUndo::GrowLeft(cells) => drop(self.cells.drain(..cells)),
+            }
+        }
+        self.cells.truncate(step.tape_len);
+
+        self.instruction_ptr = step.instruction_ptr;
+        self.cell_ptr = step.cell_ptr;
+        self.origin = step.origin;
+        self.output_len = step.output_len;
+        self.input_len = step.input_len;
+        self.eof_reads = step.eof_reads;
+        if let Some(eof_states) = step.eof_states {
+            self.eof_states = eof_states;
+        }
+        self.loop_stack.truncate(step.loop_depth.saturating_sub(1));
+        self.loop_stack.extend(step.loop_top);
+    }
+
+    /// Undo the last step, using the history from `Program::enable_history()`.
+    ///
+    /// Everything the step changed is put back, apart from profiling counts, and no cell
+    /// change callbacks are called. Output already written and input already read can't be
+    /// taken back, so running forward again reads new input. Returns false, without changing
+    /// anything, if history isn't enabled or there are no steps left in it to undo.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("+>++");
+    /// prgm.enable_history(100);
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    ///
+    /// assert!(prgm.step_back());
+    /// assert_eq!(prgm.tape(), &[1, 1]);
+    /// ```
+    pub fn step_back(&mut self) -> bool {
+        match self.history.as_mut().and_then(|h| h.steps.pop_back()) {
+            Some(step) => {
+                self.undo_step(step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rewind to the start of the current iteration of the innermost loop the program is
+    /// inside, using the history from `Program::enable_history()`.
+    ///
+    /// The start of an iteration is right after its `[` ran, so the program is back at the
+    /// first instruction of the loop's body with the cells as they were when the iteration
+    /// began. If the program is already there, it rewinds to the start of the iteration
+    /// before, so calling this over and over goes back through the loop one iteration at a
+    /// time. Loops around the innermost one aren't rewound past. Everything is undone the same
+    /// way as with `Program::step_back()`.
+    ///
+    /// Returns false, without changing anything, if the history doesn't go back far enough.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::NotInLoop` if the program isn't inside a
+    /// loop.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("+++[>+<-]");
+    /// prgm.enable_history(100);
+    /// // Partway through the second time around the loop.
+    /// for _ in 0..13 {
+    ///     prgm.step(|| ' ', |_| ()).unwrap();
+    /// }
+    /// assert_eq!(prgm.tape(), &[2, 2]);
+    ///
+    /// // Back to the start of the second time around, then the first.
+    /// assert!(prgm.step_back_iteration().unwrap());
+    /// assert_eq!(prgm.tape(), &[2, 1]);
+    /// assert!(prgm.step_back_iteration().unwrap());
+    /// assert_eq!(prgm.tape(), &[3]);
+    /// assert!(!prgm.step_back_iteration().unwrap());
+    /// ```
+    pub fn step_back_iteration(&mut self) -> Result<bool> {
+        let open = *self.loop_stack.last().ok_or(BFError {
+            kind: BFErrorKind::NotInLoop,
+        })?;
+        let Some(history) = self.history.as_mut() else {
+            return Ok(false);
+        };
+
+        // Inside the loop, the loop stack only ever gets shorter than it is now when the
+        // loop's own `[` runs to start each iteration. Anything shorter means the program
+        // wasn't in this run of the loop yet.
+        let depth = self.loop_stack.len();
+        let mut start = None;
+        for (idx, step) in history.steps.iter().enumerate().rev() {
+            if step.instruction_ptr == open && step.loop_depth + 1 == depth {
+                // Right after the `[` is the start of the iteration, unless that's now.
+                if idx + 1 < history.steps.len() {
+                    start = Some(idx + 1);
+                    break;
+                }
+            } else if step.loop_depth < depth {
+                break;
+            }
+        }
+
+        let Some(start) = start else {
+            return Ok(false);
+        };
+        let steps: Vec<StepUndo> = history.steps.drain(start..).collect();
+        for step in steps.into_iter().rev() {
+            self.undo_step(step);
+        }
+        Ok(true)
+    }
+
+    /// Render the tape as a hex dump, the same way `--dump-tape` prints it.
+    ///
+    /// Each line shows 16 cells, starting with the index of the first one in hex, then each
+    /// cell in hex, then the cells as ascii with anything unprintable shown as `.`. The cell
+    /// the cell pointer is on is marked with a `>` in front of it. Only the region of the tape
+    /// up to the last nonzero cell or the cell pointer, whichever is further, is shown. Use
+    /// `Program::dump_tape()` to show the cells in another radix.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+>++");
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    ///
+    /// assert_eq!(
+    ///     prgm.hex_dump(),
+    ///     "00000000  00 41>02                                          |.A.|\n"
+    /// );
+    /// ```
+    pub fn hex_dump(&self) -> String {
+        self.dump_tape(Radix::Hex)
+    }
+
+    /// Render the tape the same way as `Program::hex_dump()`, but with the cells in `radix`.
+    ///
+    /// Every cell is padded to the same width, so the columns still line up. The index at the
+    /// start of each line stays in hex.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{Program, Radix};
+    /// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+>++");
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    ///
+    /// assert!(prgm
+    ///     .dump_tape(Radix::Decimal)
+    ///     .starts_with("00000000  000 065>002 "));
+    /// ```
+    pub fn dump_tape(&self, radix: Radix) -> String {
+        const ROW_LEN: usize = 16;
+        let width = radix.width();
+
+        let used_cells = self
+            .cells
+            .iter()
+            .rposition(|&c| c != 0)
+            .map_or(0, |last| last + 1)
+            .max(self.cell_ptr + 1);
+        let cell = |idx: usize| self.cells.get(idx).copied().unwrap_or(0);
+
+        let mut dump = String::new();
+        for start in (0..used_cells).step_by(ROW_LEN) {
+            let end = (start + ROW_LEN).min(used_cells);
+            dump += &format!("{:08x} ", start);
+
+            for idx in start..start + ROW_LEN {
+                // An extra space splits the row in half, like most hex dumps do.
+                if idx - start == ROW_LEN / 2 {
+                    dump.push(' ');
+                }
+                dump.push(if idx == self.cell_ptr { '>' } else { ' ' });
+                if idx < end {
+                    dump += &radix.format_padded(cell(idx));
+                } else {
+                    dump += &" ".repeat(width);
+                }
+            }
+
+            dump += "  |";
+            for idx in start..end {
+                let c = cell(idx);
+                dump.push(if c.is_ascii_graphic() || c == b' ' {
+                    c as char
+                } else {
+                    '.'
+                });
+            }
+            dump += "|\n";
+        }
+
+        dump
+    }
+
+    /// Tell the program how many bytes of input it has left, for the extended `?` command from
+    /// `Instructions::from_string_extended()`.
+    ///
+    /// Every `,` after this counts down by one. Set it again before running the program with
+    /// new input, since it isn't touched by `Program::reset()`. Use the length of the input
+    /// source, e.g. from `BasicInput::input_remaining()`, or None if it isn't known, in which
+    /// case `?` is an error.
+    pub fn set_input_len(&mut self, len: Option<usize>) {
+        self.input_len = len;
+    }
+
+    /// Get the number of instructions in the program. See `Instructions::len()`.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Check if the program has no instructions at all. See `Instructions::is_empty()`.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Parse more BF code and append it to the end of the program's instructions.
+    ///
+    /// Execution state is left alone, so a program that has finished will continue on into
+    /// the new instructions the next time it's executed or stepped. This is what allows feeding
+    /// a program to the interpreter a piece at a time, e.g. from a REPL. If the instructions are
+    /// shared with other programs, this program gets its own copy first, so the others aren't
+    /// affected.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`
+    /// if the brackets of the existing and appended code combined don't match, in which case
+    /// nothing is appended. Unmatched `]` are allowed if the program was built with lenient
+    /// brackets.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut outstring = String::new();
+    /// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+.");
+    /// prgm.execute(|| ' ', |c| outstring.push(c)).unwrap();
+    ///
+    /// prgm.extend_source("+.").unwrap();
+    /// prgm.execute(|| ' ', |c| outstring.push(c)).unwrap();
+    ///
+    /// assert_eq!(outstring, "AB");
+    /// ```
+    pub fn extend_source(&mut self, more: &str) -> Result<()> {
+        let more = Instructions::from_string(more);
+
+        let mut depth = 0usize;
+        for instruction in self.instructions.0.iter().chain(&more.0) {
+            match instruction {
+                Instruct::OpenLoop => depth += 1,
+                Instruct::CloseLoop if depth > 0 => depth -= 1,
+                Instruct::CloseLoop if !self.config.lenient_brackets => {
+                    return Err(BFError {
+                        kind: BFErrorKind::MissingOpen,
+                    })
+                }
+                _ => (),
+            }
+        }
+        if depth > 0 {
+            return Err(BFError {
+                kind: BFErrorKind::MissingClose,
+            });
+        }
+
+        self.has_loops |= more
+            .0
+            .iter()
+            .any(|i| matches!(i, Instruct::OpenLoop | Instruct::CloseLoop));
+        if let Some(profile) = &mut self.profile {
+            profile
+                .instruction_counts
+                .resize(self.instructions.0.len() + more.0.len(), 0);
+        }
+        Arc::make_mut(&mut self.instructions).0.extend(more.0);
+
+        Ok(())
+    }
+
+    /// Execute the entire BF program.
+    ///
+    /// Step-by-step interprets the entire BF program according to its Instructions.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the instructions are invalid. These errors are runtime BF
+    /// errors.
+    pub fn execute<Fin, Fout>(&mut self, mut input: Fin, mut output: Fout) -> Result<()>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        if !self.has_loops && self.profile.is_none() && self.history.is_none() {
+            return self.execute_linear(input, output);
+        }
+
+        loop {
+            if self.done()? {
+                break;
+            }
+            self.step(&mut input, &mut output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute the entire BF program on binary input, collecting its binary output.
+    ///
+    /// Each `,` reads the next byte of `input` straight into the cell, and each `.` adds the cell
+    /// to the output as a byte, so there are no chars involved anywhere. This is the simplest way
+    /// to use a BF program as a byte transformer. Once `input` runs out, reads give 0, the same
+    /// as at the end of any other input.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string(",[+.,]");
+    ///
+    /// assert_eq!(prgm.run_bytes(&[0x10, 0xff, 0x7f]).unwrap(), vec![0x11, 0x00, 0x80]);
+    /// ```
+    pub fn run_bytes(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        self.set_input_len(Some(input.len()));
+        let mut input = input.iter();
+        let mut output = vec![];
+
+        self.execute(
+            || input.next().map_or('\0', |&byte| byte as char),
+            |c| output.push(cell_byte(c)),
+        )?;
+
+        Ok(output)
+    }
+
+    /// Reset and run the program on `input`, checking that it outputs exactly `expected_output`.
+    ///
+    /// Reads past the end of `input` give 0, and the length of `input` is what `?` sees. This
+    /// makes assertions over BF programs one line, with a readable message when they fail.
+    /// There's no step limit, so a program that never finishes never returns.
+    ///
+    /// # Errors
+    ///
+    /// Will return a `Mismatch` if the output is different in any way, or if the program
+    /// stops with a runtime BF error, even if the output up to that point was right.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string(",[+.,]");
+    /// assert!(prgm.expect("abc", "bcd").is_ok());
+    ///
+    /// let mismatch = prgm.expect("abc", "bce").unwrap_err();
+    /// assert_eq!(mismatch.position, 2);
+    /// assert_eq!(mismatch.actual, "bcd");
+    /// ```
+    pub fn expect(
+        &mut self,
+        input: &str,
+        expected_output: &str,
+    ) -> std::result::Result<(), Mismatch> {
+        self.reset();
+        self.set_input_len(Some(input.chars().count()));
+        let mut input = input.chars();
+        let mut actual = String::new();
+
+        let error = self
+            .execute(|| input.next().unwrap_or('\0'), |c| actual.push(c))
+            .err()
+            .map(|e| e.kind);
+
+        if error.is_none() && actual == expected_output {
+            return Ok(());
+        }
+
+        let position = actual
+            .chars()
+            .zip(expected_output.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Err(Mismatch {
+            position,
+            actual,
+            expected: expected_output.to_string(),
+            error,
+        })
+    }
+
+    /// Reset and execute the entire BF program using `buffer` as the tape.
+    ///
+    /// The program's own tape is left empty and `buffer` is used in its place, so the tape is
+    /// never allocated and executing doesn't allocate at all. That's handy on hot paths that
+    /// run a program over and over, or anywhere allocating isn't an option. The buffer is
+    /// zeroed first, and the cells are left in it afterwards. Since it can't grow, it's a fixed
+    /// tape of `buffer.len()` cells, or the `ProgramBuilder::tape_len()` if that's shorter, and
+    /// moving off it follows the `ProgramBuilder::pointer_underflow()` policy like any other
+    /// fixed tape, with `PointerUnderflow::Grow` being an error. Profiling counts aren't
+    /// gathered.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`, including
+    /// `BFErrorKind::CellBoundsError` if the start pointer isn't in `buffer`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{errors::BFErrorKind, Program};
+    /// let mut prgm = Program::from_string("++>+++[<+>-]<.>>");
+    /// let mut tape = [0; 3];
+    /// let mut output = String::new();
+    ///
+    /// prgm.execute_in(&mut tape, || '\0', |c| output.push(c)).unwrap();
+    /// assert_eq!(output, "\u{5}");
+    /// assert_eq!(tape, [5, 0, 0]);
+    ///
+    /// let error = prgm.execute_in(&mut tape[..2], || '\0', |_| ()).unwrap_err();
+    /// assert_eq!(error.kind, BFErrorKind::CellBoundsError);
+    /// ```
+    pub fn execute_in<Fin, Fout>(
+        &mut self,
+        buffer: &mut [u8],
+        mut input: Fin,
+        mut output: Fout,
+    ) -> Result<()>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        let tape_len = self
+            .config
+            .tape_len
+            .map_or(buffer.len(), |len| len.min(buffer.len()));
+        let tape = &mut buffer[..tape_len];
+        tape.fill(0);
+
+        // Like reset(), but without making room on the program's own tape.
+        self.instruction_ptr = 0;
+        self.cells.clear();
+        self.cell_ptr = self.config.start_pointer;
+        self.origin = self.config.start_pointer;
+        self.loop_stack.clear();
+        self.output_len = 0;
+        self.clear_eof_reads();
+
+        if self.cell_ptr >= tape.len() {
+            return Err(BFError {
+                kind: BFErrorKind::CellBoundsError,
+            });
+        }
+
+        while !self.done()? {
+            match self.instructions.0[self.instruction_ptr] {
+                Instruct::MvPtr(n) => self.move_cell_pointer_within(&n, Some(tape.len()))?,
+                Instruct::MvValue(n) => {
+                    let value = tape[self.cell_ptr].wrapping_add_signed(n as i8);
+                    write_cell(tape, self.cell_ptr, value, &mut self.cell_hook);
+                }
+                Instruct::SetValue(n) => write_cell(tape, self.cell_ptr, n, &mut self.cell_hook),
+                Instruct::Move(to) => self.move_cell_in(tape, &[to])?,
+                Instruct::Copy(a, b) => self.move_cell_in(tape, &[a, b])?,
+                Instruct::Input => {
+                    let value = self.read_input(Some(tape), &mut input)?;
+                    write_cell(tape, self.cell_ptr, value, &mut self.cell_hook);
+                }
+                Instruct::InputLen => {
+                    let value = self.input_len_value()?;
+                    write_cell(tape, self.cell_ptr, value, &mut self.cell_hook);
+                }
+                Instruct::Output => self.write_output(tape[self.cell_ptr], &mut output)?,
+                Instruct::OpenLoop => self.open_loop_on(tape[self.cell_ptr])?,
+                Instruct::CloseLoop => self.close_loop()?,
+            }
+
+            self.instruction_ptr += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Execute the rest of a program that has no loops.
+    ///
+    /// Without loops, instructions just run one after another, so this skips everything
+    /// `Program::step()` and `Program::done()` do to handle loops.
+    fn execute_linear<Fin, Fout>(&mut self, mut input: Fin, mut output: Fout) -> Result<()>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        while self.instruction_ptr < self.instructions.0.len() {
+            self.validate_cells_length();
+
+            match self.instructions.0[self.instruction_ptr] {
+                Instruct::MvPtr(n) => self.move_cell_pointer(&n)?,
+                Instruct::MvValue(n) => self.move_cell_value(&n),
+                Instruct::SetValue(n) => self.set_cell(n),
+                Instruct::Move(to) => self.move_cell(&[to])?,
+                Instruct::Copy(a, b) => self.move_cell(&[a, b])?,
+                Instruct::Input => self.input_cell(&mut input)?,
+                Instruct::InputLen => self.input_len_cell()?,
+                Instruct::Output => self.output_cell(&mut output)?,
+                Instruct::OpenLoop | Instruct::CloseLoop => {
+                    unreachable!("Program with loops run through the linear path.")
+                }
+            }
+
+            self.instruction_ptr += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Execute the entire BF program with `,` and `.` doing nothing at all.
+    ///
+    /// This is meant for benchmarking the interpreter itself. Even blank IO closures get called
+    /// for every `,` and `.`, but here no IO happens and there are no closures to call, so only
+    /// the cost of executing the instructions is measured. Input leaves the cell as it is, and
+    /// output doesn't count towards the output limit. Profiling counts aren't gathered either.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`, apart from any to do
+    /// with IO.
+    pub fn execute_no_io(&mut self) -> Result<()> {
+        while !self.done()? {
+            self.validate_cells_length();
+
+            match self.instructions.0[self.instruction_ptr] {
+                Instruct::MvPtr(n) => self.move_cell_pointer(&n)?,
+                Instruct::MvValue(n) => self.move_cell_value(&n),
+                Instruct::SetValue(n) => self.set_cell(n),
+                Instruct::Move(to) => self.move_cell(&[to])?,
+                Instruct::Copy(a, b) => self.move_cell(&[a, b])?,
+                Instruct::Input | Instruct::Output | Instruct::InputLen => (),
+                Instruct::OpenLoop => self.open_loop()?,
+                Instruct::CloseLoop => self.close_loop()?,
+            }
+
+            self.instruction_ptr += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Execute up to `chunk_steps` steps of the BF program, then hand control back.
+    ///
+    /// This is for running a program a bit at a time, such as in a UI event loop or to animate
+    /// the machine a little each frame. Unlike `Program::execute()`, the program isn't reset,
+    /// so each call picks up exactly where the last one left off. Call it again until it
+    /// returns `ExecutionStatus::Finished`.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{ExecutionStatus, Program};
+    /// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+.");
+    /// let mut output = String::new();
+    /// let mut chunks = 1;
+    ///
+    /// while prgm.execute_chunked(10, || ' ', |c| output.push(c)).unwrap()
+    ///     == ExecutionStatus::Running
+    /// {
+    ///     chunks += 1;
+    /// }
+    /// assert_eq!(output, "A");
+    /// assert!(chunks > 1);
+    /// ```
+    pub fn execute_chunked<Fin, Fout>(
+        &mut self,
+        chunk_steps: usize,
+        mut input: Fin,
+        mut output: Fout,
+    ) -> Result<ExecutionStatus>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        for _ in 0..chunk_steps {
+            if self.done()? {
+                return Ok(ExecutionStatus::Finished);
+            }
+            self.step(&mut input, &mut output)?;
+        }
+
+        Ok(if self.done()? {
+            ExecutionStatus::Finished
+        } else {
+            ExecutionStatus::Running
+        })
+    }
+
+    /// Execute the BF program until cell `idx` holds `value`, for at most `max_steps` steps.
+    ///
+    /// This is a data breakpoint: the cell is checked after every step, and execution stops
+    /// right after the step that gave it the value, like when a counter hits 0. Cells past the
+    /// end of the tape count as 0, the same as when they're reached. Like
+    /// `Program::execute_chunked()`, the program isn't reset, so calling this again continues
+    /// from where it stopped. If the cell still holds the value then, it stops again after a
+    /// single step.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{Program, WatchStatus};
+    /// let mut prgm = Program::from_string("+++++[>++<-]");
+    ///
+    /// let status = prgm.run_until_cell(1, 6, 100, || '\0', |_| ()).unwrap();
+    /// assert_eq!(status, WatchStatus::Reached);
+    /// // Stopped on the third time around the loop, before cell 0 is counted down again.
+    /// assert_eq!(prgm.tape(), [3, 6]);
+    ///
+    /// let status = prgm.run_until_cell(0, 0, 100, || '\0', |_| ()).unwrap();
+    /// assert_eq!(status, WatchStatus::Reached);
+    /// assert_eq!(prgm.tape(), [0, 10]);
+    /// ```
+    pub fn run_until_cell<Fin, Fout>(
+        &mut self,
+        idx: usize,
+        value: u8,
+        max_steps: usize,
+        mut input: Fin,
+        mut output: Fout,
+    ) -> Result<WatchStatus>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        for _ in 0..max_steps {
+            if self.done()? {
+                return Ok(WatchStatus::Finished);
+            }
+            self.step(&mut input, &mut output)?;
+
+            if self.cells.get(idx).copied().unwrap_or(0) == value {
+                return Ok(WatchStatus::Reached);
+            }
+        }
+
+        Ok(if self.done()? {
+            WatchStatus::Finished
+        } else {
+            WatchStatus::StepLimit
+        })
+    }
+
+    /// Execute up to `max_steps` steps of the BF program and return the output they produced.
+    ///
+    /// This is `Program::execute_chunked()` with the output collected as bytes, for previewing
+    /// the first bit of a long-running program's output without waiting for it to finish. The
+    /// program isn't reset, so calling this again continues where it stopped and returns the
+    /// output from there on. Since reads can be split across calls, input comes from a
+    /// closure that keeps its place, the same as with `Program::execute()`.
+    ///
+    /// The output comes back even if the program errors partway through, alongside the error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{ExecutionStatus, Program};
+    /// let mut prgm = Program::from_string("+[.+]");
+    ///
+    /// let (output, status) = prgm.run_partial(|| '\0', 20);
+    /// assert_eq!(output, vec![1, 2, 3, 4, 5]);
+    /// assert_eq!(status.unwrap(), ExecutionStatus::Running);
+    ///
+    /// let (output, _) = prgm.run_partial(|| '\0', 8);
+    /// assert_eq!(output, vec![6, 7]);
+    /// ```
+    pub fn run_partial<Fin>(
+        &mut self,
+        input: Fin,
+        max_steps: usize,
+    ) -> (Vec<u8>, Result<ExecutionStatus>)
+    where
+        Fin: FnMut() -> char,
+    {
+        let mut output = vec![];
+        let status = self.execute_chunked(max_steps, input, |c| output.push(cell_byte(c)));
+
+        (output, status)
+    }
+
+    /// Get an iterator that runs the program lazily, giving each byte as it's output.
+    ///
+    /// Nothing runs until the iterator is asked for a byte, and then the program runs just
+    /// until it outputs one, so nothing is buffered. The program isn't reset, so the iterator
+    /// picks up from wherever the program is. A runtime BF error comes out as an `Err` item,
+    /// which is the last one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// // Counts up forever, but only as far as anyone looks.
+    /// let mut prgm = Program::from_string("+[.+]");
+    /// let first: Vec<u8> = prgm.output_iter(|| '\0').take(3).map(Result::unwrap).collect();
+    ///
+    /// assert_eq!(first, vec![1, 2, 3]);
+    /// ```
+    pub fn output_iter<Fin>(&mut self, input: Fin) -> OutputIter<'_, Fin>
+    where
+        Fin: FnMut() -> char,
+    {
+        OutputIter {
+            prgm: self,
+            input,
+            finished: false,
+        }
+    }
+
+    /// Execute the BF program, stopping once `max_steps` steps have been executed.
+    ///
+    /// Works the same as `Program::execute()`, but guards against programs that never finish.
+    /// The budget only counts steps executed during this call, so calling this again continues
+    /// the program with a fresh budget.
+    ///
+    /// Skipping over a loop whose cell is 0 means scanning ahead for the matching `]`, which
+    /// can be a lot of work for a single step. So that the budget really bounds the work done,
+    /// every instruction scanned over counts as a step too.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::StepLimitExceeded` if the program hasn't
+    /// finished after `max_steps` steps, as well as any runtime BF errors.
+    pub fn execute_limited<Fin, Fout>(
+        &mut self,
+        max_steps: usize,
+        mut input: Fin,
+        mut output: Fout,
+    ) -> Result<()>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        self.step_budget = Some(max_steps);
+
+        let result = loop {
+            match self.done() {
+                Ok(true) => break Ok(()),
+                Ok(false) => (),
+                Err(e) => break Err(e),
+            }
+
+            if let Err(e) = self
+                .spend_steps(1)
+                .and_then(|()| self.step(&mut input, &mut output))
+            {
+                break Err(e);
+            }
+        };
+
+        self.step_budget = None;
+        result
+    }
+
+    /// Execute the BF program, stopping once it has run for longer than `timeout`.
+    ///
+    /// Works the same as `Program::execute()`, but bounds how long a program gets by wall-clock
+    /// time rather than by steps, which is handier for things like user-facing services. The
+    /// clock is only checked every few thousand steps to keep the overhead low, so the program
+    /// can run a little past the timeout, and even a zero timeout lets a short program finish.
+    /// A program blocked waiting for input isn't stopped until the input comes. Like `Program::execute_limited()`, the timeout only counts time
+    /// spent in this call.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::Timeout` if the program hasn't finished
+    /// within `timeout`, as well as any runtime BF errors.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{errors::BFErrorKind, Program};
+    /// use std::time::Duration;
+    ///
+    /// let mut prgm = Program::from_string("+[]");
+    /// let error = prgm
+    ///     .execute_with_timeout(Duration::from_millis(10), || ' ', |_| ())
+    ///     .unwrap_err();
+    ///
+    /// assert_eq!(error.kind, BFErrorKind::Timeout);
+    /// ```
+    pub fn execute_with_timeout<Fin, Fout>(
+        &mut self,
+        timeout: std::time::Duration,
+        mut input: Fin,
+        mut output: Fout,
+    ) -> Result<()>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        /// Steps between checks of the clock.
+        const CHECK_INTERVAL: usize = 4096;
+
+        let start = std::time::Instant::now();
+
+        for steps in 1.. {
+            if self.done()? {
+                break;
+            }
+            if steps % CHECK_INTERVAL == 0 && start.elapsed() > timeout {
+                return Err(BFError {
+                    kind: BFErrorKind::Timeout,
+                });
+            }
+
+            self.step(&mut input, &mut output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Take `steps` steps out of the budget, if there is one.
+    fn spend_steps(&mut self, steps: usize) -> Result<()> {
+        if let Some(budget) = self.step_budget.as_mut() {
+            *budget = budget.checked_sub(steps).ok_or(BFError {
+                kind: BFErrorKind::StepLimitExceeded,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Check if the program halts within `max_steps` steps when run with blank IO.
+    ///
+    /// The program is reset first. This is a quick way to screen for programs that obviously
+    /// never finish, even with trivial input.
+    ///
+    /// # Errors
+    ///
+    /// Hitting the step limit is not an error and gives `Ok(false)`, but any other runtime BF
+    /// error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// assert!(Program::from_string("+++[-]").halts_within(100).unwrap());
+    /// assert!(!Program::from_string("+[]").halts_within(100).unwrap());
+    /// ```
+    pub fn halts_within(&mut self, max_steps: usize) -> Result<bool> {
+        let input = BasicInput::new();
+        let output = BasicOutput::new();
+
+        self.reset();
+        match self.execute_limited(max_steps, || input.blank(), |c| output.blank(c)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind == BFErrorKind::StepLimitExceeded => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get everything the program outputs when run with blank input for at most `max_steps`
+    /// steps.
+    ///
+    /// The program is reset first, and every read gives 0. This is handy for characterizing
+    /// programs that just print something, or for generating the expected output of a test.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::StepLimitExceeded` if the program hasn't
+    /// finished after `max_steps` steps, as well as any other runtime BF errors. Either way, the
+    /// output so far is lost.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+.+.");
+    ///
+    /// assert_eq!(prgm.output_for_blank_input(1000).unwrap(), b"AB");
+    /// ```
+    pub fn output_for_blank_input(&mut self, max_steps: usize) -> Result<Vec<u8>> {
+        let mut output = vec![];
+
+        self.reset();
+        self.execute_limited(max_steps, || '\0', |c| output.push(cell_byte(c)))?;
+
+        Ok(output)
+    }
+
+    /// Count how many instructions the program executes on `input`, up to `max_steps`.
+    ///
+    /// The program is reset first, each `,` reads the next byte of `input`, or 0 once it runs
+    /// out, and output is thrown away. Every executed instruction counts as one step, including
+    /// a `[` that skips its loop, however far it has to skip, so the count only depends on the
+    /// program and its input. That makes it a precise, repeatable measure of how much work a
+    /// program does, e.g. for comparing different programs for the same task, unlike timing
+    /// it. Optimized instructions do more per step, so they count fewer.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::StepLimitExceeded` if the program hasn't
+    /// finished after `max_steps` steps, as well as any other runtime BF errors.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string(",[-]");
+    ///
+    /// // A read, then the 3 instructions of the loop each time around, then the last `[`.
+    /// assert_eq!(prgm.count_steps(&[2], 1000).unwrap(), 8);
+    /// assert_eq!(prgm.count_steps(&[], 1000).unwrap(), 2);
+    /// ```
+    pub fn count_steps(&mut self, input: &[u8], max_steps: usize) -> Result<usize> {
+        let (steps, _) = self.run_stepwise(input, Some(max_steps), |_, _, _| None::<()>)?;
+        Ok(steps)
+    }
+
+    /// Run the program on binary input, counting how many times its outermost loop runs, along
+    /// with collecting its binary output.
+    ///
+    /// The program is reset first, and input and output work the same as in
+    /// `Program::run_bytes()`. The count goes up each time the body of a loop that isn't inside
+    /// any other loop starts, so a `[` that skips its loop doesn't count, and nested loops
+    /// never count. For a program with one big driver loop, that's how many times it went
+    /// around. If there are several top-level loops, their counts are added together. Loops
+    /// that optimizing has replaced with single instructions, like `[-]`, aren't loops anymore,
+    /// so they don't count either.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string(",[>+.>++[-]<<-]");
+    ///
+    /// assert_eq!(prgm.run_counting_outer_loop(&[3]).unwrap(), (3, vec![1, 2, 3]));
+    /// ```
+    pub fn run_counting_outer_loop(&mut self, input: &[u8]) -> Result<(u64, Vec<u8>)> {
+        let mut output = vec![];
+        let mut iterations = 0;
+        // Whether the step about to run starts outside every loop.
+        let mut outside_loops = true;
+
+        self.run_stepwise(input, None, |prgm, _, byte| {
+            output.extend(byte);
+            let inside_loops = !prgm.loop_stack.is_empty();
+            if outside_loops && inside_loops {
+                iterations += 1;
+            }
+            outside_loops = !inside_loops;
+            None::<()>
+        })?;
+
+        Ok((iterations, output))
+    }
+
+    /// Check that the program never uses more than `budget` cells, by running it with blank
+    /// input for up to `max_steps` steps.
+    ///
+    /// The program is reset first, every `,` reads 0, and output is thrown away. Each cell up
+    /// to the furthest the cell pointer reaches counts as used, the same as the tape growing
+    /// to reach it. This is meant for holding programs to the budget they declare with a
+    /// `@tape` header, see `rbf::header_field()`, e.g. in CI over a collection of programs.
+    ///
+    /// Returns None if the program finished within its budget, or where it first went over.
+    /// Steps are counted the same way as in `Program::count_steps()`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::StepLimitExceeded` if the program hasn't
+    /// finished after `max_steps` steps without going over, as well as any other runtime BF
+    /// errors.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let src = "@tape 2\n+>+>+";
+    /// let budget = rbf::header_field(src, "tape").unwrap().parse().unwrap();
+    ///
+    /// let exceeded = Program::from_string(src)
+    ///     .check_tape_budget(budget, 1000)
+    ///     .unwrap()
+    ///     .unwrap();
+    /// assert_eq!((exceeded.step, exceeded.cell), (4, 2));
+    /// ```
+    pub fn check_tape_budget(
+        &mut self,
+        budget: usize,
+        max_steps: usize,
+    ) -> Result<Option<BudgetExceeded>> {
+        let (_, exceeded) = self.run_stepwise(&[], Some(max_steps), |prgm, steps, _| {
+            (prgm.cells.len().max(prgm.cell_ptr + 1) > budget).then_some(BudgetExceeded {
+                budget,
+                step: steps,
+                cell: prgm.cell_ptr,
+            })
+        })?;
+        Ok(exceeded)
+    }
+
+    /// Check that the program never uses more cells than its source `src` declares with a
+    /// `@tape` header, by running it with blank input for up to `max_steps` steps.
+    ///
+    /// This is `Program::check_tape_budget()` with the budget read from the header by
+    /// `rbf::header_field()`. A program without a `@tape` header hasn't promised anything, so
+    /// it always passes.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::InvalidFormat` if the `@tape` header isn't
+    /// a number of cells, as well as the same errors as `Program::check_tape_budget()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let src = "@tape 2\n+>+>+";
+    ///
+    /// let exceeded = Program::from_string(src)
+    ///     .check_declared_tape_budget(src, 1000)
+    ///     .unwrap()
+    ///     .unwrap();
+    /// assert_eq!((exceeded.step, exceeded.cell), (4, 2));
+    /// ```
+    pub fn check_declared_tape_budget(
+        &mut self,
+        src: &str,
+        max_steps: usize,
+    ) -> Result<Option<BudgetExceeded>> {
+        let Some(budget) = header_field(src, "tape") else {
+            return Ok(None);
+        };
+        let budget = budget.parse().map_err(|_| BFError {
+            kind: BFErrorKind::InvalidFormat,
+        })?;
+
+        self.check_tape_budget(budget, max_steps)
+    }
+
+    /// Reset the program and run it on binary `input`, for up to `max_steps` steps if limited,
+    /// calling `on_step` after every step.
+    ///
+    /// Input works the same as in `Program::run_bytes()`. `on_step` gets the program, how many
+    /// steps have run so far, and the byte the step output, if any. Returns how many steps ran,
+    /// along with what `on_step` returned if it returned Some, which stops the program there.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::StepLimitExceeded` if the program hasn't
+    /// finished or been stopped after `max_steps` steps, as well as any other runtime BF
+    /// errors.
+    fn run_stepwise<T, F>(
+        &mut self,
+        input: &[u8],
+        max_steps: Option<usize>,
+        mut on_step: F,
+    ) -> Result<(usize, Option<T>)>
+    where
+        F: FnMut(&Program, usize, Option<u8>) -> Option<T>,
+    {
+        self.reset();
+        self.set_input_len(Some(input.len()));
+        let mut input = input.iter();
+
+        let mut steps = 0;
+        while !self.done()? {
+            if max_steps == Some(steps) {
+                return Err(BFError {
+                    kind: BFErrorKind::StepLimitExceeded,
+                });
+            }
+
+            let mut output = None;
+            self.step(
+                || input.next().map_or('\0', |&byte| byte as char),
+                |c| output = Some(cell_byte(c)),
+            )?;
+            steps += 1;
+
+            if let Some(stopped) = on_step(self, steps, output) {
+                return Ok((steps, Some(stopped)));
+            }
+        }
+
+        Ok((steps, None))
+    }
+
+    /// Enable profiling, starting from zeroed counts.
+    ///
+    /// While profiling is enabled, every executed instruction and every run of a loop body is
+    /// counted, and the range of cells the cell pointer visits is tracked. Counts are kept
+    /// across `Program::reset()` so that several runs with different input can be profiled
+    /// together. Calling this again starts over from zero.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile {
+            instruction_counts: vec![0; self.instructions.0.len()],
+            loop_iterations: BTreeMap::new(),
+            cell_range: (self.cell_ptr, self.cell_ptr),
+        });
+    }
+
+    /// Call `callback` with the index and new value of a cell every time a cell changes.
+    ///
+    /// This is meant for things like live tape visualizations, which can then redraw only the
+    /// cells that changed instead of the whole tape every step. The callback only fires when a
+    /// value actually changes, so e.g. reading the same value a cell already holds doesn't call
+    /// it. Setting a new callback replaces the old one. Programs without a callback pay nothing
+    /// extra.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let changes = Arc::new(Mutex::new(vec![]));
+    /// let recorded = Arc::clone(&changes);
+    ///
+    /// let mut prgm = Program::from_string("++>-");
+    /// prgm.on_cell_change(move |idx, value| recorded.lock().unwrap().push((idx, value)));
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    ///
+    /// assert_eq!(*changes.lock().unwrap(), vec![(0, 1), (0, 2), (1, 255)]);
+    /// ```
+    pub fn on_cell_change<F>(&mut self, callback: F)
+    where
+        F: FnMut(usize, u8) + Send + 'static,
+    {
+        self.cell_hook = Some(CellHook(Box::new(callback)));
+    }
+
+    /// Call `callback` with the new length of the tape every time the tape grows.
+    ///
+    /// The tape starts out empty and grows as the program uses cells further along than any
+    /// it has used before, so this tracks how much memory a program takes as it runs rather than just
+    /// how much it took by the end. It also fires when the tape grows to the left, with
+    /// `PointerUnderflow::Grow`. A tape with a fixed length from `ProgramBuilder::tape_len()`
+    /// still grows as it's used, up to that length, but the buffer given to
+    /// `Program::execute_in()` never grows. Setting a new callback replaces the old one.
+    /// Programs without a callback pay nothing extra.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let lengths = Arc::new(Mutex::new(vec![]));
+    /// let recorded = Arc::clone(&lengths);
+    ///
+    /// let mut prgm = Program::from_string("+>><>>+");
+    /// prgm.on_tape_growth(move |len| recorded.lock().unwrap().push(len));
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    ///
+    /// assert_eq!(*lengths.lock().unwrap(), vec![1, 2, 3, 4]);
+    /// ```
+    pub fn on_tape_growth<F>(&mut self, callback: F)
+    where
+        F: FnMut(usize) + Send + 'static,
+    {
+        self.growth_hook = Some(GrowthHook(Box::new(callback)));
+    }
+
+    /// Get the profiling results, or None if profiling isn't enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Get which instructions have been executed since profiling was enabled.
+    ///
+    /// The returned Vec is indexed the same as the instructions and is `true` for each
+    /// instruction that ran at least once. Any `false` entries are dead code under the input the
+    /// program was run with. Returns None if profiling isn't enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("[-]+");
+    /// prgm.enable_profiling();
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    ///
+    /// // The loop is skipped since the cell starts at 0, so the loop body never runs.
+    /// assert_eq!(prgm.coverage(), Some(vec![true, false, false, true]));
+    /// ```
+    pub fn coverage(&self) -> Option<Vec<bool>> {
+        self.profile
+            .as_ref()
+            .map(|p| p.instruction_counts.iter().map(|&n| n > 0).collect())
+    }
+
+    /// Summarize the profiling results as the `limit` hottest instructions and loops, or None
+    /// if profiling isn't enabled.
+    ///
+    /// There's a section for instructions, listing each one's execution count, index and BF
+    /// code, and a section for loops, listing how many times each body ran, the indices of its
+    /// brackets and its BF code, cut short if it's long. Both are sorted hottest first, with
+    /// ties in program order, and leave out anything that never ran. Optimized instructions
+    /// show their optimized form, e.g. `[-]` for a cell being set to 0.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Program;
+    /// let mut prgm = Program::from_string("++[>+++[-]<-]");
+    /// prgm.enable_profiling();
+    /// prgm.execute(|| ' ', |_| ()).unwrap();
+    ///
+    /// let report = prgm.profile_report(2).unwrap();
+    /// assert!(report.starts_with("hottest instructions:\n           8       7  [\n"));
+    /// assert!(report.ends_with("           6   7-9     [-]\n           2   2-12    [>+++[-]<-]\n"));
+    /// ```
+    pub fn profile_report(&self, limit: usize) -> Option<String> {
+        /// Longest the code of a loop gets before it's cut short.
+        const MAX_CODE_LEN: usize = 40;
+
+        let profile = self.profile.as_ref()?;
+        let code = |range: std::ops::Range<usize>| {
+            let code = Instructions(self.instructions.0[range].to_vec(), None).to_string();
+            match code.char_indices().nth(MAX_CODE_LEN) {
+                Some((cut, _)) => format!("{}...", &code[..cut]),
+                None => code,
+            }
+        };
+
+        let mut instructions: Vec<(usize, u64)> = profile
+            .instruction_counts
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        instructions.sort_by_key(|&(idx, count)| (std::cmp::Reverse(count), idx));
+
+        let mut loops: Vec<(usize, u64)> = profile
+            .loop_iterations
+            .iter()
+            .map(|(&open, &iterations)| (open, iterations))
+            .collect();
+        loops.sort_by_key(|&(open, iterations)| (std::cmp::Reverse(iterations), open));
+
+        let mut report = String::from("hottest instructions:\n");
+        for &(idx, count) in instructions.iter().take(limit) {
+            report += &format!("{:>12}  {:>6}  {}\n", count, idx, code(idx..idx + 1));
+        }
+        report += "hottest loops:\n";
+        for &(open, iterations) in loops.iter().take(limit) {
+            let close = self.instructions.matching_bracket(open).unwrap_or(open);
+            let span = format!("{}-{}", open, close);
+            report += &format!(
+                "{:>12}   {:<8}{}\n",
+                iterations,
+                span,
+                code(open..close + 1)
+            );
+        }
+
+        Some(report)
+    }
+
+    /// Execute the next step in the BF program.
+    pub fn step<Fin, Fout>(&mut self, input: Fin, output: Fout) -> Result<()>
+    where
+        Fin: FnOnce() -> char,
+        Fout: FnOnce(char),
+    {
+        self.start_history_step();
+        let result = self.run_step(input, output);
+        self.finish_history_step(result.is_ok());
+        result
+    }
+
+    /// Do the work of `Program::step()`, apart from recording history.
+    fn run_step<Fin, Fout>(&mut self, input: Fin, output: Fout) -> Result<()>
+    where
+        Fin: FnOnce() -> char,
+        Fout: FnOnce(char),
+    {
+        // Make sure cells length is good so any possible operations we do work.
+        self.validate_cells_length();
+
+        if let Some(profile) = self.profile.as_mut() {
+            if let Some(count) = profile.instruction_counts.get_mut(self.instruction_ptr) {
+                *count += 1;
+            }
+            profile.visit_cell(self.cell_ptr);
+        }
+
+        let instruction = match self.instructions.0.get(self.instruction_ptr) {
+            Some(i) => i,
+            None => {
+                return Err(BFError {
+                    kind: BFErrorKind::InstructionBoundsError,
+                })
+            }
+        };
+
+        // println!( // Dirty debugging
+        //     "i: {:?}; iptr: {}; cptr: {}; cv: {};",
+        //     instruction, self.instruction_ptr, self.cell_ptr, self.cells[self.cell_ptr]
+        // );
+
+        match *instruction {
+            Instruct::MvPtr(n) => {
+                self.move_cell_pointer(&n)?;
+                if let Some(profile) = self.profile.as_mut() {
+                    profile.visit_cell(self.cell_ptr);
+                }
+            }
+            Instruct::MvValue(n) => self.move_cell_value(&n),
+            Instruct::SetValue(n) => self.set_cell(n),
+            Instruct::Move(to) => self.move_cell(&[to])?,
+            Instruct::Copy(a, b) => self.move_cell(&[a, b])?,
+            Instruct::Input => self.input_cell(input)?,
+            Instruct::InputLen => self.input_len_cell()?,
+            Instruct::Output => self.output_cell(output)?,
+            Instruct::OpenLoop => self.open_loop()?,
+            Instruct::CloseLoop => self.close_loop()?,
+        }
+
+        self.instruction_ptr += 1;
+
+        Ok(())
+    }
+
+    /// Check if the program has finished executing.
+    pub fn done(&self) -> Result<bool> {
+        if self.instruction_ptr >= self.instructions.0.len() {
+            if !self.loop_stack.is_empty() {
+                Err(BFError {
+                    kind: BFErrorKind::MissingClose,
+                })
+            } else {
+                Ok(true)
+            }
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Move the cell pointer either right or left. BF instructions ">" and "<" respectively.
+    ///
+    /// Note that it takes an amount. If there are repeating ">" or "<" instructions, rather
+    /// than move multiple times in a row, it can be optimized and moved only once, x spaces.
+    fn move_cell_pointer(&mut self, amount: &isize) -> Result<()> {
+        self.move_cell_pointer_within(amount, self.config.tape_len)
+    }
+
+    /// Move the cell pointer on a tape of `tape_len` cells, or an unbounded one if None.
+    fn move_cell_pointer_within(&mut self, amount: &isize, tape_len: Option<usize>) -> Result<()> {
+        self.cell_ptr = self.offset_cell(*amount, tape_len)?;
+        Ok(())
+    }
+
+    /// Get the index of the cell `offset` from the cell pointer on a tape of `tape_len` cells,
+    /// or an unbounded one if None, without moving the pointer there.
+    ///
+    /// The cell is found the same way as moving the pointer there would, with the same checks
+    /// and underflow policy. If the tape has to grow to the left to reach it, it's grown here,
+    /// and the cell pointer moves along with the cell it's on.
+    fn offset_cell(&mut self, offset: isize, tape_len: Option<usize>) -> Result<usize> {
+        let out_of_bounds = || BFError {
+            kind: BFErrorKind::CellBoundsError,
+        };
+
+        if self.config.strict_origin && (self.cell_ptr as isize + offset) < self.origin as isize {
+            return Err(out_of_bounds());
+        }
+
+        let cell = match self.cell_ptr.checked_add_signed(offset) {
+            Some(val) => val,
+            None => match (self.config.pointer_underflow, tape_len) {
+                (PointerUnderflow::Clamp, _) => 0,
+                (PointerUnderflow::Wrap, Some(len)) => {
+                    (self.cell_ptr as isize + offset).rem_euclid(len as isize) as usize
+                }
+                (PointerUnderflow::Grow, None) => {
+                    self.grow_left(offset.unsigned_abs() - self.cell_ptr);
+                    0
+                }
+                _ => return Err(out_of_bounds()),
+            },
+        };
+
+        if tape_len.is_some_and(|len| cell >= len) {
+            return Err(out_of_bounds());
+        }
+
+        Ok(cell)
+    }
+
+    /// Add `cells` zeroed cells to the left end of the tape, moving everything that refers to a
+    /// cell index along with it.
+    fn grow_left(&mut self, cells: usize) {
+        self.record_cell_change(CellUndo::GrowLeft(cells));
+        self.cells.splice(0..0, std::iter::repeat_n(0, cells));
+        self.cell_ptr += cells;
+        self.origin += cells;
+        if let Some(profile) = self.profile.as_mut() {
+            profile.cell_range.0 += cells;
+            profile.cell_range.1 += cells;
+        }
+        if let Some(hook) = self.growth_hook.as_mut() {
+            (hook.0)(self.cells.len());
+        }
+    }
+
+    /// Check the cells length and make sure it's long enough such that cell_ptr is a valid index.
+    fn validate_cells_length(&mut self) {
+        self.validate_cell(self.cell_ptr);
+    }
+
+    /// Make sure the cells are long enough for `cell` to be a valid index.
+    fn validate_cell(&mut self, cell: usize) {
+        if self.cells.len() <= cell {
+            self.cells.resize(cell + 1, 0);
+            if let Some(hook) = self.growth_hook.as_mut() {
+                (hook.0)(self.cells.len());
+            }
+        }
+    }
+
+    /// Increment/decrement current cell value by `amount`.
+    ///
+    /// Multiple subsequent calls to this can be replaced by a single call with the sum in
+    /// order to optimize.
+    fn move_cell_value(&mut self, amount: &isize) {
+        self.set_cell(self.cells[self.cell_ptr].wrapping_add_signed(*amount as i8));
+    }
+
+    /// Add the current cell onto the cells at each of `offsets` from it, then clear it.
+    fn move_cell(&mut self, offsets: &[isize]) -> Result<()> {
+        let value = self.cells[self.cell_ptr];
+        // The loop this replaced doesn't even run on a 0 cell, so nothing else gets touched.
+        if value == 0 {
+            return Ok(());
+        }
+
+        // Each target is found on its own rather than by moving there and back, since moving
+        // back doesn't undo a move that was clamped.
+        for &offset in offsets {
+            let target = self.offset_cell(offset, self.config.tape_len)?;
+            self.validate_cell(target);
+            let moved = self.cells[target].wrapping_add(value);
+            self.write_own_cell(target, moved);
+        }
+        self.set_cell(0);
+
+        Ok(())
+    }
+
+    /// Do what `Program::move_cell()` does, but on `tape` instead of the program's own tape.
+    fn move_cell_in(&mut self, tape: &mut [u8], offsets: &[isize]) -> Result<()> {
+        let value = tape[self.cell_ptr];
+        if value == 0 {
+            return Ok(());
+        }
+
+        for &offset in offsets {
+            let target = self.offset_cell(offset, Some(tape.len()))?;
+            let moved = tape[target].wrapping_add(value);
+            write_cell(tape, target, moved, &mut self.cell_hook);
+        }
+        write_cell(tape, self.cell_ptr, 0, &mut self.cell_hook);
+
+        Ok(())
+    }
+
+    /// Set the current cell to `value`, letting the cell change callback know if it changed.
+    fn set_cell(&mut self, value: u8) {
+        self.write_own_cell(self.cell_ptr, value);
+    }
+
+    /// Set the program's own cell at `idx` to `value`, recording the change for history and
+    /// letting the cell change callback know if it changed.
+    fn write_own_cell(&mut self, idx: usize, value: u8) {
+        self.record_cell_change(CellUndo::Write(idx, self.cells[idx]));
+        write_cell(&mut self.cells, idx, value, &mut self.cell_hook);
+    }
+
+    /// Set the current cell to how many bytes of input are left, as far as a cell can hold.
+    fn input_len_cell(&mut self) -> Result<()> {
+        let value = self.input_len_value()?;
+        self.set_cell(value);
+        Ok(())
+    }
+
+    /// Get how many bytes of input are left, as far as a cell can hold.
+    fn input_len_value(&self) -> Result<u8> {
+        let len = self.input_len.ok_or(BFError {
+            kind: BFErrorKind::UnknownInputLength,
+        })?;
+        Ok(len.min(255) as u8)
+    }
+
+    /// Using the input closure, retrieve a character into the cells at cell ptr.
+    fn input_cell<F>(&mut self, input: F) -> Result<()>
+    where
+        F: FnOnce() -> char,
+    {
+        let value = self.read_input(None, input)?;
+        self.set_cell(value);
+        Ok(())
+    }
+
+    /// Read a byte of input from the input closure, checking it fits in a cell.
+    ///
+    /// `tape` is the tape the program is running on, if it's not the program's own cells.
+    fn read_input<F>(&mut self, tape: Option<&[u8]>, input: F) -> Result<u8>
+    where
+        F: FnOnce() -> char,
+    {
+        let in_char = input();
+        let in_byte = in_char as u32;
+
+        if let Some(len) = self.input_len.as_mut() {
+            *len = len.saturating_sub(1);
+        }
+
+        if in_byte == 0 {
+            if let Some(max) = self.config.max_eof_reads {
+                let state = self.state_hash_with(tape.unwrap_or(&self.cells));
+                if !self.eof_states.insert(state) {
+                    self.eof_reads += 1;
+                }
+                if self.eof_reads > max {
+                    return Err(BFError {
+                        kind: BFErrorKind::InputExhausted,
+                    });
+                }
+            }
+        } else {
+            self.clear_eof_reads();
+        }
+
+        // Gotta check to make sure it's only 8 bit int
+        if in_byte < 256 {
+            Ok(in_byte as u8)
+        } else {
+            Err(BFError {
+                kind: BFErrorKind::InvalidInput,
+            })
+        }
+    }
+
+    /// Start counting reads of 0 without progress over, for `ProgramBuilder::max_eof_reads()`.
+    fn clear_eof_reads(&mut self) {
+        self.eof_reads = 0;
+        self.eof_states.clear();
+    }
+
+    /// Output a character at current cell into the output closure.
+    fn output_cell<F>(&mut self, output: F) -> Result<()>
+    where
+        F: FnOnce(char),
+    {
+        self.write_output(self.cells[self.cell_ptr], output)
+    }
+
+    /// Output `value` into the output closure, as long as it's allowed out.
+    fn write_output<F>(&mut self, value: u8, output: F) -> Result<()>
+    where
+        F: FnOnce(char),
+    {
+        if self.config.max_output == Some(self.output_len) {
+            return Err(BFError {
+                kind: BFErrorKind::OutputLimitExceeded,
+            });
+        }
+
+        if self.config.strict_output && !value.is_ascii() {
+            return Err(BFError {
+                kind: BFErrorKind::InvalidOutput,
+            });
+        }
+
+        self.output_len += 1;
+        self.clear_eof_reads();
+        output(value as char);
+        Ok(())
+    }
+
+    /// Handle the open loop instructions, `[`.
+    fn open_loop(&mut self) -> Result<()> {
+        self.open_loop_on(self.cells[self.cell_ptr])
+    }
+
+    /// Handle a `[` with `value` in the current cell.
+    fn open_loop_on(&mut self, value: u8) -> Result<()> {
+        if value > 0 {
+            self.loop_stack.push(self.instruction_ptr);
+        } else {
+            self.move_to_closed_loop()?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle the close loop instruction, ']'.
+    fn close_loop(&mut self) -> Result<()> {
+        self.instruction_ptr = match self.loop_stack.pop() {
+            Some(n) => {
+                // Every run of a loop body ends by jumping back from here.
+                if let Some(profile) = self.profile.as_mut() {
+                    *profile.loop_iterations.entry(n).or_insert(0) += 1;
+                }
+                n
+            }
+            None if self.config.lenient_brackets => return Ok(()),
+            None => {
+                return Err(BFError {
+                    kind: BFErrorKind::MissingOpen,
+                });
+            }
+        } - 1;
+        Ok(())
+    }
+
+    /// Find the associated close loop to our current open loop and go there.
+    fn move_to_closed_loop(&mut self) -> Result<()> {
+        // How many loops inside this one we're in, which is all that's needed to find its end.
+        let mut depth = 0usize;
+        let mut current_instruction = self.instruction_ptr + 1; // We don't want to add
+                                                                // current open loop to stack
+        loop {
+            // Scanning is bounded by the step budget just like executing is.
+            self.spend_steps(1)?;
+
+            // Only the instructions between the brackets count towards the limit.
+            if let Some(max) = self.config.max_skip {
+                if current_instruction - self.instruction_ptr - 1 > max {
+                    return Err(BFError {
+                        kind: BFErrorKind::SkipLimitExceeded,
+                    });
+                }
+            }
+
+            let instruction = match self.instructions.0.get(current_instruction) {
+                Some(i) => i,
+                None => {
+                    return Err(BFError {
+                        kind: BFErrorKind::MissingClose,
+                    });
+                }
+            };
+
+            match instruction {
+                Instruct::OpenLoop => depth += 1,
+                Instruct::CloseLoop if depth == 0 => {
+                    self.instruction_ptr = current_instruction;
+                    return Ok(());
+                }
+                Instruct::CloseLoop => depth -= 1,
+                _ => (),
+            }
+
+            current_instruction += 1;
+        }
+    }
+}
+
+/// Get the cell value an output char was made from.
+///
+/// Output chars always come from a single cell, so they fit in a byte.
+fn cell_byte(c: char) -> u8 {
+    c as u32 as u8
+}
+
+/// Set `cells[idx]` to `value`, letting the cell change callback know if it changed.
+///
+/// This doesn't take the whole program, so it works on tapes the program doesn't own too.
+fn write_cell(cells: &mut [u8], idx: usize, value: u8, hook: &mut Option<CellHook>) {
+    let cell = &mut cells[idx];
+
+    if let Some(hook) = hook.as_mut() {
+        if *cell != value {
+            (hook.0)(idx, value);
+        }
+    }
+    *cell = value;
+}
+
+/// Basic input operation for BF.
+///
+/// Provides a method that can be used for the input of the BF program.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::*;
+/// let basic_input = BasicInput::new();
+///
+/// // Read single char from terminal.
+/// let c = basic_input.input_char();
+/// ```
+pub struct BasicInput {
+    source: InputSource,
+    /// Printed to stderr before each terminal read, if set.
+    prompt: Option<String>,
+}
+
+/// Where a `BasicInput` reads its chars from.
+enum InputSource {
+    /// Read from the terminal.
+    Terminal(Term),
+    /// Read from a preset buffer of chars, `position` being the next one to read.
+    Preset {
+        chars: Vec<char>,
+        position: Cell<usize>,
+    },
+    /// Read from a preset buffer of chars, starting over from the first once they run out.
+    Cycling {
+        chars: Vec<char>,
+        position: Cell<usize>,
+    },
+    /// Generate pseudo-random bytes, holding the state of the generator.
+    Random(Cell<u64>),
+    /// Read from each of `sources` in turn, `current` being the one still being read.
+    Chain {
+        sources: Vec<BasicInput>,
+        current: Cell<usize>,
+    },
+}
+
+/// Step a xorshift64 pseudo-random number generator, returning the next state.
+///
+/// Not anywhere near good enough for anything cryptographic, but plenty for reproducible
+/// random input. The state must be nonzero.
+fn xorshift(mut state: u64) -> u64 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+/// Basic output operation for BF.
+///
+/// Provides a method for output of the BF program.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::*;
+/// let mut basic_output = BasicOutput::new();
+///
+/// // Output single char to terminal.
+/// basic_output.print_char('a');
+/// ```
+pub struct BasicOutput {
+    stdout: std::io::Stdout,
+    newlines: NewlineMode,
+    nulls: NullMode,
+    cells: CellFormat,
+    radix: Radix,
+    flushes: FlushMode,
+    /// Output held back until the next flush, with `FlushMode::AtEnd`.
+    buffer: String,
+}
+
+/// How newlines in program output get translated before being written.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::NewlineMode;
+/// let mut output = String::new();
+/// for c in "a\nb".chars() {
+///     NewlineMode::Crlf.translate(c, |c| output.push(c));
+/// }
+///
+/// assert_eq!(output, "a\r\nb");
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum NewlineMode {
+    /// Output exactly what the program outputs.
+    #[default]
+    Raw,
+    /// Output each `\n` as `\r\n`, for terminals that expect it.
+    Crlf,
+}
+
+impl NewlineMode {
+    /// Pass `c` on to `output`, translated according to the mode.
+    pub fn translate<F>(self, c: char, mut output: F)
+    where
+        F: FnMut(char),
+    {
+        if self == NewlineMode::Crlf && c == '\n' {
+            output('\r');
+        }
+        output(c);
+    }
+}
+
+/// How null bytes in program output get written, since terminals usually don't show them.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::NullMode;
+/// let mut output = String::new();
+/// for c in "a\0b".chars() {
+///     NullMode::Replace('.').translate(c, |c| output.push(c));
+/// }
+///
+/// assert_eq!(output, "a.b");
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum NullMode {
+    /// Output null bytes as they are.
+    #[default]
+    Raw,
+    /// Leave null bytes out entirely.
+    Skip,
+    /// Output each null byte as the given char instead, like `␀`, so it can be seen.
+    Replace(char),
+}
+
+impl NullMode {
+    /// Pass `c` on to `output`, translated according to the mode.
+    pub fn translate<F>(self, c: char, mut output: F)
+    where
+        F: FnMut(char),
+    {
+        match self {
+            NullMode::Skip if c == '\0' => {}
+            NullMode::Replace(placeholder) if c == '\0' => output(placeholder),
+            _ => output(c),
+        }
+    }
+}
+
+/// How each output cell gets written, as a char or as a number.
+///
+/// Cells are always stored as bytes from 0 to 255, but a program doing arithmetic might think
+/// of them as signed, from -128 to 127, where 255 is -1. The numeric formats write the value
+/// the way the program thinks of it, rather than as the char with that code point. Each number
+/// is followed by a newline, and is in decimal unless another `Radix` is given. Loops work the
+/// same either way, since a cell is 0 exactly when it's 0 as a signed number too.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::CellFormat;
+/// let mut output = String::new();
+/// for c in ['\u{5}', '\u{ff}'] {
+///     CellFormat::Signed.format(c, |c| output.push(c));
+/// }
+///
+/// assert_eq!(output, "5\n-1\n");
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum CellFormat {
+    /// Write the char with the cell's value as its code point.
+    #[default]
+    Char,
+    /// Write the cell's value as an unsigned number, from 0 to 255.
+    Unsigned,
+    /// Write the cell's value as a signed number, from -128 to 127.
+    Signed,
+}
+
+impl CellFormat {
+    /// Pass the chars for writing output char `c` on to `output`, according to the format.
+    pub fn format<F>(self, c: char, output: F)
+    where
+        F: FnMut(char),
+    {
+        self.format_in(Radix::Decimal, c, output);
+    }
+
+    /// The same as `CellFormat::format()`, but with numbers written in `radix`.
+    ///
+    /// Negative signed numbers are written as a `-` followed by the size of the number, so
+    /// 255 as a signed number in hex is `-1`.
+    pub fn format_in<F>(self, radix: Radix, c: char, mut output: F)
+    where
+        F: FnMut(char),
+    {
+        let value = cell_byte(c);
+        let number = match self {
+            CellFormat::Char => return output(c),
+            CellFormat::Unsigned => radix.format(value),
+            CellFormat::Signed if (value as i8) < 0 => {
+                format!("-{}", radix.format((value as i8).unsigned_abs()))
+            }
+            CellFormat::Signed => radix.format(value),
+        };
+
+        number.chars().for_each(&mut output);
+        output('\n');
+    }
+}
+
+/// The base cell values are written in, for tape dumps and numeric output.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::Radix;
+/// assert_eq!(Radix::Binary.format(5), "101");
+/// assert_eq!(Radix::Hex.format_padded(5), "05");
+/// ```
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Radix {
+    /// Base 2.
+    Binary,
+    /// Base 8.
+    Octal,
+    /// Base 10.
+    #[default]
+    Decimal,
+    /// Base 16, with lowercase digits.
+    Hex,
+}
+
+impl Radix {
+    /// Write `value` in this radix, with no padding or prefix.
+    pub fn format(self, value: u8) -> String {
+        match self {
+            Radix::Binary => format!("{:b}", value),
+            Radix::Octal => format!("{:o}", value),
+            Radix::Decimal => value.to_string(),
+            Radix::Hex => format!("{:x}", value),
+        }
+    }
+
+    /// Write `value` in this radix, padded with zeros to the width of the biggest cell value.
+    pub fn format_padded(self, value: u8) -> String {
+        format!("{:0>1$}", self.format(value), self.width())
+    }
+
+    /// Get how many digits the biggest cell value, 255, takes in this radix.
+    fn width(self) -> usize {
+        self.format(u8::MAX).len()
+    }
+}
+
+/// When a `BasicOutput` flushes what it prints to the terminal.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum FlushMode {
+    /// Flush after every char, so output shows up as soon as the program outputs it.
+    #[default]
+    EveryChar,
+    /// Hold all output back until `BasicOutput::flush()` is called or the `BasicOutput` is
+    /// dropped, then write it in one go.
+    ///
+    /// This is the fastest way to print output-heavy programs, e.g. when benchmarking or
+    /// redirecting to a file, but nothing shows up while the program runs, which is no good
+    /// for interactive programs, and all of the output is kept in memory until then.
+    AtEnd,
+}
+
+impl Default for BasicInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BasicInput {
+    /// Create new BasicInput struct.
+    pub fn new() -> Self {
+        Self {
+            source: InputSource::Terminal(Term::stdout()),
+            prompt: None,
+        }
+    }
+
+    /// Create new BasicInput struct that reads from the preset string `input` instead of the
+    /// terminal.
+    ///
+    /// Once every char of `input` has been read, further reads give a char with ascii value of 0,
+    /// the same as a non-interactive terminal.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let basic_input = BasicInput::from_string("hi");
+    ///
+    /// assert_eq!(basic_input.input_char(), 'h');
+    /// assert_eq!(basic_input.input_remaining(), Some(1));
+    /// ```
+    pub fn from_string(input: &str) -> Self {
+        Self {
+            source: InputSource::Preset {
+                chars: input.chars().collect(),
+                position: Cell::new(0),
+            },
+            prompt: None,
+        }
+    }
+
+    /// Create new BasicInput struct that reads the preset `chars` over and over.
+    ///
+    /// Rather than running out, the input wraps back around to the first char once every char
+    /// has been read. This is nicer than blank input for benchmarking programs that read input
+    /// forever, since they get varied input without needing an endless supply of it. With no
+    /// chars at all, reads give a char with ascii value of 0.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let basic_input = BasicInput::cycling(&['a', 'b']);
+    ///
+    /// assert_eq!(basic_input.input_char(), 'a');
+    /// assert_eq!(basic_input.input_char(), 'b');
+    /// assert_eq!(basic_input.input_char(), 'a');
+    /// ```
+    pub fn cycling(chars: &[char]) -> Self {
+        Self {
+            source: InputSource::Cycling {
+                chars: chars.to_vec(),
+                position: Cell::new(0),
+            },
+            prompt: None,
+        }
+    }
+
+    /// Create new BasicInput struct that reads pseudo-random bytes generated from `seed`.
+    ///
+    /// The same seed always gives the same sequence of input, so runs are reproducible. Handy
+    /// for stress testing programs that consume a lot of input.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let a = BasicInput::random(42);
+    /// let b = BasicInput::random(42);
+    ///
+    /// assert_eq!(a.input_char(), b.input_char());
+    /// ```
+    pub fn random(seed: u64) -> Self {
+        // Xorshift gets stuck on a state of 0, so swap it for some other fixed value.
+        let state = if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        };
+
+        Self {
+            source: InputSource::Random(Cell::new(state)),
+            prompt: None,
+        }
+    }
+
+    /// Create new BasicInput struct that reads from each of `sources` in turn.
+    ///
+    /// Reads come from the first source until it runs out, then carry on with the next one, so
+    /// a program can be primed with a fixed header before reading interactively. Only once
+    /// every source has run out do reads give a char with ascii value of 0. A terminal source
+    /// runs out when it can't be read from, and cycling and random sources never run out.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let basic_input = BasicInput::chain(vec![
+    ///     BasicInput::from_string("a"),
+    ///     BasicInput::from_string(""),
+    ///     BasicInput::from_string("b"),
+    /// ]);
+    ///
+    /// assert_eq!(basic_input.input_char(), 'a');
+    /// assert_eq!(basic_input.input_char(), 'b');
+    /// assert_eq!(basic_input.input_char(), '\0');
+    /// ```
+    pub fn chain(sources: Vec<BasicInput>) -> Self {
+        Self {
+            source: InputSource::Chain {
+                sources,
+                current: Cell::new(0),
+            },
+            prompt: None,
+        }
+    }
+
+    /// Get how many chars of input are left, if the input is finite.
+    ///
+    /// This is only a hint for tooling, e.g. to detect a program reading more input than it was
+    /// given. Terminal, cycling and random input have no known end, so they always give None,
+    /// as does chained input with any of them still to be read.
+    pub fn input_remaining(&self) -> Option<usize> {
+        match &self.source {
+            InputSource::Terminal(_) | InputSource::Cycling { .. } | InputSource::Random(_) => None,
+            InputSource::Preset { chars, position } => Some(chars.len() - position.get()),
+            InputSource::Chain { sources, current } => sources[current.get()..]
+                .iter()
+                .map(BasicInput::input_remaining)
+                .sum(),
+        }
+    }
+
+    /// Set a prompt to print to stderr whenever input is about to be read, or None to turn the
+    /// prompt off.
+    ///
+    /// BF programs often can't signal that they're waiting for input, so this lets the user know
+    /// without the program having to print anything itself. The prompt goes to stderr so it
+    /// doesn't end up mixed into the program's output. It's off by default. For chained input,
+    /// it's set on every source.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let mut basic_input = BasicInput::new();
+    /// basic_input.set_prompt(Some("? ".to_string()));
+    /// ```
+    pub fn set_prompt(&mut self, prompt: Option<String>) {
+        if let InputSource::Chain { sources, .. } = &mut self.source {
+            for source in sources {
+                source.set_prompt(prompt.clone());
+            }
+        }
+        self.prompt = prompt;
+    }
+
+    /// Blank input, handy when benchmarking and input doesn't matter.
+    pub fn blank(&self) -> char {
+        ' '
+    }
+
+    /// Input single char from terminal, or from the preset input if there is one.
+    ///
+    /// If the terminal is not an interactive terminal, the terminal from the `console` crate
+    /// returns an error from `read_char()`. In this situation, this function will return a char
+    /// with ascii value of 0. Preset input that has run out also gives a char with ascii value
+    /// of 0.
+    pub fn input_char(&self) -> char {
+        self.read_char().unwrap_or(0u8 as char)
+    }
+
+    /// Read a single char, or None if the input has run out.
+    fn read_char(&self) -> Option<char> {
+        match &self.source {
+            InputSource::Terminal(term) => {
+                if let Some(prompt) = &self.prompt {
+                    eprint!("{}", prompt);
+                }
+
+                term.read_char().ok()
+            }
+            InputSource::Preset { chars, position } => {
+                let c = chars.get(position.get())?;
+                position.set(position.get() + 1);
+                Some(*c)
+            }
+            InputSource::Cycling { chars, position } => {
+                let c = chars.get(position.get())?;
+                position.set((position.get() + 1) % chars.len());
+                Some(*c)
+            }
+            InputSource::Random(state) => {
+                state.set(xorshift(state.get()));
+                // The high bits of xorshift are the better quality ones.
+                Some((state.get() >> 56) as u8 as char)
+            }
+            InputSource::Chain { sources, current } => {
+                while let Some(source) = sources.get(current.get()) {
+                    if let Some(c) = source.read_char() {
+                        return Some(c);
+                    }
+                    current.set(current.get() + 1);
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Default for BasicOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BasicOutput {
+    /// Create new BasicOutput struct.
+    pub fn new() -> Self {
+        Self {
+            stdout: std::io::stdout(),
+            newlines: NewlineMode::Raw,
+            nulls: NullMode::Raw,
+            cells: CellFormat::Char,
+            radix: Radix::Decimal,
+            flushes: FlushMode::EveryChar,
+            buffer: String::new(),
+        }
+    }
+
+    /// Set how newlines are translated when printed. Output is raw by default.
+    pub fn set_newline_mode(&mut self, mode: NewlineMode) {
+        self.newlines = mode;
+    }
+
+    /// Set how null bytes are written when printed. They're written raw by default.
+    ///
+    /// Only cells written as chars can be null bytes, so this has no effect with the numeric
+    /// cell formats, which write 0 as a number.
+    pub fn set_null_mode(&mut self, mode: NullMode) {
+        self.nulls = mode;
+    }
+
+    /// Set how each output cell is written. Cells are written as chars by default.
+    pub fn set_cell_format(&mut self, format: CellFormat) {
+        self.cells = format;
+    }
+
+    /// Set the radix numbers are written in by the numeric cell formats. It's decimal by
+    /// default.
+    pub fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+    }
+
+    /// Set when printed output is flushed. Output is flushed after every char by default.
+    ///
+    /// Switching back to `FlushMode::EveryChar` flushes anything held back first.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::*;
+    /// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+.+.+.");
+    /// let mut output = BasicOutput::new();
+    /// output.set_flush_mode(FlushMode::AtEnd);
+    ///
+    /// prgm.execute(|| '\0', |c| output.print_char(c)).unwrap();
+    /// output.flush();
+    /// ```
+    pub fn set_flush_mode(&mut self, mode: FlushMode) {
+        if mode == FlushMode::EveryChar {
+            self.flush();
+        }
+        self.flushes = mode;
+    }
+
+    /// Write out any output being held back and flush the terminal.
+    pub fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            print!("{}", self.buffer);
+            self.buffer.clear();
+        }
+        self.stdout.flush().expect("Error flushing output");
+    }
+
+    /// Blank output, handy when benchmarking and output doesn't matter.
+    pub fn blank(&self, _: char) {}
+
+    /// Print single char to terminal.
+    pub fn print_char(&mut self, c: char) {
+        let newlines = self.newlines;
+        let nulls = self.nulls;
+        let radix = self.radix;
+        let translate = |c, output: &mut dyn FnMut(char)| {
+            nulls.translate(c, |c| newlines.translate(c, &mut *output));
+        };
+
+        match self.flushes {
+            FlushMode::EveryChar => {
+                self.cells
+                    .format_in(radix, c, |c| translate(c, &mut |c| print!("{}", c)));
+                self.stdout.flush().expect("Error flushing output");
+            }
+            FlushMode::AtEnd => {
+                let buffer = &mut self.buffer;
+                self.cells
+                    .format_in(radix, c, |c| translate(c, &mut |c| buffer.push(c)));
+            }
+        }
+    }
+}
+
+impl Drop for BasicOutput {
+    fn drop(&mut self) {
+        // Nothing is held back unless flushing at the end, so there's nothing to lose otherwise.
+        if !self.buffer.is_empty() {
+            print!("{}", self.buffer);
+            let _ = self.stdout.flush();
+        }
+    }
+}
+
+/// Chunked output operation for BF.
+///
+/// Collects output in an internal buffer and writes it to the underlying `Write` in chunks of
+/// `chunk_size` bytes, rather than writing and flushing every char like `BasicOutput` does. This
+/// is much faster for programs that output a lot. Anything left in the buffer is written when
+/// `flush()` is called or when the `ChunkedOutput` is dropped.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::*;
+/// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+.+.+.");
+///
+/// let mut output = ChunkedOutput::new(std::io::stdout()).chunk_size(64 * 1024);
+/// prgm.execute(|| '\0', |c| output.print_char(c)).unwrap();
+/// output.flush().unwrap();
+/// ```
+pub struct ChunkedOutput<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl<W: Write> ChunkedOutput<W> {
+    /// Default number of bytes buffered before writing a chunk.
+    pub const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+    /// Create new ChunkedOutput struct writing to `writer` with the default chunk size.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: Vec::with_capacity(Self::DEFAULT_CHUNK_SIZE),
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Set how many bytes are buffered before they are written out as one chunk.
+    ///
+    /// A chunk size of 0 is treated as 1.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self.buffer.reserve(self.chunk_size);
+        self
+    }
+
+    /// Buffer a single char, writing out a chunk if the buffer is full.
+    pub fn print_char(&mut self, c: char) {
+        self.buffer_char(c).expect("Error writing output");
+    }
+
+    /// Do what `ChunkedOutput::print_char()` does, but return an error if writing fails.
+    fn buffer_char(&mut self, c: char) -> std::io::Result<()> {
+        let mut bytes = [0; 4];
+        self.buffer
+            .extend_from_slice(c.encode_utf8(&mut bytes).as_bytes());
+
+        if self.buffer.len() >= self.chunk_size {
+            self.write_buffer()?;
+        }
+        Ok(())
+    }
+
+    /// Write out everything in the buffer and flush the underlying writer.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.write_buffer()?;
+        self.writer.flush()
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Write the whole buffer to the underlying writer as one chunk.
+    fn write_buffer(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.writer.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for ChunkedOutput<W> {
+    fn drop(&mut self) {
+        // Can't report an error from drop, so a failed final write is ignored.
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Execute program with blanks without boilerplate.
+    ///
+    /// Will panic in the case of a BF error.
+    fn blank_execute_prgm(prgm: &mut Program) -> Result<()> {
+        let input = BasicInput::new();
+        let output = BasicOutput::new();
+
+        prgm.execute(|| input.blank(), |c| output.blank(c))
+    }
+
+    #[test]
+    fn str_to_instructions() {
+        let instructions_str = "+-<>s[]comment,."; // the 's' and the word 'comment' are
+                                                   // comments and should be ignored in the output
+        let instructions = Instructions::from_string(instructions_str);
+        assert_eq!(
+            instructions,
+            Instructions(
+                vec![
+                    Instruct::MvValue(1),
+                    Instruct::MvValue(-1),
+                    Instruct::MvPtr(-1),
+                    Instruct::MvPtr(1),
+                    Instruct::OpenLoop,
+                    Instruct::CloseLoop,
+                    Instruct::Input,
+                    Instruct::Output,
+                ],
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn create_program() {
+        let instructions = Instructions::from_string("+-><[],.");
+        let new_program = Program::new(instructions.clone());
+        let custom_program = Program {
+            instructions: Arc::new(instructions),
+            instruction_ptr: 0,
+            cell_ptr: 0,
+            origin: 0,
+            cells: vec![],
+            loop_stack: vec![],
+            output_len: 0,
+            has_loops: true,
+            profile: None,
+            config: Config::default(),
+            cell_hook: None,
+            growth_hook: None,
+            step_budget: None,
+            input_len: None,
+            eof_reads: 0,
+            eof_states: HashSet::new(),
+            history: None,
+        };
+
+        assert_eq!(new_program, custom_program);
+    }
+
+    #[test]
+    fn optimize_multiples() {
+        use Instruct::*;
+
+        let mut instructions = Instructions::from_string("++++>>++++-<>>>-<");
+        instructions.optimize_combine_multiples();
+
+        let expected = Instructions(
+            vec![
+                MvValue(4),
+                MvPtr(2),
+                MvValue(3),
+                MvPtr(2),
+                MvValue(-1),
+                MvPtr(-1),
+            ],
+            None,
+        );
+
+        assert_eq!(instructions, expected);
+
+        let mut instructions = Instructions::from_string("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
+        instructions.optimize_combine_multiples();
+
+        let expected = Instructions(
+            vec![
+                MvValue(8),
+                OpenLoop,
+                MvPtr(1),
+                MvValue(4),
+                OpenLoop,
+                MvPtr(1),
+                MvValue(2),
+                MvPtr(1),
+                MvValue(3),
+                MvPtr(1),
+                MvValue(3),
+                MvPtr(1),
+                MvValue(1),
+                MvPtr(-4),
+                MvValue(-1),
+                CloseLoop,
+                MvPtr(1),
+                MvValue(1),
+                MvPtr(1),
+                MvValue(1),
+                MvPtr(1),
+                MvValue(-1),
+                MvPtr(2),
+                MvValue(1),
+                OpenLoop,
+                MvPtr(-1),
+                CloseLoop,
+                MvPtr(-1),
+                MvValue(-1),
+                CloseLoop,
+                MvPtr(2),
+                Output,
+                MvPtr(1),
+                MvValue(-3),
+                Output,
+                MvValue(7),
+                Output,
+                Output,
+                MvValue(3),
+                Output,
+                MvPtr(2),
+                Output,
+                MvPtr(-1),
+                MvValue(-1),
+                Output,
+                MvPtr(-1),
+                Output,
+                MvValue(3),
+                Output,
+                MvValue(-6),
+                Output,
+                MvValue(-8),
+                Output,
+                MvPtr(2),
+                MvValue(1),
+                Output,
+                MvPtr(1),
+                MvValue(2),
+                Output,
+            ],
+            None,
+        );
+
+        assert_eq!(instructions, expected);
+    }
+
+    #[test]
+    fn instructions_to_string() {
+        let instructions = Instructions::from_string("+-<>s[]comment,.");
+        assert_eq!(instructions.to_string(), "+-<>[],.");
+
+        let mut instructions = Instructions::from_string("++++>>++++-<>>>-<");
+        instructions.optimize();
+        assert_eq!(instructions.to_string(), "++++>>+++>>-<");
+    }
+
+    #[test]
+    fn chunked_output() {
+        /// Writer that records the size of every write it gets.
+        struct RecordWrites(Vec<usize>);
+
+        impl Write for RecordWrites {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.push(buf.len());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut output = ChunkedOutput::new(RecordWrites(vec![])).chunk_size(4);
+        let mut prgm = Program::from_string("+[.+]"); // Outputs 1 through 255, 255 bytes total.
+        prgm.execute(|| ' ', |c| output.print_char(c)).unwrap();
+        output.flush().unwrap();
+
+        // Values past 127 are two bytes as UTF-8, so 127 + 128 * 2 bytes are written. Every write
+        // is at least a full chunk apart from the last write of whatever is left over.
+        let writes = &output.get_ref().0;
+        assert_eq!(writes.iter().sum::<usize>(), 127 + 128 * 2);
+        assert!(writes[..writes.len() - 1].iter().all(|&n| n >= 4));
+
+        // Dropping writes out anything left in the buffer.
+        let mut bytes = vec![];
+        {
+            let mut output = ChunkedOutput::new(&mut bytes);
+            output.print_char('h');
+            output.print_char('i');
+        }
+        assert_eq!(bytes, b"hi");
+    }
+
+    #[test]
+    fn step_limit() {
+        let mut prgm = Program::from_string("+++[-]");
+        assert!(!prgm.halts_within(4).unwrap());
+        assert!(prgm.halts_within(100).unwrap());
+
+        // The budget is per call, so a limited program can be continued.
+        prgm.reset();
+        let result = prgm.execute_limited(4, || ' ', |_| ()).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::StepLimitExceeded));
+        prgm.execute_limited(100, || ' ', |_| ()).unwrap();
+
+        // Scanning over a skipped loop counts too, one step per instruction.
+        let mut prgm = Program::from_string(&format!("[{}]", "+".repeat(50)));
+        assert!(!prgm.halts_within(51).unwrap());
+        assert!(prgm.halts_within(52).unwrap());
+
+        // Errors other than the step limit still come through.
+        let result = Program::from_string("<")
+            .halts_within(100)
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+    }
+
+    #[test]
+    fn profiling() {
+        let mut prgm = Program::from_string("++[-]>[+]");
+        assert_eq!(prgm.coverage(), None);
+
+        prgm.enable_profiling();
+        blank_execute_prgm(&mut prgm).unwrap();
+
+        let counts = &prgm.profile().unwrap().instruction_counts;
+        assert_eq!(counts, &vec![1, 1, 3, 2, 2, 1, 1, 0, 0]);
+        let iterations = &prgm.profile().unwrap().loop_iterations;
+        assert_eq!(iterations, &BTreeMap::from([(2, 2)]));
+        assert_eq!(
+            prgm.coverage().unwrap(),
+            vec![true, true, true, true, true, true, true, false, false]
+        );
+
+        // Counts carry over a reset.
+        prgm.reset();
+        blank_execute_prgm(&mut prgm).unwrap();
+        assert_eq!(prgm.profile().unwrap().instruction_counts[2], 6);
+        assert_eq!(prgm.profile().unwrap().loop_iterations[&2], 4);
+
+        // Nested loops count every run of the inner body.
+        let mut prgm = Program::from_string("+++[>++[-]<-]");
+        prgm.enable_profiling();
+        blank_execute_prgm(&mut prgm).unwrap();
+        let iterations = &prgm.profile().unwrap().loop_iterations;
+        assert_eq!(iterations, &BTreeMap::from([(3, 3), (7, 6)]));
+
+        // The cell range covers where the pointer went, not just where it ended up.
+        assert_eq!(prgm.profile().unwrap().cell_range, (0, 1));
+        let mut prgm = ProgramBuilder::new(Instructions::from_string("<<>>>>><"))
+            .start_pointer(5)
+            .build();
+        prgm.enable_profiling();
+        blank_execute_prgm(&mut prgm).unwrap();
+        assert_eq!(prgm.profile().unwrap().cell_range, (3, 8));
+    }
+
+    #[test]
+    fn net_pointer_shift() {
+        let shift = |s| Instructions::from_string(s).net_pointer_shift();
+
+        assert_eq!(shift(""), Some(0));
+        assert_eq!(shift(">>><"), Some(2));
+        assert_eq!(shift("<[->>+<<]<"), Some(-2));
+        assert_eq!(shift(">[>[-]<[>+<-]]"), Some(1));
+        assert_eq!(shift(">[>[-]<[>+<-]>]"), None);
+        assert_eq!(shift("[>"), None);
+        assert_eq!(shift("]>"), None);
+    }
+
+    #[test]
+    fn preset_input() {
+        let input = BasicInput::from_string("ab");
+        assert_eq!(input.input_remaining(), Some(2));
+
+        let mut outstring = String::new();
+        let mut prgm = Program::from_string(",.,.,+.");
+        prgm.execute(|| input.input_char(), |c| outstring.push(c))
+            .unwrap();
+
+        // The third read is past the end of the input, so it's 0.
+        assert_eq!(outstring, "ab\u{1}");
+        assert_eq!(input.input_remaining(), Some(0));
+
+        assert_eq!(BasicInput::new().input_remaining(), None);
+    }
+
+    #[test]
+    fn loop_tree() {
+        let tree = Instructions::from_string("+[>[-]<[>+<-]]>[.]")
+            .cfg()
+            .unwrap();
+        let expected = LoopTree {
+            roots: vec![
+                LoopNode {
+                    open: 1,
+                    close: 13,
+                    children: vec![
+                        LoopNode {
+                            open: 3,
+                            close: 5,
+                            children: vec![],
+                        },
+                        LoopNode {
+                            open: 7,
+                            close: 12,
+                            children: vec![],
+                        },
+                    ],
+                },
+                LoopNode {
+                    open: 15,
+                    close: 17,
+                    children: vec![],
+                },
+            ],
+        };
+        assert_eq!(tree, expected);
+
+        let cfg_error = |s| Instructions::from_string(s).cfg().map_err(|e| e.kind);
+        assert_eq!(cfg_error("[]]"), Err(BFErrorKind::MissingOpen));
+        assert_eq!(cfg_error("[[]"), Err(BFErrorKind::MissingClose));
+    }
+
+    #[test]
+    fn loop_free_execution() {
+        let source =
+            "++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.>,.<+.";
+
+        let mut fast = Program::from_string(source);
+        assert!(!fast.has_loops);
+        let mut fast_out = String::new();
+        fast.execute(|| 'x', |c| fast_out.push(c)).unwrap();
+
+        // Stepping manually always goes through the general path.
+        let mut general = Program::from_string(source);
+        let mut general_out = String::new();
+        while !general.done().unwrap() {
+            general.step(|| 'x', |c| general_out.push(c)).unwrap();
+        }
+
+        assert_eq!(fast_out, "HxI");
+        assert_eq!(fast_out, general_out);
+        assert_eq!(fast, general);
+
+        // Errors still come through the fast path.
+        let result = blank_execute_prgm(&mut Program::from_string("+<")).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+    }
+
+    #[test]
+    fn extend_source() {
+        let mut outstring = String::new();
+        let mut prgm = Program::from_string("+++.");
+        prgm.execute(|| ' ', |c| outstring.push(c)).unwrap();
+
+        // Loops can span the new code, but only once balanced.
+        let result = prgm.extend_source("[-").map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingClose));
+        let result = prgm.extend_source("]]").map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingOpen));
+        assert_eq!(*prgm.instructions, Instructions::from_string("+++."));
+
+        prgm.extend_source("[->++<]>.").unwrap();
+        prgm.execute(|| ' ', |c| outstring.push(c)).unwrap();
+        assert_eq!(outstring, "\u{3}\u{6}");
+    }
+
+    #[test]
+    fn random_input() {
+        let chars =
+            |input: BasicInput| -> Vec<char> { (0..64).map(|_| input.input_char()).collect() };
+
+        assert_eq!(chars(BasicInput::random(7)), chars(BasicInput::random(7)));
+        assert_ne!(chars(BasicInput::random(7)), chars(BasicInput::random(8)));
+        assert_ne!(chars(BasicInput::random(0)), vec!['\0'; 64]);
+        assert!(chars(BasicInput::random(7))
+            .iter()
+            .all(|&c| (c as u32) < 256));
+    }
+
+    #[test]
+    fn cycling_input() {
+        let input = BasicInput::cycling(&['x', 'y', 'z']);
+        let chars: String = (0..7).map(|_| input.input_char()).collect();
+        assert_eq!(chars, "xyzxyzx");
+        assert_eq!(input.input_remaining(), None);
+
+        assert_eq!(BasicInput::cycling(&[]).input_char(), '\0');
+    }
+
+    #[test]
+    fn matching_bracket() {
+        let instructions = Instructions::from_string("][[-][>+<-]");
+
+        assert_eq!(instructions.matching_bracket(0), None);
+        assert_eq!(instructions.matching_bracket(1), None);
+        assert_eq!(instructions.matching_bracket(2), Some(4));
+        assert_eq!(instructions.matching_bracket(4), Some(2));
+        assert_eq!(instructions.matching_bracket(5), Some(10));
+        assert_eq!(instructions.matching_bracket(10), Some(5));
+        assert_eq!(instructions.matching_bracket(6), None);
+        assert_eq!(instructions.matching_bracket(100), None);
+    }
+
+    #[test]
+    fn all_bracket_errors() {
+        let kinds = |s| {
+            Instructions::from_string(s)
+                .check_all_brackets()
+                .into_iter()
+                .map(|e| {
+                    assert_eq!(e.position_kind, PositionKind::Instruction);
+                    (e.kind, e.position)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(kinds("+[->[+]<]"), vec![]);
+        assert_eq!(
+            kinds("] comment ]+[[-]["),
+            vec![
+                (BFErrorKind::MissingOpen, 0),
+                (BFErrorKind::MissingOpen, 1),
+                (BFErrorKind::MissingClose, 3),
+                (BFErrorKind::MissingClose, 7),
+            ]
+        );
+
+        // The first error found is the same one running the program would hit.
+        let instructions = Instructions::from_string("+]");
+        assert_eq!(
+            instructions.check_all_brackets()[0].kind,
+            instructions.cfg().unwrap_err().kind
+        );
+    }
+
+    #[test]
+    fn start_pointer() {
+        let mut prgm = ProgramBuilder::new(Instructions::from_string("<<+<+"))
+            .start_pointer(3)
+            .build();
+        assert_eq!(prgm.cell_ptr, 3);
+        assert_eq!(prgm.cells, vec![0; 4]);
+
+        blank_execute_prgm(&mut prgm).unwrap();
+        assert_eq!(prgm.cells, vec![1, 1, 0, 0]);
+
+        prgm.reset();
+        assert_eq!(prgm.cell_ptr, 3);
+        assert_eq!(prgm.cells, vec![0; 4]);
+
+        // Still can't go left of cell 0.
+        let mut prgm = ProgramBuilder::new(Instructions::from_string("<<"))
+            .start_pointer(1)
+            .build();
+        let result = blank_execute_prgm(&mut prgm).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+    }
+
+    #[test]
+    fn same_program() {
+        let mut prgm = Program::from_string("+[-]");
+        blank_execute_prgm(&mut prgm).unwrap();
+
+        assert!(prgm.same_program(&Program::from_string("+ comment [-]")));
+        assert!(!prgm.same_program(&Program::from_string("+[+]")));
+    }
+
+    #[test]
+    fn optimize_leading_loops() {
+        let optimized = |s| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.optimize_remove_leading_loops();
+            instructions
+        };
+
+        assert_eq!(optimized("[...]+++"), Instructions::from_string("+++"));
+        assert_eq!(
+            optimized(">[-]<[>+<-][[]]>+[-]"),
+            Instructions::from_string("><>+[-]")
+        );
+        assert_eq!(optimized("+[-]"), Instructions::from_string("+[-]"));
+        assert_eq!(optimized(",[.,]"), Instructions::from_string(",[.,]"));
+        assert_eq!(optimized("[[]"), Instructions::from_string("[[]"));
+    }
+
+    #[test]
+    fn cell_change_callback() {
+        use std::sync::{Arc, Mutex};
+
+        let changes = Arc::new(Mutex::new(vec![]));
+        let recorded = Arc::clone(&changes);
+
+        // `,` reads 'a' over a cell that's already 'a', and the `+-` pair nets to no change, but
+        // each individual change still fires.
+        let mut prgm = Program::from_string("+[-],>,<,+-");
+        prgm.on_cell_change(move |idx, value| recorded.lock().unwrap().push((idx, value)));
+        prgm.execute(|| 'a', |_| ()).unwrap();
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![(0, 1), (0, 0), (0, 97), (1, 97), (0, 98), (0, 97)]
+        );
+    }
+
+    #[test]
+    fn newline_translation() {
+        let translated = |mode: NewlineMode| {
+            let mut output = String::new();
+            for c in "\n\ta\r\n".chars() {
+                mode.translate(c, |c| output.push(c));
+            }
+            output
+        };
+
+        assert_eq!(translated(NewlineMode::default()), "\n\ta\r\n");
+        assert_eq!(translated(NewlineMode::Crlf), "\r\n\ta\r\r\n");
+    }
+
+    #[test]
+    fn null_translation() {
+        let translated = |mode: NullMode| {
+            let mut output = String::new();
+            for c in "\0a\0\0".chars() {
+                mode.translate(c, |c| output.push(c));
+            }
+            output
+        };
+
+        assert_eq!(translated(NullMode::default()), "\0a\0\0");
+        assert_eq!(translated(NullMode::Skip), "a");
+        assert_eq!(translated(NullMode::Replace('␀')), "␀a␀␀");
+
+        // Numeric output never has null bytes to translate.
+        let mut output = BasicOutput::new();
+        output.set_flush_mode(FlushMode::AtEnd);
+        output.set_null_mode(NullMode::Skip);
+        output.set_newline_mode(NewlineMode::Crlf);
+        output.print_char('\0');
+        output.set_cell_format(CellFormat::Unsigned);
+        output.print_char('\0');
+        assert_eq!(output.buffer, "0\r\n");
+        output.buffer.clear();
+    }
+
+    #[test]
+    fn optimize_fold_constants() {
+        let folded = |s| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.optimize_fold_constants();
+            instructions
+        };
+        let combined = |s| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.optimize_combine_multiples();
+            instructions
+        };
+
+        assert_eq!(folded("+>+<+"), combined("++>+<"));
+        assert_eq!(folded("+>+<+.>-<-"), combined("++.-"));
+        assert_eq!(folded("[>+<+>+<-]"), combined("[>++<]"));
+        assert_eq!(folded("+++,>"), combined(",>"));
+        assert_eq!(folded(">>+<-<[-]>>"), combined(">->+<<[-]>>"));
+        assert_eq!(folded("+>-<->+"), combined(">"));
+        assert_eq!(folded(&"+".repeat(255)), combined("-"));
+
+        // Folding doesn't change what the program does.
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let mut instructions = Instructions::from_string(source);
+        instructions.optimize();
+        assert!(instructions.0.len() < Instructions::from_string(source).0.len());
+
+        let mut output = String::new();
+        Program::new(instructions)
+            .execute(|| '\0', |c| output.push(c))
+            .unwrap();
+        assert_eq!(output, "Hello World!\n");
+    }
+
+    #[test]
+    fn optimize_propagate_values() {
+        let prepared = |s: &str| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.optimize_clear_loops();
+            instructions.optimize_combine_multiples();
+            instructions
+        };
+        let propagated = |s: &str| {
+            let mut instructions = prepared(s);
+            instructions.optimize_propagate_values();
+            instructions.0
+        };
+        use Instruct::*;
+
+        // Coming back to a set cell after moving away still changes the set.
+        let source = "[-]>[-]<+";
+        assert_eq!(
+            propagated(source),
+            vec![MvPtr(1), SetValue(0), MvPtr(-1), SetValue(1)]
+        );
+        let mut folded = prepared(source);
+        folded.optimize_fold_constants();
+        assert_eq!(folded, prepared(source));
+
+        assert_eq!(propagated("[-]++>+<+++>-<"), vec![SetValue(5)]);
+        assert_eq!(
+            propagated("[-]+>+<[-]---"),
+            vec![MvPtr(1), MvValue(1), MvPtr(-1), SetValue(253)]
+        );
+
+        // Values are written before they're output, and forgotten once input overwrites them.
+        assert_eq!(
+            propagated("[-]+.>+<+."),
+            vec![
+                SetValue(1),
+                Output,
+                SetValue(2),
+                Output,
+                MvPtr(1),
+                MvValue(1),
+                MvPtr(-1)
+            ]
+        );
+        assert_eq!(propagated("[-]+,+"), vec![Input, MvValue(1)]);
+
+        // Values aren't tracked into or out of loops, where changes are only folded.
+        let mut expected = vec![SetValue(1)];
+        let mut folded = prepared("[>+<-]+");
+        folded.optimize_fold_constants();
+        expected.extend(folded.0);
+        assert_eq!(propagated("[-]+[>+<-]+"), expected);
+
+        // Blocks that go left of where they start are only folded, since which cells they
+        // reach depends on the pointer underflow policy.
+        let source = "[-]++<[-]+>+";
+        let mut folded = prepared(source);
+        folded.optimize_fold_constants();
+        assert_eq!(propagated(source), folded.0);
+        let mut prgm = ProgramBuilder::new(Instructions(propagated(source), None))
+            .pointer_underflow(PointerUnderflow::Clamp)
+            .build();
+        blank_execute_prgm(&mut prgm).unwrap();
+        assert_eq!(prgm.tape(), [1, 1]);
+
+        // Code with unmatched brackets is left alone.
+        assert_eq!(propagated("[-]+>+<]"), prepared("[-]+>+<]").0);
+
+        // Propagating doesn't change what the program does.
+        let source = ">+++++[-]++<++[+]+++>>[-]->+<<<[-]>>>[-]<<+.";
+        let mut prgm = Program::new(Instructions(propagated(source), None));
+        blank_execute_prgm(&mut prgm).unwrap();
+        let mut unoptimized = Program::from_string(source);
+        blank_execute_prgm(&mut unoptimized).unwrap();
+        assert_eq!(prgm.tape(), unoptimized.tape());
+    }
+
+    #[test]
+    fn instructions_len() {
+        let instructions = Instructions::from_string("+-<>s[]comment,.");
+        assert_eq!(instructions.len(), 8);
+        assert!(!instructions.is_empty());
+
+        let prgm = Program::from_string("no instructions here");
+        assert_eq!(prgm.len(), 0);
+        assert!(prgm.is_empty());
+    }
+
+    #[test]
+    fn error_codes() {
+        let kinds = [
+            BFErrorKind::MissingOpen,
+            BFErrorKind::MissingClose,
+            BFErrorKind::InvalidInput,
+            BFErrorKind::CellBoundsError,
+            BFErrorKind::InstructionBoundsError,
+            BFErrorKind::IoError,
+            BFErrorKind::StepLimitExceeded,
+            BFErrorKind::InvalidOutput,
+            BFErrorKind::OutputLimitExceeded,
+            BFErrorKind::InvalidFormat,
+            BFErrorKind::ReplayMismatch,
+            BFErrorKind::ProgramTooLarge,
+            BFErrorKind::SkipLimitExceeded,
+            BFErrorKind::UnknownInputLength,
+            BFErrorKind::InputExhausted,
+            BFErrorKind::Timeout,
+            BFErrorKind::NotInLoop,
+        ];
+
+        // Codes are stable, so they're checked exactly rather than just for being unique.
+        let codes: Vec<u16> = kinds.iter().map(|k| k.code()).collect();
+        assert_eq!(codes, (1..=17).collect::<Vec<u16>>());
+    }
+
+    #[test]
+    fn shared_instructions() {
+        let instructions = Arc::new(Instructions::from_string("+."));
+        let mut a = Program::new(Arc::clone(&instructions));
+        let mut b = ProgramBuilder::new(Arc::clone(&instructions)).build();
+        assert_eq!(Arc::strong_count(&instructions), 3);
+
+        // Extending one program doesn't touch the instructions the others share.
+        a.extend_source("+.").unwrap();
+        assert_eq!(a.len(), 4);
+        assert_eq!(b.len(), 2);
+        assert_eq!(*instructions, Instructions::from_string("+."));
+
+        let mut output = String::new();
+        a.execute(|| ' ', |c| output.push(c)).unwrap();
+        b.execute(|| ' ', |c| output.push(c)).unwrap();
+        assert_eq!(output, "\u{1}\u{2}\u{1}");
+    }
+
+    #[test]
+    fn execute_without_io() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        let mut with_io = Program::from_string(source);
+        blank_execute_prgm(&mut with_io).unwrap();
+        let mut without_io = Program::from_string(source);
+        without_io.execute_no_io().unwrap();
+        assert_eq!(without_io.cells, with_io.cells);
+        assert_eq!(without_io.output_len, 0);
+
+        // Input leaves the cell alone.
+        let mut prgm = Program::from_string("+,");
+        prgm.execute_no_io().unwrap();
+        assert_eq!(prgm.cells, vec![1]);
+
+        let result = Program::from_string("<.")
+            .execute_no_io()
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+    }
+
+    #[test]
+    fn validate_source() {
+        assert!(is_valid(""));
+        assert!(is_valid("[[]][] comments [ are ] fine"));
+        assert!(!is_valid("[[]"));
+
+        let error = |kind, position| {
+            Err(BracketError {
+                kind,
+                position,
+                position_kind: PositionKind::Byte,
+            })
+        };
+        assert_eq!(validate("+]["), error(BFErrorKind::MissingOpen, 1));
+        assert_eq!(validate("[[-]+[]"), error(BFErrorKind::MissingClose, 0));
+        assert_eq!(validate("[]["), error(BFErrorKind::MissingClose, 2));
+        // Positions are byte offsets, so multibyte comments count for more than one.
+        assert_eq!(validate("é]"), error(BFErrorKind::MissingOpen, 2));
+        assert!(validate("é]")
+            .unwrap_err()
+            .to_string()
+            .ends_with("(at byte 2)"));
+    }
+
+    #[test]
+    fn source_with_input() {
+        let (instructions, input) = Instructions::from_source_with_input("+[-]!,.\nmore");
+        assert_eq!(instructions, Instructions::from_string("+[-]"));
+        assert_eq!(input, ",.\nmore");
+
+        let (instructions, input) = Instructions::from_source_with_input("+.");
+        assert_eq!(instructions, Instructions::from_string("+."));
+        assert_eq!(input, "");
+
+        // Only the extended version parses `?`, but both split in the same place.
+        let (instructions, input) = Instructions::from_source_with_input_extended("?[-]!ab?");
+        assert_eq!(instructions, Instructions::from_string_extended("?[-]"));
+        assert_eq!(input, "ab?");
+        let (instructions, _) = Instructions::from_source_with_input("?[-]!ab?");
+        assert_eq!(instructions, Instructions::from_string("[-]"));
+    }
+
+    #[test]
+    fn optimization_check() {
+        let hello = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let mut optimized = Instructions::from_string(hello);
+        optimized.optimize();
+        assert!(!optimization_diverges(
+            &Instructions::from_string(hello),
+            &optimized
+        ));
+
+        // A buggy optimization that changes output or cells gets caught.
+        let original = Instructions::from_string("+++.");
+        let wrong = Instructions::from_string("++.");
+        assert!(optimization_diverges(&original, &wrong));
+        let different_cells = Instructions::from_string("+++.>+");
+        assert!(optimization_diverges(&original, &different_cells));
+
+        // Runs that don't finish aren't compared.
+        assert!(!optimization_diverges(
+            &Instructions::from_string("+[]"),
+            &Instructions::from_string("+")
+        ));
+    }
+
+    #[test]
+    fn state_hashing() {
+        let mut a = Program::from_string("+>+<-");
+        let mut b = Program::from_string("+>+<-");
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        a.step(|| ' ', |_| ()).unwrap();
+        assert_ne!(a.state_hash(), b.state_hash());
+        b.step(|| ' ', |_| ()).unwrap();
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        // Untouched cells past the end of the tape are the same as zeroed ones.
+        b.cells.resize(100, 0);
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        blank_execute_prgm(&mut a).unwrap();
+        assert_ne!(a.state_hash(), b.state_hash());
+    }
+
+    #[test]
+    fn parse_limit() {
+        let instructions = Instructions::from_string_limited("+[-] comment >.", 6).unwrap();
+        assert_eq!(instructions, Instructions::from_string("+[-]>."));
+
+        let result = Instructions::from_string_limited("+[-]>.", 5).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::ProgramTooLarge));
+
+        assert!(Instructions::from_string_limited("", 0).is_ok());
+    }
+
+    #[test]
+    fn binary_io() {
+        // Reads every byte and outputs it with the high bit flipped, stopping at a 0 byte.
+        let mut prgm = Program::from_string(",[>++++++++[<++++++++++++++++>-]<.,]");
+        let input: Vec<u8> = (1..=255).collect();
+        let expected: Vec<u8> = input.iter().map(|b| b ^ 0x80).collect();
+        assert_eq!(prgm.run_bytes(&input).unwrap(), expected);
+
+        prgm.reset();
+        assert_eq!(prgm.run_bytes(&[]).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn parse_diagnostics() {
+        let source = "+[-]\n\t> ok.é,";
+        let (instructions, diagnostics) = Instructions::from_string_with_diagnostics(source);
+        assert_eq!(instructions, Instructions::from_string(source));
+
+        let found: Vec<(usize, char)> = diagnostics
+            .iter()
+            .map(|d| (d.position, d.character))
+            .collect();
+        assert_eq!(found, vec![(8, 'o'), (9, 'k'), (11, 'é')]);
+
+        let (_, diagnostics) = Instructions::from_string_with_diagnostics("+- \n<>");
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn pointer_underflow() {
+        let run = |source, tape_len: Option<usize>, policy| {
+            let mut builder =
+                ProgramBuilder::new(Instructions::from_string(source)).pointer_underflow(policy);
+            if let Some(len) = tape_len {
+                builder = builder.tape_len(len);
+            }
+            let mut prgm = builder.build();
+            prgm.execute(|| ' ', |_| ())
+                .map(|()| prgm.cell_ptr)
+                .map_err(|e| e.kind)
+        };
+        let bounds_error = Err(BFErrorKind::CellBoundsError);
+
+        assert_eq!(run("<", None, PointerUnderflow::Error), bounds_error);
+        assert_eq!(run("<", Some(4), PointerUnderflow::Error), bounds_error);
+
+        assert_eq!(run("<", Some(4), PointerUnderflow::Wrap), Ok(3));
+        assert_eq!(run(">><<<<<<<", Some(4), PointerUnderflow::Wrap), Ok(3));
+        // A growing tape has no end to wrap to.
+        assert_eq!(run("<", None, PointerUnderflow::Wrap), bounds_error);
+
+        assert_eq!(run("<", None, PointerUnderflow::Clamp), Ok(0));
+        assert_eq!(run("><<<>", Some(4), PointerUnderflow::Clamp), Ok(1));
+
+        // The right end of a fixed tape is always an error.
+        assert_eq!(run(">>>", Some(4), PointerUnderflow::Wrap), Ok(3));
+        assert_eq!(run(">>>>", Some(4), PointerUnderflow::Wrap), bounds_error);
+        assert_eq!(run(">>>>", Some(4), PointerUnderflow::Clamp), bounds_error);
+
+        // Collapsed moves wrap by their full amount.
+        let mut instructions = Instructions::from_string(">>><<<<<<");
+        instructions.optimize();
+        let mut prgm = ProgramBuilder::new(instructions)
+            .tape_len(4)
+            .pointer_underflow(PointerUnderflow::Wrap)
+            .build();
+        prgm.execute(|| ' ', |_| ()).unwrap();
+        assert_eq!(prgm.cell_ptr, 1);
+
+        // Growing keeps every cell, just further right.
+        assert_eq!(run("<", None, PointerUnderflow::Grow), Ok(0));
+        assert_eq!(run("<", Some(4), PointerUnderflow::Grow), bounds_error);
+        let mut prgm = ProgramBuilder::new(Instructions::from_string("+>++<<<+++>>"))
+            .pointer_underflow(PointerUnderflow::Grow)
+            .build();
+        prgm.execute(|| ' ', |_| ()).unwrap();
+        assert_eq!(prgm.tape(), &[3, 0, 1, 2]);
+        assert_eq!(prgm.cell_ptr, 2);
+    }
+
+    #[test]
+    fn strict_origin() {
+        let run = |source, start, policy| {
+            ProgramBuilder::new(Instructions::from_string(source))
+                .start_pointer(start)
+                .pointer_underflow(policy)
+                .strict_origin(true)
+                .build()
+                .execute(|| ' ', |_| ())
+                .map_err(|e| e.kind)
+        };
+        let bounds_error = Err(BFErrorKind::CellBoundsError);
+
+        // Going left is fine as long as it doesn't pass the origin.
+        assert_eq!(run(">>+<<", 0, PointerUnderflow::Grow), Ok(()));
+        assert_eq!(run(">>+<<<", 0, PointerUnderflow::Grow), bounds_error);
+        assert_eq!(run("<", 0, PointerUnderflow::Clamp), bounds_error);
+        assert_eq!(run("<", 3, PointerUnderflow::Error), bounds_error);
+        assert_eq!(run("><", 3, PointerUnderflow::Error), Ok(()));
+
+        // The origin moves with the cells when the tape grows, so it's still the same cell.
+        let mut prgm = ProgramBuilder::new(Instructions::from_string("<<+>>>"))
+            .pointer_underflow(PointerUnderflow::Grow)
+            .build();
+        prgm.execute(|| ' ', |_| ()).unwrap();
+        assert_eq!(prgm.origin, 2);
+        assert_eq!(prgm.tape(), &[1, 0, 0]);
+    }
+
+    #[test]
+    fn static_output_len() {
+        let len = |s| Instructions::from_string(s).static_output_len();
+
+        assert_eq!(len(""), Some(0));
+        assert_eq!(len("+.>.[-]<."), Some(3));
+        assert_eq!(len("+[-[+]]..."), Some(3));
+        assert_eq!(len("+[[-].]"), None);
+        assert_eq!(len("..]"), None);
+        assert_eq!(len("..["), None);
+    }
+
+    #[test]
+    fn skip_distance_limit() {
+        let limited = |source: &str| {
+            ProgramBuilder::new(Instructions::from_string(source))
+                .max_skip_distance(3)
+                .build()
+        };
+
+        assert!(blank_execute_prgm(&mut limited("[+++]")).is_ok());
+        let result = blank_execute_prgm(&mut limited("[++++]")).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::SkipLimitExceeded));
+
+        // A huge region after an unmatched `[` is given up on after the first thousand.
+        let mut prgm = ProgramBuilder::new(Instructions::from_string(&format!(
+            "[{}",
+            "+".repeat(1_000_000)
+        )))
+        .max_skip_distance(1000)
+        .build();
+        let result = blank_execute_prgm(&mut prgm).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::SkipLimitExceeded));
+    }
+
+    #[test]
+    fn tape_hex_dump() {
+        let mut prgm = Program::from_string(&format!("{}>>[-]", "+".repeat(b'a' as usize)));
+        prgm.execute(|| ' ', |_| ()).unwrap();
+        assert_eq!(
+            prgm.hex_dump(),
+            "00000000  61 00>00                                          |a..|\n"
+        );
+
+        // Rows are split at 16 cells, and the pointer can be marked on any of them.
+        let mut prgm = ProgramBuilder::new(Instructions::from_string("+<<<<<<<<<<<<<<<<<+"))
+            .start_pointer(17)
+            .build();
+        prgm.execute(|| ' ', |_| ()).unwrap();
+        assert_eq!(
+            prgm.hex_dump(),
+            concat!(
+                "00000000 >01 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n",
+                "00000010  00 01                                             |..|\n",
+            )
+        );
+
+        // A fresh program still shows the cell the pointer starts on.
+        assert_eq!(
+            Program::from_string("").hex_dump(),
+            "00000000 >00                                                |.|\n"
+        );
+    }
+
+    #[test]
+    fn instructions_builder() {
+        let built = InstructionsBuilder::new()
+            .input()
+            .add(-48)
+            .loop_start()
+            .move_ptr(2)
+            .add(3)
+            .move_ptr(-2)
+            .add(-1)
+            .loop_end()
+            .output()
+            .build()
+            .unwrap();
+        let mut parsed = Instructions::from_string(
+            ",------------------------------------------------[>>+++<<-].",
+        );
+        parsed.optimize_combine_multiples();
+        assert_eq!(built, parsed);
+
+        let result = InstructionsBuilder::new()
+            .loop_end()
+            .build()
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingOpen));
+        let result = InstructionsBuilder::new()
+            .loop_start()
+            .loop_start()
+            .loop_end()
+            .build()
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingClose));
+        assert!(InstructionsBuilder::new().build().unwrap().is_empty());
+    }
+
+    #[test]
+    fn chunked_execution() {
+        let hello = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        let mut output = String::new();
+        let mut prgm = Program::from_string(hello);
+        let mut chunks = 1;
+        while prgm.execute_chunked(7, || ' ', |c| output.push(c)).unwrap()
+            == ExecutionStatus::Running
+        {
+            chunks += 1;
+        }
+        assert_eq!(output, "Hello World!\n");
+        assert!(chunks > 10);
+
+        // Finished programs stay finished, and an empty chunk does nothing.
+        let status = prgm.execute_chunked(7, || ' ', |_| ()).unwrap();
+        assert_eq!(status, ExecutionStatus::Finished);
+        let mut prgm = Program::from_string("+");
+        assert_eq!(
+            prgm.execute_chunked(0, || ' ', |_| ()).unwrap(),
+            ExecutionStatus::Running
+        );
+        assert_eq!(
+            prgm.execute_chunked(1, || ' ', |_| ()).unwrap(),
+            ExecutionStatus::Finished
+        );
+
+        let result = Program::from_string("+<")
+            .execute_chunked(5, || ' ', |_| ())
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+    }
+
+    #[test]
+    fn optimize_set_values() {
+        let optimized = |s| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.optimize();
+            instructions
+        };
+
+        // Clears and the changes around them all collapse into the last set.
+        let set_five = Instructions(vec![Instruct::SetValue(5)], None);
+        assert_eq!(optimized("+[-]+++[-]+++++"), set_five);
+        assert_eq!(optimized("+[[-]]+++++"), set_five);
+        assert_eq!(optimized("+>,[+]+++<>+++<").to_string(), ">,[-]++++++<+");
+
+        // Only odd changes are sure to reach 0.
+        assert_eq!(optimized("+[--]").to_string(), "+[--]");
+
+        // The body only turns into a clear after folding, so a single round isn't enough.
+        let source = "+[>+<->-<]";
+        let mut one_round = Instructions::from_string(source);
+        one_round.optimize_with(passes::STANDARD);
+        assert_eq!(one_round.to_string(), "+[-]");
+        assert_eq!(
+            optimized(source),
+            Instructions(vec![Instruct::SetValue(0)], None)
+        );
+
+        // Sets run the same as the loops they replaced.
+        let source = ">+++++[-]++<++[+]+++>>,[-]-";
+        let mut prgm = Program::new(optimized(source));
+        blank_execute_prgm(&mut prgm).unwrap();
+        let mut unoptimized = Program::from_string(source);
+        blank_execute_prgm(&mut unoptimized).unwrap();
+        assert_eq!(prgm.tape(), unoptimized.tape());
+    }
+
+    #[test]
+    fn optimize_known_loops() {
+        // Known loops are only found once sets and moves have been.
+        let prepared = |s: &str| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.optimize_clear_loops();
+            instructions.optimize_fold_set_values();
+            instructions.optimize_move_loops();
+            instructions
+        };
+        let folded = |s: &str| {
+            let mut instructions = prepared(s);
+            instructions.optimize_fold_known_loops();
+            instructions.to_string()
+        };
+
+        assert_eq!(folded("[-]++[->+++<]"), "[-]>++++++<");
+        assert_eq!(folded("[-]++[>--->+>+<<<-]"), "[-]>------>++>++<<<");
+        assert_eq!(folded("[-]+++[->+<]"), "[-]>+++<");
+        assert_eq!(folded("[-][->+>+<<]"), "[-]");
+        // The total wraps just like the loop would.
+        assert_eq!(
+            folded(&format!("[-]+++[->{}<]", "+".repeat(100))),
+            format!("[-]>{}<", "+".repeat(44))
+        );
+
+        // Without a set right before, the number of times around isn't known.
+        for source in [
+            "++[->+++<]",
+            "[-]++>[->+++<]",
+            "[-]++[->+++<<]",
+            "[-]++[-->+<]",
+        ] {
+            assert_eq!(folded(source), prepared(source).to_string(), "{}", source);
+        }
+
+        // Loops that move left could clamp or wrap at the start of the tape, so they stay.
+        for source in ["[-]++[<--->>+>+<<-]", "[-]+++[-<+>]", "[-]+[-<>>++<]"] {
+            assert_eq!(folded(source), prepared(source).to_string(), "{}", source);
+        }
+        let mut optimized = prepared("+[-]++[-<+++>]");
+        optimized.optimize_fold_known_loops();
+        let mut prgm = ProgramBuilder::new(optimized)
+            .pointer_underflow(PointerUnderflow::Clamp)
+            .build();
+        blank_execute_prgm(&mut prgm).unwrap();
+        let mut unoptimized = ProgramBuilder::new(Instructions::from_string("+[-]++[-<+++>]"))
+            .pointer_underflow(PointerUnderflow::Clamp)
+            .build();
+        blank_execute_prgm(&mut unoptimized).unwrap();
+        assert_eq!(prgm.tape(), unoptimized.tape());
+
+        for source in ["+++++[>+++<-]>[-]+++[>++<-]>.", "[-]+++++[->++>+++<<]>>."] {
+            let mut optimized = Instructions::from_string(source);
+            optimized.optimize();
+            let mut prgm = Program::new(optimized);
+            blank_execute_prgm(&mut prgm).unwrap();
+            let mut unoptimized = Program::from_string(source);
+            blank_execute_prgm(&mut unoptimized).unwrap();
+            assert_eq!(prgm.tape(), unoptimized.tape(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn optimize_move_loops() {
+        let moved = |s| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.optimize_move_loops();
+            instructions.0
+        };
+
+        assert_eq!(moved("[->+<]"), vec![Instruct::Move(1)]);
+        assert_eq!(moved("[<<<+>>>-]"), vec![Instruct::Move(-3)]);
+        assert_eq!(moved("[->+>+<<]"), vec![Instruct::Copy(1, 2)]);
+        assert_eq!(moved("[-<+>>>+<<]"), vec![Instruct::Copy(-1, 2)]);
+
+        // Only loops that take exactly 1 off and add exactly 1 on are moves.
+        for source in [
+            "[->++<]",
+            "[-->+<]",
+            "[+>+<]",
+            "[->+<<]",
+            "[->-<]",
+            "[->+>+>+<<<]",
+            "[-]",
+        ] {
+            assert_eq!(
+                moved(source),
+                Instructions::from_string(source).0,
+                "{}",
+                source
+            );
+        }
+
+        // The canonical copy, through a temporary cell that's moved back afterwards.
+        let mut copy = Instructions::from_string(">+++++[->+>+<<]>>[-<<+>>]<<");
+        copy.optimize();
+        assert_eq!(copy.to_rle(), "> +5 m1,2 >2 m-2 <2");
+
+        for source in [
+            ">+++++[->+>+<<]>>[-<<+>>]",
+            "+++[->>+<<]>>.",
+            "+[->+<]<",
+            "[->+<]",
+        ] {
+            let mut optimized = Instructions::from_string(source);
+            optimized.optimize();
+            assert!(optimized.behaviorally_equal(&Instructions::from_string(source), &[b""]));
+
+            let mut cells = [0; 8];
+            let mut tape = [0; 8];
+            let result = Program::new(optimized).execute_in(&mut cells, || '\0', |_| ());
+            let expected = Program::from_string(source).execute_in(&mut tape, || '\0', |_| ());
+            assert_eq!(result.map_err(|e| e.kind), expected.map_err(|e| e.kind));
+            assert_eq!(cells, tape);
+        }
+
+        // Moves past the start of the tape clamp onto cell 0 instead of panicking.
+        let mut optimized = Instructions::from_string("+++[-<+>]");
+        optimized.optimize();
+        let mut prgm = ProgramBuilder::new(optimized)
+            .pointer_underflow(PointerUnderflow::Clamp)
+            .build();
+        assert!(prgm.execute(|| '\0', |_| ()).is_ok());
+        let mut cells = [0; 2];
+        let mut prgm = Program::new(Instructions(
+            vec![Instruct::MvValue(3), Instruct::Copy(-1, 1)],
+            None,
+        ));
+        prgm.config.pointer_underflow = PointerUnderflow::Clamp;
+        assert!(prgm.execute_in(&mut cells, || '\0', |_| ()).is_ok());
+        assert_eq!(cells, [0, 3]);
+
+        // Growing the tape to the left shifts the cell that's being moved.
+        let mut optimized = Instructions::from_string("++[-<<+>>]");
+        optimized.optimize();
+        let mut prgm = ProgramBuilder::new(optimized)
+            .pointer_underflow(PointerUnderflow::Grow)
+            .build();
+        prgm.execute(|| '\0', |_| ()).unwrap();
+        assert_eq!(prgm.tape(), [2, 0, 0]);
+    }
+
+    #[test]
+    fn blank_input_output() {
+        let mut prgm = Program::from_string(",+.,[.]>-.");
+        assert_eq!(prgm.output_for_blank_input(100).unwrap(), vec![1, 255]);
+        // Running again starts over rather than continuing.
+        assert_eq!(prgm.output_for_blank_input(100).unwrap(), vec![1, 255]);
+
+        let result = Program::from_string("+[.]")
+            .output_for_blank_input(100)
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::StepLimitExceeded));
+        let result = Program::from_string(".<")
+            .output_for_blank_input(100)
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+    }
+
+    #[test]
+    fn behavioral_equality() {
+        let samples: [&[u8]; 4] = [b"", b"\x05", b"\xff\x01", b"hello"];
+        let equal = |a, b| {
+            Instructions::from_string(a).behaviorally_equal(&Instructions::from_string(b), &samples)
+        };
+
+        assert!(equal(",[->+<]>.", ",[>+<-]>."));
+        assert!(equal("+>", "+"));
+        assert!(!equal(",.", ",+."));
+        assert!(!equal(",>.", ",>+<."));
+
+        // Errors have to match, output included.
+        assert!(equal("+<", "++-<"));
+        assert!(equal("<", "[-]<"));
+        assert!(!equal("+.<", "+<"));
+        assert!(!equal("<", ">"));
+
+        // Ones that run forever can't be told apart.
+        assert!(equal("+[]", "+[>+]"));
+
+        let mut optimized = Instructions::from_string(",[->+>++<<]>.>.");
+        optimized.optimize();
+        assert!(
+            optimized.behaviorally_equal(&Instructions::from_string(",[->+>++<<]>.>."), &samples)
+        );
+    }
+
+    #[test]
+    fn tape_capacity_hint() {
+        let source = format!("{}+", ">".repeat(4095));
+        let mut prgm = ProgramBuilder::new(Instructions::from_string(&source))
+            .tape_capacity_hint(4096)
+            .build();
+
+        // The tape never moves, so it was never reallocated.
+        let tape = prgm.cells.as_ptr();
+        blank_execute_prgm(&mut prgm).unwrap();
+        assert_eq!(prgm.tape().len(), 4096);
+        assert_eq!(prgm.cells.as_ptr(), tape);
+        assert!(prgm.cells.capacity() >= 4096);
+
+        // Going past the hint is fine.
+        let mut prgm = ProgramBuilder::new(Instructions::from_string(&source))
+            .tape_capacity_hint(16)
+            .build();
+        blank_execute_prgm(&mut prgm).unwrap();
+        assert_eq!(prgm.tape().len(), 4096);
+    }
+
+    #[test]
+    fn partial_runs() {
+        let mut prgm = Program::from_string(",.,.,.");
+        let mut input = "abc".chars();
+        let mut read = || input.next().unwrap_or('\0');
+
+        let (output, status) = prgm.run_partial(&mut read, 3);
+        assert_eq!(output, b"a");
+        assert_eq!(status.unwrap(), ExecutionStatus::Running);
+        let (output, status) = prgm.run_partial(&mut read, 100);
+        assert_eq!(output, b"bc");
+        assert_eq!(status.unwrap(), ExecutionStatus::Finished);
+
+        // Output from before an error isn't lost.
+        let (output, status) = Program::from_string("+.<").run_partial(|| '\0', 100);
+        assert_eq!(output, vec![1]);
+        assert_eq!(
+            status.map_err(|e| e.kind),
+            Err(BFErrorKind::CellBoundsError)
+        );
+    }
+
+    #[test]
+    fn uses_io() {
+        let uses = |s| {
+            let instructions = Instructions::from_string(s);
+            (instructions.uses_input(), instructions.uses_output())
+        };
+
+        assert_eq!(uses(""), (false, false));
+        assert_eq!(uses("+[->+<]"), (false, false));
+        assert_eq!(uses("[,]"), (true, false));
+        assert_eq!(uses("+++."), (false, true));
+        assert_eq!(uses(",[.,]"), (true, true));
+    }
+
+    #[test]
+    fn extended_input_len() {
+        let source = "?.,?.,,,?.";
+        assert_eq!(
+            Instructions::from_string(source),
+            Instructions::from_string(".,.,,,.")
+        );
+
+        let mut prgm = Program::new(Instructions::from_string_extended(source));
+        assert_eq!(prgm.run_bytes(b"abc").unwrap(), vec![3, 2, 0]);
+        prgm.reset();
+        assert_eq!(prgm.run_bytes(&[0; 300]).unwrap(), vec![255, 255, 255]);
+
+        let input = BasicInput::from_string("xy");
+        prgm.reset();
+        prgm.set_input_len(input.input_remaining());
+        let mut output = vec![];
+        prgm.execute(|| input.input_char(), |c| output.push(c as u8))
+            .unwrap();
+        assert_eq!(output, vec![2, 1, 0]);
+
+        // Without a length, there's nothing to load.
+        prgm.reset();
+        prgm.set_input_len(None);
+        let result = blank_execute_prgm(&mut prgm).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::UnknownInputLength));
+    }
+
+    #[test]
+    fn tape_snapshots() {
+        let mut prgm = Program::from_string("+>++>+++[-]<");
+        blank_execute_prgm(&mut prgm).unwrap();
+        let snapshot = prgm.snapshot_tape();
+        assert_eq!(snapshot, vec![1, 2, 0]);
+
+        // Running from a restored tape carries on with the saved memory.
+        prgm.reset();
+        prgm.restore_tape(&snapshot);
+        blank_execute_prgm(&mut prgm).unwrap();
+        assert_eq!(prgm.tape(), &[2, 4, 0]);
+
+        // A shorter tape grows back to reach the cell pointer.
+        prgm.restore_tape(&[7]);
+        assert_eq!(prgm.tape(), &[7, 0]);
+
+        let mut prgm = ProgramBuilder::new(Instructions::from_string(""))
+            .tape_len(2)
+            .build();
+        prgm.restore_tape(&[1, 2, 3]);
+        assert_eq!(prgm.tape(), &[1, 2]);
+    }
+
+    #[test]
+    fn output_iterator() {
+        let mut prgm = Program::from_string(",[.,]");
+        let mut input = "abc".chars();
+        let output: Vec<u8> = prgm
+            .output_iter(|| input.next().unwrap_or('\0'))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(output, b"abc");
+        assert_eq!(prgm.output_iter(|| '\0').count(), 0);
+
+        // The program only runs as far as the output that's been asked for.
+        let mut prgm = Program::from_string("+.+.+.");
+        assert_eq!(prgm.output_iter(|| '\0').next().unwrap().unwrap(), 1);
+        assert_eq!(prgm.instruction_ptr, 2);
+
+        // An error ends the iteration.
+        let mut prgm = Program::from_string("+.<.");
+        let items: Vec<_> = prgm
+            .output_iter(|| '\0')
+            .map(|item| item.map_err(|e| e.kind))
+            .collect();
+        assert_eq!(items, vec![Ok(1), Err(BFErrorKind::CellBoundsError)]);
+    }
+
+    #[test]
+    fn eof_read_limit() {
+        let run = |source: &str, input: &str| {
+            let mut input = input.chars();
+            ProgramBuilder::new(Instructions::from_string(source))
+                .max_eof_reads(3)
+                .build()
+                .execute(|| input.next().unwrap_or('\0'), |_| ())
+                .map_err(|e| e.kind)
+        };
+
+        // Reads that move the program along aren't counted, however many there are.
+        assert_eq!(run(",,,,,,,,", ""), Ok(()));
+        // Counting 10 zeroes, where the count makes every read a new state.
+        assert_eq!(run("++++++++++[>,<-]", ""), Ok(()));
+
+        // A program waiting forever for nonzero input is stopped, after the first read in the
+        // loop and 3 more from the same state.
+        let mut reads = 0;
+        let result = ProgramBuilder::new(Instructions::from_string("+[,[-]+]"))
+            .max_eof_reads(3)
+            .build()
+            .execute(
+                || {
+                    reads += 1;
+                    '\0'
+                },
+                |_| (),
+            )
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::InputExhausted));
+        assert_eq!(reads, 5);
+        assert_eq!(run("+[,[-]+]", "abc"), Err(BFErrorKind::InputExhausted));
+        // Going round a bigger circle is caught too.
+        assert_eq!(run("+[>,<,+]", ""), Err(BFErrorKind::InputExhausted));
+
+        // The tape of execute_in() counts as the state too.
+        let mut tape = [0; 4];
+        let result = ProgramBuilder::new(Instructions::from_string("+[,[-]+]"))
+            .max_eof_reads(3)
+            .build()
+            .execute_in(&mut tape, || '\0', |_| ())
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::InputExhausted));
+    }
+
+    #[test]
+    fn description() {
+        let summary = Instructions::from_string(",[.,]<").describe();
+        let fields: Vec<(&str, &str)> = summary
+            .lines()
+            .map(|line| line.split_once(": ").unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("instructions", "6"),
+                ("loops", "1"),
+                ("max nesting", "1"),
+                ("uses input", "yes"),
+                ("uses output", "yes"),
+                ("output length", "unknown"),
+                ("pointer shift", "-1"),
+                ("complexity", "2"),
+            ]
+        );
+
+        assert_eq!(
+            Instructions::from_string("").describe(),
+            "instructions: 0\nloops: 0\nmax nesting: 0\nuses input: no\nuses output: no\n\
+             output length: 0\npointer shift: 0\ncomplexity: 1\n"
+        );
+    }
+
+    #[test]
+    fn fixed_buffer_execution() {
+        let source = "++++++++[>++++++++<-]>+.>,.<<";
+        let mut expected = Program::from_string(source);
+        let mut expected_output = vec![];
+        let result = expected
+            .execute(|| 'z', |c| expected_output.push(c))
+            .map_err(|e| e.kind);
+
+        let mut prgm = Program::from_string(source);
+        let mut tape = [9; 4];
+        let mut output = vec![];
+        let result_in = prgm
+            .execute_in(&mut tape, || 'z', |c| output.push(c))
+            .map_err(|e| e.kind);
+        assert_eq!(result_in, result);
+        assert_eq!(output, expected_output);
+        assert_eq!(tape, [0, 65, b'z', 0]);
+        assert!(prgm.tape().is_empty());
+
+        // The buffer is the whole tape, whatever the underflow policy.
+        let run = |policy, source: &str, tape: &mut [u8]| {
+            ProgramBuilder::new(Instructions::from_string(source))
+                .pointer_underflow(policy)
+                .build()
+                .execute_in(tape, || '\0', |_| ())
+                .map_err(|e| e.kind)
+        };
+        let mut tape = [0; 3];
+        assert_eq!(run(PointerUnderflow::Wrap, "<+<+", &mut tape), Ok(()));
+        assert_eq!(tape, [0, 1, 1]);
+        assert_eq!(
+            run(PointerUnderflow::Clamp, "<+>>>", &mut tape),
+            Err(BFErrorKind::CellBoundsError)
+        );
+        assert_eq!(tape, [1, 0, 0]);
+        assert_eq!(
+            run(PointerUnderflow::Grow, "<", &mut tape),
+            Err(BFErrorKind::CellBoundsError)
+        );
+        assert_eq!(
+            run(PointerUnderflow::Error, "+", &mut []),
+            Err(BFErrorKind::CellBoundsError)
+        );
+
+        // A shorter tape length leaves the rest of the buffer alone.
+        let mut tape = [7; 4];
+        let result = ProgramBuilder::new(Instructions::from_string("+>+>+"))
+            .tape_len(2)
+            .build()
+            .execute_in(&mut tape, || '\0', |_| ())
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+        assert_eq!(tape, [1, 1, 7, 7]);
+    }
+
+    #[test]
+    fn reader_parsing() {
+        let source = format!("{}[->+<] comment ü >.", "+".repeat(10000));
+        let mut progress = vec![];
+        let instructions =
+            Instructions::from_reader_with_progress(source.as_bytes(), |read| progress.push(read))
+                .unwrap();
+        assert_eq!(instructions, Instructions::from_string(&source));
+        assert_eq!(progress, vec![8192, source.len()]);
+
+        // Invalid UTF-8 is just more comments.
+        let source: &[u8] = &[b'+', 0xff, b'.'];
+        assert_eq!(
+            Instructions::from_reader(source).unwrap(),
+            Instructions::from_string("+.")
+        );
+
+        struct FailingReader;
+        impl std::io::Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("nope"))
+            }
+        }
+        let result = Instructions::from_reader(FailingReader).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::IoError));
+    }
+
+    #[test]
+    fn normalizing() {
+        let normalized = |s| {
+            let mut instructions = Instructions::from_string(s);
+            instructions.normalize();
+            instructions.to_string()
+        };
+
+        // Messy versions of the same program all come out the same.
+        for source in [
+            "+[->+<]>.",
+            "+-+[->+<]><>.",
+            "><+[-><>+<]>.<>",
+            "[]+[->+<][-+]>.",
+            "+[->+<][]>.",
+            "+[->+<][][]<>>.",
+        ] {
+            assert_eq!(normalized(source), "+[->+<]>.", "{}", source);
+        }
+
+        // Cancelling can expose more to cancel.
+        assert_eq!(normalized("+>><<-"), "");
+        assert_eq!(normalized("+>[-]<>[]<-+"), "+>[-]<");
+
+        // An empty loop could run forever if the cell isn't 0, so it stays.
+        assert_eq!(normalized("+[]"), "+[]");
+        assert_eq!(normalized(",[]"), ",[]");
+
+        // Moves still cancel where the original would go out of bounds, as documented.
+        assert_eq!(normalized("<>+"), "+");
+        let result = Program::from_string("<>+").execute(|| '\0', |_| ());
+        assert_eq!(
+            result.map_err(|e| e.kind),
+            Err(BFErrorKind::CellBoundsError)
+        );
+    }
+
+    #[test]
+    fn public_ops() {
+        let instructions = Instructions::from_string_extended("><+-.,?[]");
+        assert_eq!(
+            instructions.ops().collect::<Vec<_>>(),
+            vec![
+                Op::MovePtr(1),
+                Op::MovePtr(-1),
+                Op::Add(1),
+                Op::Add(-1),
+                Op::Output,
+                Op::Input,
+                Op::InputLen,
+                Op::LoopStart,
+                Op::LoopEnd,
+            ]
+        );
+        for (idx, op) in instructions.ops().enumerate() {
+            assert_eq!(instructions.op_at(idx), Some(op));
+        }
+        assert_eq!(instructions.op_at(instructions.len()), None);
+
+        let optimized = Instructions::from_rle("=4 m-1 m1,2").unwrap();
+        assert_eq!(
+            optimized.ops().collect::<Vec<_>>(),
+            vec![Op::Set(4), Op::Move(-1), Op::Copy(1, 2)]
+        );
+    }
+
+    #[test]
+    fn source_positions() {
+        let source = "Add: ++\r\n\tü [->+<]\n\n.";
+        let instructions = Instructions::from_string_with_positions(source);
+        assert_eq!(instructions, Instructions::from_string(source));
+
+        let positions: Vec<_> = (0..instructions.len())
+            .map(|idx| instructions.source_position(idx).unwrap())
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (1, 6),
+                (1, 7),
+                (2, 4),
+                (2, 5),
+                (2, 6),
+                (2, 7),
+                (2, 8),
+                (2, 9),
+                (4, 1)
+            ]
+        );
+        assert_eq!(instructions.source_position(instructions.len()), None);
+
+        // Changed instructions don't line up with the source anymore.
+        let mut optimized = instructions.clone();
+        optimized.optimize();
+        assert_eq!(optimized.source_position(0), None);
+        let mut normalized = instructions.clone();
+        normalized.normalize();
+        assert_eq!(normalized.source_position(0), None);
+    }
+
+    #[test]
+    fn cell_watch() {
+        let mut prgm = Program::from_string("+++[>+++<-]>[-]+");
+        let mut run = |idx, value, max_steps| {
+            prgm.run_until_cell(idx, value, max_steps, || '\0', |_| ())
+                .map_err(|e| e.kind)
+        };
+
+        // Each time the cell gets the value is found in turn.
+        assert_eq!(run(0, 2, 100), Ok(WatchStatus::Reached));
+        assert_eq!(run(1, 6, 100), Ok(WatchStatus::Reached));
+        assert_eq!(run(1, 0, 100), Ok(WatchStatus::Reached));
+        assert_eq!(run(1, 1, 1), Ok(WatchStatus::StepLimit));
+        assert_eq!(run(1, 1, 100), Ok(WatchStatus::Reached));
+        assert_eq!(run(1, 1, 100), Ok(WatchStatus::Finished));
+
+        // Cells that haven't been reached are 0, and the check only happens after steps.
+        let mut prgm = Program::from_string("+>>");
+        let status = prgm.run_until_cell(5, 0, 10, || '\0', |_| ()).unwrap();
+        assert_eq!(status, WatchStatus::Reached);
+        assert_eq!(prgm.instruction_ptr, 1);
+
+        let result = Program::from_string("+<")
+            .run_until_cell(0, 7, 10, || '\0', |_| ())
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+    }
+
+    #[test]
+    fn cell_formats() {
+        let formatted = |format: CellFormat, values: &[u8]| {
+            let mut output = String::new();
+            for &value in values {
+                format.format(value as char, |c| output.push(c));
+            }
+            output
+        };
+
+        let values = [0, 1, 127, 128, 255];
+        assert_eq!(
+            formatted(CellFormat::Char, &values),
+            "\0\u{1}\u{7f}\u{80}\u{ff}"
+        );
+        assert_eq!(
+            formatted(CellFormat::Unsigned, &values),
+            "0\n1\n127\n128\n255\n"
+        );
+        assert_eq!(
+            formatted(CellFormat::Signed, &values),
+            "0\n1\n127\n-128\n-1\n"
+        );
+        assert_eq!(CellFormat::default(), CellFormat::Char);
+    }
+
+    #[test]
+    fn chained_input() {
+        let input = BasicInput::chain(vec![
+            BasicInput::from_string("ab"),
+            BasicInput::chain(vec![]),
+            BasicInput::from_string("c"),
+        ]);
+        assert_eq!(input.input_remaining(), Some(3));
+
+        let read: String = (0..4).map(|_| input.input_char()).collect();
+        assert_eq!(read, "abc\0");
+        assert_eq!(input.input_remaining(), Some(0));
+
+        // Endless sources are never moved past.
+        let input = BasicInput::chain(vec![
+            BasicInput::from_string("a"),
+            BasicInput::cycling(&['b']),
+            BasicInput::from_string("c"),
+        ]);
+        assert_eq!(input.input_remaining(), None);
+        let read: String = (0..4).map(|_| input.input_char()).collect();
+        assert_eq!(read, "abbb");
+    }
+
+    #[test]
+    fn basic_block_structure() {
+        let blocks = Instructions::from_string(",[>+[-]<-]>.")
+            .basic_blocks()
+            .unwrap();
+        let ranges: Vec<_> = blocks.iter().map(|b| b.range.clone()).collect();
+        assert_eq!(ranges, vec![0..1, 2..4, 5..6, 7..9, 10..12]);
+
+        let successors: Vec<_> = blocks.iter().map(|b| b.successors.clone()).collect();
+        assert_eq!(
+            successors,
+            vec![vec![1, 4], vec![2, 3], vec![2, 3], vec![1, 4], vec![]]
+        );
+        let predecessors: Vec<_> = blocks.iter().map(|b| b.predecessors.clone()).collect();
+        assert_eq!(
+            predecessors,
+            vec![vec![], vec![0, 3], vec![1, 2], vec![1, 2], vec![0, 3]]
+        );
+
+        // No loops is one block with nowhere to go, even with no instructions at all.
+        let blocks = Instructions::from_string("").basic_blocks().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].range.is_empty() && blocks[0].successors.is_empty());
+
+        for (source, kind) in [
+            ("[", BFErrorKind::MissingClose),
+            ("]", BFErrorKind::MissingOpen),
+        ] {
+            let result = Instructions::from_string(source)
+                .basic_blocks()
+                .map_err(|e| e.kind);
+            assert_eq!(result, Err(kind));
+        }
+    }
+
+    #[test]
+    fn profile_reports() {
+        let mut prgm = Program::from_string("+[>++[-]<-]");
+        assert_eq!(prgm.profile_report(5), None);
+
+        prgm.enable_profiling();
+        prgm.execute(|| '\0', |_| ()).unwrap();
+        assert_eq!(
+            prgm.profile_report(2).unwrap(),
+            "hottest instructions:\n\
+             \x20          3       5  [\n\
+             \x20          2       1  [\n\
+             hottest loops:\n\
+             \x20          2   5-7     [-]\n\
+             \x20          1   1-10    [>++[-]<-]\n"
+        );
+
+        let mut prgm = Program::from_string(&format!("+[{}{}-]", ">".repeat(25), "<".repeat(25)));
+        prgm.enable_profiling();
+        prgm.execute(|| '\0', |_| ()).unwrap();
+        let report = prgm.profile_report(10).unwrap();
+        let code = format!("[{}{}...", ">".repeat(25), "<".repeat(14));
+        assert!(report.ends_with(&format!("1-53    {}\n", code)));
+    }
+
+    #[test]
+    fn execution_timeout() {
+        use std::time::Duration;
+
+        let mut prgm = Program::from_string("+[>+<]");
+        let result = prgm
+            .execute_with_timeout(Duration::from_millis(20), || '\0', |_| ())
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::Timeout));
+        // It got into the loop before being stopped.
+        assert_eq!(prgm.cells.len(), 2);
+
+        // A program that finishes in time runs just like with execute().
+        let mut prgm = Program::from_string("++++++++[>++++++++<-]>+.");
+        let mut output = String::new();
+        prgm.execute_with_timeout(Duration::from_secs(60), || '\0', |c| output.push(c))
+            .unwrap();
+        assert_eq!(output, "A");
+
+        // The clock isn't checked before the first steps, so even a zero timeout lets short
+        // programs finish.
+        for code in ["", "+"] {
+            let mut prgm = Program::from_string(code);
+            let result = prgm
+                .execute_with_timeout(Duration::ZERO, || '\0', |_| ())
+                .map_err(|e| e.kind);
+            assert_eq!(result, Ok(()));
+        }
+        let mut prgm = Program::from_string("+[]");
+        let result = prgm
+            .execute_with_timeout(Duration::ZERO, || '\0', |_| ())
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::Timeout));
+    }
+
+    #[test]
+    fn tape_growth_hook() {
+        use std::sync::Mutex;
+
+        let run = |mut prgm: Program| {
+            let lengths = Arc::new(Mutex::new(vec![]));
+            let recorded = Arc::clone(&lengths);
+            prgm.on_tape_growth(move |len| recorded.lock().unwrap().push(len));
+            prgm.execute(|| '\0', |_| ()).unwrap();
+            let lengths = lengths.lock().unwrap().clone();
+            lengths
+        };
+
+        let mut instructions = Instructions::from_string(">>>>,<<<<,>>>>>>>>,");
+        instructions.optimize();
+        assert_eq!(run(Program::new(instructions)), vec![1, 5, 9]);
+
+        // Growing to the left reports the whole new length too.
+        let prgm = ProgramBuilder::new(Instructions::from_string("+<<+"))
+            .pointer_underflow(PointerUnderflow::Grow)
+            .build();
+        assert_eq!(run(prgm).last(), Some(&3));
+
+        // Wrapping onto the far end of a fixed tape grows it all at once.
+        let prgm = ProgramBuilder::new(Instructions::from_string("+<+"))
+            .tape_len(3)
+            .pointer_underflow(PointerUnderflow::Wrap)
+            .build();
+        assert_eq!(run(prgm), vec![1, 3]);
+    }
+
+    #[test]
+    fn expected_output() {
+        let mut prgm = Program::from_string(",.,.");
+        assert_eq!(prgm.expect("hi", "hi"), Ok(()));
+
+        let mismatch = prgm.expect("hi", "hit").unwrap_err();
+        assert_eq!(mismatch.position, 2);
+        assert_eq!(mismatch.error, None);
+        assert_eq!(
+            mismatch.to_string(),
+            "The output differs at char 2.\n\
+             Expected output: \"hit\"\n\
+             Actual output:   \"hi\""
+        );
 
-        Ok(())
+        // Getting the output right doesn't count if the program then fails.
+        let mut prgm = Program::from_string(",.<");
+        let mismatch = prgm.expect("a", "a").unwrap_err();
+        assert_eq!(mismatch.error, Some(BFErrorKind::CellBoundsError));
+        assert_eq!(mismatch.position, 1);
+        assert!(mismatch
+            .to_string()
+            .starts_with("The program stopped with an error: Tried to access cell"));
     }
 
-    /// Check the cells length and make sure it's long enough such that cell_ptr is a valid index.
-    fn validate_cells_length(&mut self) {
-        while self.cells.len() <= self.cell_ptr {
-            self.cells.push(0);
+    #[test]
+    fn flushing_at_end() {
+        let mut output = BasicOutput::new();
+        output.set_flush_mode(FlushMode::AtEnd);
+        output.set_newline_mode(NewlineMode::Crlf);
+        for c in "a\nb".chars() {
+            output.print_char(c);
         }
-    }
+        assert_eq!(output.buffer, "a\r\nb");
 
-    /// Increment/decrement current cell value by `amount`.
-    ///
-    /// Multiple subsequent calls to this can be replaced by a single call with the sum in
-    /// order to optimize.
-    fn move_cell_value(&mut self, amount: &isize) {
-        self.cells[self.cell_ptr] = self.cells[self.cell_ptr].wrapping_add_signed(*amount as i8);
+        // Going back to flushing every char writes out what was held back.
+        output.set_flush_mode(FlushMode::EveryChar);
+        assert!(output.buffer.is_empty());
+        output.print_char('c');
+        assert!(output.buffer.is_empty());
     }
 
-    /// Using the input closure, retrieve a character into the cells at cell ptr.
-    fn input_cell<F>(&mut self, input: F) -> Result<()>
-    where
-        F: FnOnce() -> char,
-    {
-        let in_char = input();
-        let in_byte = in_char as u32;
+    #[test]
+    fn shebang_lines() {
+        let source = "#!/usr/bin/env -S rbf -o\n+[-].";
+        let expected = Instructions::from_string("+[-].");
+        assert_eq!(Instructions::from_string(source), expected);
+        assert_eq!(Instructions::from_string_extended(source), expected);
+        assert_eq!(
+            Instructions::from_string_limited(source, 5).unwrap(),
+            expected
+        );
+        assert_eq!(
+            Instructions::from_reader(source.as_bytes()).unwrap(),
+            expected
+        );
+        assert_eq!(Instructions::from_source_with_input(source).0, expected);
+        assert_eq!(
+            Instructions::from_source_with_input_extended(source).0,
+            expected
+        );
 
-        // Gotta check to make sure it's only 8 bit int
-        if in_byte < 256 {
-            self.cells[self.cell_ptr] = in_byte as u8;
-            Ok(())
-        } else {
-            Err(BFError {
-                kind: BFErrorKind::InvalidInput,
-            })
-        }
-    }
+        let (instructions, comments) = Instructions::from_string_with_comments(source);
+        assert_eq!(instructions, expected);
+        assert_eq!(instructions.to_string_with_comments(&comments), source);
 
-    /// Output a character at current cell into the output closure.
-    fn output_cell<F>(&self, output: F)
-    where
-        F: FnOnce(char),
-    {
-        output(self.cells[self.cell_ptr] as char);
-    }
+        let positions = Instructions::from_string_with_positions(source);
+        assert_eq!(positions.source_position(0), Some((2, 1)));
 
-    /// Handle the open loop instructions, `[`.
-    fn open_loop(&mut self) -> Result<()> {
-        if self.cells[self.cell_ptr] > 0 {
-            self.loop_stack.push(self.instruction_ptr);
-        } else {
-            self.move_to_closed_loop()?;
-        }
+        let (_, diagnostics) = Instructions::from_string_with_diagnostics("#!rbf\n+x");
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                position: 7,
+                character: 'x'
+            }]
+        );
 
-        Ok(())
+        // Only a #! at the very start is a shebang.
+        assert_eq!(Instructions::from_string(" #!-\n").len(), 1);
+        assert_eq!(Instructions::from_reader(&b"#-!"[..]).unwrap().len(), 1);
+        assert_eq!(
+            Instructions::from_string("#!+-"),
+            Instructions::from_string("")
+        );
+        assert!(validate("#![\n").is_ok());
     }
 
-    /// Handle the close loop instruction, ']'.
-    fn close_loop(&mut self) -> Result<()> {
-        self.instruction_ptr = match self.loop_stack.pop() {
-            Some(n) => n,
-            None => {
-                return Err(BFError {
-                    kind: BFErrorKind::MissingOpen,
-                });
-            }
-        } - 1;
-        Ok(())
+    #[test]
+    fn breaking_loops() {
+        let mut prgm = Program::from_string("+[>+[+]<-]");
+        let result = prgm.break_loop().map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::NotInLoop));
+
+        // Step into the inner loop, then break out of it and let the outer loop finish.
+        while prgm.loop_stack().len() < 2 {
+            prgm.step(|| '\0', |_| ()).unwrap();
+        }
+        assert_eq!(prgm.loop_stack(), &[1, 4]);
+        prgm.break_loop().unwrap();
+        assert_eq!(prgm.loop_stack(), &[1]);
+        prgm.execute(|| '\0', |_| ()).unwrap();
+        assert_eq!(prgm.tape(), &[0, 1]);
+
+        // Loops without a ] can't be broken out of.
+        let mut prgm = Program::from_string("+[");
+        prgm.step(|| '\0', |_| ()).unwrap();
+        prgm.step(|| '\0', |_| ()).unwrap();
+        let result = prgm.break_loop().map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingClose));
+        assert_eq!(prgm.loop_stack(), &[1]);
     }
 
-    /// Find the associated close loop to our current open loop and go there.
-    fn move_to_closed_loop(&mut self) -> Result<()> {
-        let mut loopstack: Vec<usize> = vec![];
-        let mut current_instruction = self.instruction_ptr + 1; // We don't want to add
-                                                                // current open loop to stack
-        loop {
-            let instruction = match self.instructions.0.get(current_instruction) {
-                Some(i) => i,
-                None => {
-                    return Err(BFError {
-                        kind: BFErrorKind::MissingClose,
-                    });
-                }
-            };
+    #[test]
+    fn parsing_many_programs() {
+        // Separators can be made of commands, since only whole lines count.
+        let src = "+[-]\n---\n  ---  \n,.--- not a separator\n---";
+        let programs = Instructions::parse_many(src, "---").unwrap();
+        assert_eq!(
+            programs,
+            vec![
+                Instructions::from_string("+[-]"),
+                Instructions::from_string(""),
+                Instructions::from_string(",.---"),
+                Instructions::from_string(""),
+            ]
+        );
 
-            match instruction {
-                Instruct::OpenLoop => loopstack.push(current_instruction),
-                Instruct::CloseLoop => {
-                    if loopstack.pop().is_none() {
-                        self.instruction_ptr = current_instruction;
-                        return Ok(());
-                    }
-                }
-                _ => (),
-            }
+        assert_eq!(Instructions::parse_many("", "===").unwrap().len(), 1);
 
-            current_instruction += 1;
-        }
+        // Loops can't span programs.
+        let result = Instructions::parse_many("[\n===\n]", "===").map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingClose));
     }
-}
 
-/// Basic input operation for BF.
-///
-/// Provides a method that can be used for the input of the BF program.
-///
-/// # Examples
-///
-/// ```rust
-/// # use rbf::*;
-/// let basic_input = BasicInput::new();
-///
-/// // Read single char from terminal.
-/// let c = basic_input.input_char();
-/// ```
-pub struct BasicInput {
-    term: Term,
-}
+    #[test]
+    fn step_counting() {
+        let mut prgm = Program::from_string(",[->+<]");
+        assert_eq!(prgm.count_steps(b"\x03", 1000).unwrap(), 2 + 3 * 6);
+        // Counting starts over each time.
+        assert_eq!(prgm.count_steps(b"\x03", 1000).unwrap(), 20);
+        assert_eq!(prgm.count_steps(b"\x03", 20).unwrap(), 20);
 
-/// Basic output operation for BF.
-///
-/// Provides a method for output of the BF program.
-///
-/// # Examples
-///
-/// ```rust
-/// # use rbf::*;
-/// let mut basic_output = BasicOutput::new();
-///
-/// // Output single char to terminal.
-/// basic_output.print_char('a');
-/// ```
-pub struct BasicOutput {
-    stdout: std::io::Stdout,
-}
+        let result = prgm.count_steps(b"\x03", 19).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::StepLimitExceeded));
 
-impl BasicInput {
-    /// Create new BasicInput struct.
-    pub fn new() -> Self {
-        Self {
-            term: Term::stdout(),
-        }
+        // Optimizing turns the loop into a single step.
+        let mut instructions = Instructions::from_string(",[->+<]");
+        instructions.optimize();
+        assert_eq!(
+            Program::new(instructions)
+                .count_steps(b"\x03", 1000)
+                .unwrap(),
+            2
+        );
     }
 
-    /// Blank input, handy when benchmarking and input doesn't matter.
-    pub fn blank(&self) -> char {
-        ' '
-    }
+    #[test]
+    fn cell_radixes() {
+        let radixes = [Radix::Binary, Radix::Octal, Radix::Decimal, Radix::Hex];
+        let formatted: Vec<_> = radixes.iter().map(|r| r.format(255)).collect();
+        assert_eq!(formatted, ["11111111", "377", "255", "ff"]);
+        let padded: Vec<_> = radixes.iter().map(|r| r.format_padded(5)).collect();
+        assert_eq!(padded, ["00000101", "005", "005", "05"]);
+        assert_eq!(Radix::default(), Radix::Decimal);
 
-    /// Input single char from terminal.
-    ///
-    /// If the terminal is not an interactive terminal, the terminal from the `console` crate
-    /// returns an error from `read_char()`. In this situation, this function will return a char
-    /// with ascii value of 0.
-    pub fn input_char(&self) -> char {
-        match self.term.read_char() {
-            Ok(c) => c,
-            Err(_) => 0u8 as char,
+        let mut output = String::new();
+        for c in ['\u{a}', '\u{80}', '\u{ff}'] {
+            CellFormat::Signed.format_in(Radix::Hex, c, |c| output.push(c));
         }
+        assert_eq!(output, "a\n-80\n-1\n");
+
+        let mut prgm = Program::from_string("+++>++<");
+        prgm.execute(|| ' ', |_| ()).unwrap();
+        assert_eq!(
+            prgm.dump_tape(Radix::Octal),
+            format!("00000000 >003 002{}  |..|\n", " ".repeat(4 * 14 + 1))
+        );
+        assert_eq!(prgm.dump_tape(Radix::Hex), prgm.hex_dump());
     }
-}
 
-impl BasicOutput {
-    /// Create new BasicOutput struct.
-    pub fn new() -> Self {
-        Self {
-            stdout: std::io::stdout(),
+    #[test]
+    fn slicing_instructions() {
+        let instructions = Instructions::from_string_with_positions("+[>[-]\n<-]");
+        assert_eq!(
+            instructions.slice(0..0),
+            Some(Instructions::from_string(""))
+        );
+        assert_eq!(
+            instructions.slice(0..instructions.len()),
+            Some(instructions.clone())
+        );
+
+        let inner = instructions.slice(3..6).unwrap();
+        assert_eq!(inner.to_string(), "[-]");
+        assert_eq!(inner.source_position(0), Some((1, 4)));
+
+        for range in [1..3, 4..8, 0..10, 10..10] {
+            assert_eq!(instructions.slice(range.clone()), None, "{:?}", range);
         }
     }
 
-    /// Blank output, handy when benchmarking and output doesn't matter.
-    pub fn blank(&self, _: char) {}
+    #[test]
+    fn current_ops() {
+        let mut prgm = Program::from_string("+[-]");
+        let mut ops = vec![];
+        while let Some(op) = prgm.current_op() {
+            ops.push((prgm.instruction_pointer(), op));
+            prgm.step(|| ' ', |_| ()).unwrap();
+        }
 
-    /// Print single char to terminal.
-    pub fn print_char(&mut self, c: char) {
-        print!("{}", c);
-        self.stdout.flush().expect("Error flushing output");
+        // The `[` runs again each time around the loop.
+        assert_eq!(
+            ops,
+            vec![
+                (0, Op::Add(1)),
+                (1, Op::LoopStart),
+                (2, Op::Add(-1)),
+                (3, Op::LoopEnd),
+                (1, Op::LoopStart),
+            ]
+        );
+        assert_eq!(prgm.instruction_pointer(), 4);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn tape_budgets() {
+        let src = "#!/usr/bin/env rbf\r\n@tape  3 \n@tape 4\n@empty\n+>+";
+        assert_eq!(header_field(src, "tape"), Some("3"));
+        assert_eq!(header_field(src, "empty"), Some(""));
+        assert_eq!(header_field(src, "ta"), None);
+        // The header stops at the first line that isn't a field.
+        assert_eq!(header_field("+\n@tape 3", "tape"), None);
+        assert_eq!(header_field("\n@tape 3", "tape"), None);
 
-    /// Execute program with blanks without boilerplate.
-    ///
-    /// Will panic in the case of a BF error.
-    fn blank_execute_prgm(prgm: &mut Program) -> Result<()> {
-        let input = BasicInput::new();
-        let output = BasicOutput::new();
+        let mut prgm = Program::from_string(src);
+        assert_eq!(prgm.check_tape_budget(2, 100).unwrap(), None);
+        assert_eq!(
+            prgm.check_tape_budget(1, 100).unwrap(),
+            Some(BudgetExceeded {
+                budget: 1,
+                step: 2,
+                cell: 1,
+            })
+        );
 
-        prgm.execute(|| input.blank(), |c| output.blank(c))
+        // Going over is found even in a program that would never finish.
+        let mut prgm = Program::from_string("+[>+]");
+        let exceeded = prgm.check_tape_budget(10, 1000).unwrap().unwrap();
+        assert_eq!(exceeded.cell, 10);
+        let result = prgm.check_tape_budget(usize::MAX, 1000).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::StepLimitExceeded));
+
+        // The budget can come straight from the header.
+        let mut prgm = Program::from_string(src);
+        let exceeded = prgm.check_declared_tape_budget("@tape 1", 100).unwrap();
+        assert_eq!(exceeded.map(|e| (e.step, e.cell)), Some((2, 1)));
+        assert_eq!(prgm.check_declared_tape_budget(src, 100).unwrap(), None);
+        assert_eq!(prgm.check_declared_tape_budget("+>+", 100).unwrap(), None);
+        let result = prgm
+            .check_declared_tape_budget("@tape lots", 100)
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::InvalidFormat));
     }
 
     #[test]
-    fn str_to_instructions() {
-        let instructions_str = "+-<>s[]comment,."; // the 's' and the word 'comment' are
-                                                   // comments and should be ignored in the output
-        let instructions = Instructions::from_string(instructions_str);
+    fn from_source() {
+        let source = "+[->+<] comment";
         assert_eq!(
-            instructions,
-            Instructions(vec![
-                Instruct::MvValue(1),
-                Instruct::MvValue(-1),
-                Instruct::MvPtr(-1),
-                Instruct::MvPtr(1),
-                Instruct::OpenLoop,
-                Instruct::CloseLoop,
-                Instruct::Input,
-                Instruct::Output,
-            ])
+            Instructions::from(source.to_string()),
+            Instructions::from_string(source)
+        );
+        assert_eq!(
+            Program::from(source.to_string()),
+            Program::from_string(source)
         );
     }
 
     #[test]
-    fn create_program() {
-        let instructions = Instructions::from_string("+-><[],.");
-        let new_program = Program::new(instructions.clone());
-        let custom_program = Program {
-            instructions,
-            instruction_ptr: 0,
-            cell_ptr: 0,
-            cells: vec![],
-            loop_stack: vec![],
-        };
+    fn stepping_back() {
+        let mut prgm = Program::from_string(",[.,]");
+        assert!(!prgm.step_back());
+        prgm.enable_history(3);
 
-        assert_eq!(new_program, custom_program);
+        let mut input = "ab".chars();
+        let mut output = String::new();
+        prgm.execute(|| input.next().unwrap_or('\0'), |c| output.push(c))
+            .unwrap();
+        assert_eq!(output, "ab");
+
+        // Only the last 3 steps can be undone: the `[` that skipped the loop, the `]` and the
+        // last `,`.
+        for ip in [1, 4, 3] {
+            assert!(prgm.step_back());
+            assert_eq!(prgm.instruction_pointer(), ip);
+        }
+        assert!(!prgm.step_back());
+        assert_eq!(prgm.tape(), b"b");
+        assert_eq!(prgm.loop_stack(), &[1]);
+
+        prgm.reset();
+        assert!(!prgm.step_back());
+
+        // A step that fails isn't recorded, so stepping back undoes the one before it.
+        let mut prgm = Program::from_string("+<");
+        prgm.enable_history(10);
+        let start = prgm.tape().to_vec();
+        prgm.step(|| ' ', |_| ()).unwrap();
+        let result = prgm.step(|| ' ', |_| ()).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+        assert!(prgm.step_back());
+        assert_eq!((prgm.instruction_pointer(), prgm.tape()), (0, &start[..]));
+        assert!(!prgm.step_back());
+
+        let mut prgm = Program::from_string("+");
+        prgm.enable_history(10);
+        prgm.step(|| ' ', |_| ()).unwrap();
+        assert!(prgm.step(|| ' ', |_| ()).is_err());
+        assert!(prgm.step_back());
+        assert!(!prgm.step_back());
+
+        // Only the changes are kept, and undoing them puts back growth at either end.
+        let mut prgm = ProgramBuilder::new(Instructions::from_string(">+<<-[->>+<<]"))
+            .pointer_underflow(PointerUnderflow::Grow)
+            .build();
+        prgm.enable_history(10_000);
+        let mut tapes = vec![];
+        while !prgm.done().unwrap() {
+            tapes.push((
+                prgm.tape().to_vec(),
+                prgm.cell_pointer(),
+                prgm.loop_stack().to_vec(),
+            ));
+            prgm.step(|| ' ', |_| ()).unwrap();
+        }
+        while let Some(expected) = tapes.pop() {
+            assert!(prgm.step_back());
+            assert_eq!(
+                (
+                    prgm.tape().to_vec(),
+                    prgm.cell_pointer(),
+                    prgm.loop_stack().to_vec()
+                ),
+                expected
+            );
+        }
+        assert!(!prgm.step_back());
     }
 
     #[test]
-    fn optimize_multiples() {
-        use Instruct::*;
+    fn stepping_back_iterations() {
+        let result = Program::from_string("+")
+            .step_back_iteration()
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::NotInLoop));
 
-        let mut instructions = Instructions::from_string("++++>>++++-<>>>-<");
-        instructions.optimize_combine_multiples();
+        // Two runs of an inner loop, each going around twice.
+        let mut prgm = Program::from_string("++[>++[>+<-]<-]");
+        prgm.enable_history(1000);
+        let mut inner_starts = vec![];
+        while !prgm.done().unwrap() {
+            prgm.step(|| ' ', |_| ()).unwrap();
+            if prgm.instruction_pointer() == 7 {
+                inner_starts.push(prgm.tape().to_vec());
+            }
+        }
+        assert_eq!(inner_starts.len(), 4);
 
-        let expected = Instructions(vec![
-            MvValue(4),
-            MvPtr(2),
-            MvValue(3),
-            MvPtr(2),
-            MvValue(-1),
-            MvPtr(-1),
-        ]);
+        // Stop partway through the last time around, then go back through that run only.
+        prgm.reset();
+        while prgm.tape() != &inner_starts[3][..] || prgm.instruction_pointer() != 7 {
+            prgm.step(|| ' ', |_| ()).unwrap();
+        }
+        for _ in 0..2 {
+            prgm.step(|| ' ', |_| ()).unwrap();
+        }
 
-        assert_eq!(instructions, expected);
+        assert!(prgm.step_back_iteration().unwrap());
+        assert_eq!(prgm.tape(), &inner_starts[3][..]);
+        assert_eq!(prgm.loop_stack(), &[2, 6]);
+        assert!(prgm.step_back_iteration().unwrap());
+        assert_eq!(prgm.tape(), &inner_starts[2][..]);
+        assert!(!prgm.step_back_iteration().unwrap());
+        assert_eq!(prgm.tape(), &inner_starts[2][..]);
+    }
 
-        let mut instructions = Instructions::from_string("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
-        instructions.optimize_combine_multiples();
+    #[test]
+    fn complexities() {
+        let complexity = |s| Instructions::from_string(s).complexity();
+        assert_eq!(complexity(""), 1);
+        assert_eq!(complexity("+>-<.,"), 1);
+        assert_eq!(complexity("[]"), 2);
+        assert_eq!(complexity("[[[]]]"), complexity("[][][]"));
+        // Only the opening brackets count, even when they don't match.
+        assert_eq!(complexity("[[]"), 3);
 
-        let expected = Instructions(vec![
-            MvValue(8),
-            OpenLoop,
-            MvPtr(1),
-            MvValue(4),
-            OpenLoop,
-            MvPtr(1),
-            MvValue(2),
-            MvPtr(1),
-            MvValue(3),
-            MvPtr(1),
-            MvValue(3),
-            MvPtr(1),
-            MvValue(1),
-            MvPtr(-4),
-            MvValue(-1),
-            CloseLoop,
-            MvPtr(1),
-            MvValue(1),
-            MvPtr(1),
-            MvValue(1),
-            MvPtr(1),
-            MvValue(-1),
-            MvPtr(2),
-            MvValue(1),
-            OpenLoop,
-            MvPtr(-1),
-            CloseLoop,
-            MvPtr(-1),
-            MvValue(-1),
-            CloseLoop,
-            MvPtr(2),
-            Output,
-            MvPtr(1),
-            MvValue(-3),
-            Output,
-            MvValue(7),
-            Output,
-            Output,
-            MvValue(3),
-            Output,
-            MvPtr(2),
-            Output,
-            MvPtr(-1),
-            MvValue(-1),
-            Output,
-            MvPtr(-1),
-            Output,
-            MvValue(3),
-            Output,
-            MvValue(-6),
-            Output,
-            MvValue(-8),
-            Output,
-            MvPtr(2),
-            MvValue(1),
-            Output,
-            MvPtr(1),
-            MvValue(2),
-            Output,
-        ]);
+        let mut instructions = Instructions::from_string("+[-]>+[->+<]");
+        assert_eq!(instructions.complexity(), 3);
+        instructions.optimize();
+        assert_eq!(instructions.complexity(), 1);
+    }
 
-        assert_eq!(instructions, expected);
+    #[test]
+    fn outer_loop_counts() {
+        let run = |s: &str, input: &[u8]| {
+            Program::from_string(s)
+                .run_counting_outer_loop(input)
+                .map_err(|e| e.kind)
+        };
+
+        assert_eq!(run("+++.", &[]), Ok((0, vec![3])));
+        assert_eq!(run("[+]", &[]), Ok((0, vec![])));
+        // Nested loops don't count, however many times they run.
+        assert_eq!(run("++[>+++[-]<-]", &[]), Ok((2, vec![])));
+        assert_eq!(run("++[-]+++[-]", &[]), Ok((5, vec![])));
+        assert_eq!(run(",[.,]", b"abc"), Ok((3, b"abc".to_vec())));
+        assert_eq!(run("+[<]", &[]), Err(BFErrorKind::CellBoundsError));
+
+        // Counting starts over from a reset.
+        let mut prgm = Program::from_string("++[-]");
+        prgm.run_counting_outer_loop(&[]).unwrap();
+        assert_eq!(prgm.run_counting_outer_loop(&[]).unwrap(), (2, vec![]));
     }
 
     #[test]
@@ -747,6 +8002,77 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn lenient_brackets() {
+        let instructions = Instructions::from_string("+]+.[-]].");
+
+        let mut strict = Program::new(instructions.clone());
+        let result = blank_execute_prgm(&mut strict).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingOpen));
+
+        let mut lenient = ProgramBuilder::new(instructions)
+            .lenient_brackets(true)
+            .build();
+        let mut outstring = String::new();
+        lenient
+            .execute(|| ' ', |c| outstring.push(c))
+            .expect("Unmatched ] should be skipped.");
+        assert_eq!(outstring, "\u{2}\u{0}");
+
+        // Unmatched [ is still an error.
+        let mut lenient = ProgramBuilder::new(Instructions::from_string("+["))
+            .lenient_brackets(true)
+            .build();
+        let result = blank_execute_prgm(&mut lenient).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingClose));
+    }
+
+    #[test]
+    fn strict_output() {
+        // Outputs 128 after 127, which is only a problem with strict output.
+        let instructions = Instructions::from_string("++++++++[>++++++++<-]>[>++<-]>-.+.");
+
+        let mut outstring = String::new();
+        let mut lenient = Program::new(instructions.clone());
+        lenient.execute(|| ' ', |c| outstring.push(c)).unwrap();
+        assert_eq!(outstring, "\u{7f}\u{80}");
+
+        let mut outstring = String::new();
+        let mut strict = ProgramBuilder::new(instructions)
+            .strict_output(true)
+            .build();
+        let result = strict
+            .execute(|| ' ', |c| outstring.push(c))
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::InvalidOutput));
+        assert_eq!(outstring, "\u{7f}");
+    }
+
+    #[test]
+    fn output_limit() {
+        // Prints forever.
+        let instructions = Instructions::from_string("+[.]");
+
+        let mut outstring = String::new();
+        let mut prgm = ProgramBuilder::new(instructions).max_output(10).build();
+        let result = prgm
+            .execute(|| ' ', |c| outstring.push(c))
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::OutputLimitExceeded));
+        assert_eq!(outstring.len(), 10);
+
+        // Resetting starts the count over.
+        prgm.reset();
+        let result = blank_execute_prgm(&mut prgm).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::OutputLimitExceeded));
+
+        // Output right up to the limit is fine.
+        let mut prgm = ProgramBuilder::new(Instructions::from_string("..."))
+            .max_output(3)
+            .build();
+        blank_execute_prgm(&mut prgm).unwrap();
+    }
+
     #[test]
     fn missing_close_bracket() {
         // Situation where it wouldn't yet skip to closing bracket
@@ -0,0 +1,297 @@
+//! Compiling BF instructions to textual LLVM IR.
+
+use std::fmt::Write;
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::{Instruct, Instructions, Result};
+
+/// Number of cells on the tape of compiled programs.
+const TAPE_SIZE: usize = 30000;
+
+impl Instructions {
+    /// Compile the instructions into textual LLVM IR.
+    ///
+    /// The IR defines a `main` function implementing the program on a zeroed tape of 30000
+    /// cells, reading input with `getchar` and writing output with `putchar`. EOF reads as 0,
+    /// the same as in the interpreter. Feed it to `llc`, or to `clang` along with the C
+    /// standard library, to get a native binary:
+    ///
+    /// ```sh
+    /// clang -O2 program.ll -o program
+    /// ```
+    ///
+    /// Unlike the interpreter, the compiled program doesn't check that the cell pointer stays
+    /// on the tape, so a program that moves off either end has undefined behavior.
+    ///
+    /// Optimizing the instructions first gives much smaller IR.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::MissingOpen` or `BFErrorKind::MissingClose`
+    /// if the brackets don't match, or `BFErrorKind::UnknownInputLength` if the instructions use
+    /// the extended `?` command, since compiled programs read from stdin, which has no length.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let ir = Instructions::from_string("+[.-]").to_llvm_ir().unwrap();
+    ///
+    /// assert!(ir.contains("define i32 @main()"));
+    /// ```
+    pub fn to_llvm_ir(&self) -> Result<String> {
+        // Makes sure every loop has a partner so the basic blocks all line up.
+        self.cfg()?;
+
+        let mut emitter = Emitter::default();
+        // Writing to a String can't fail, so the fmt::Results are ignored throughout.
+        let ir = &mut emitter.ir;
+        let _ = writeln!(ir, "; Generated by rbf.");
+        let _ = writeln!(
+            ir,
+            "@tape = internal global [{} x i8] zeroinitializer",
+            TAPE_SIZE
+        );
+        let _ = writeln!(ir);
+        let _ = writeln!(ir, "declare i32 @getchar()");
+        let _ = writeln!(ir, "declare i32 @putchar(i32)");
+        let _ = writeln!(ir);
+        let _ = writeln!(ir, "define i32 @main() {{");
+        let _ = writeln!(ir, "entry:");
+        let _ = writeln!(ir, "  %ptr = alloca i64");
+        let _ = writeln!(ir, "  store i64 0, ptr %ptr");
+
+        // Ids of the loops we're currently inside, so each `]` branches back to its own `[`.
+        let mut open_loops = vec![];
+        let mut next_loop = 0;
+        let mut next_move = 0;
+
+        for instruction in &self.0 {
+            match *instruction {
+                Instruct::MvPtr(n) => {
+                    let ptr = emitter.load_ptr();
+                    let moved = emitter.temp();
+                    emitter.line(format!("{} = add i64 {}, {}", moved, ptr, n));
+                    emitter.line(format!("store i64 {}, ptr %ptr", moved));
+                }
+                Instruct::MvValue(n) => {
+                    let cell = emitter.cell_address();
+                    let value = emitter.load_cell(&cell);
+                    let added = emitter.temp();
+                    // Cells wrap, so only the amount mod 256 matters.
+                    emitter.line(format!("{} = add i8 {}, {}", added, value, n as i8));
+                    emitter.line(format!("store i8 {}, ptr {}", added, cell));
+                }
+                Instruct::SetValue(n) => {
+                    let cell = emitter.cell_address();
+                    emitter.line(format!("store i8 {}, ptr {}", n as i8, cell));
+                }
+                Instruct::Move(to) => {
+                    emitter.move_cell(&[to], next_move);
+                    next_move += 1;
+                }
+                Instruct::Copy(a, b) => {
+                    emitter.move_cell(&[a, b], next_move);
+                    next_move += 1;
+                }
+                Instruct::Output => {
+                    let cell = emitter.cell_address();
+                    let value = emitter.load_cell(&cell);
+                    let extended = emitter.temp();
+                    emitter.line(format!("{} = zext i8 {} to i32", extended, value));
+                    let result = emitter.temp();
+                    emitter.line(format!("{} = call i32 @putchar(i32 {})", result, extended));
+                }
+                Instruct::Input => {
+                    let read = emitter.temp();
+                    emitter.line(format!("{} = call i32 @getchar()", read));
+                    let eof = emitter.temp();
+                    emitter.line(format!("{} = icmp eq i32 {}, -1", eof, read));
+                    let read_or_zero = emitter.temp();
+                    emitter.line(format!(
+                        "{} = select i1 {}, i32 0, i32 {}",
+                        read_or_zero, eof, read
+                    ));
+                    let value = emitter.temp();
+                    emitter.line(format!("{} = trunc i32 {} to i8", value, read_or_zero));
+                    let cell = emitter.cell_address();
+                    emitter.line(format!("store i8 {}, ptr {}", value, cell));
+                }
+                Instruct::InputLen => {
+                    return Err(BFError {
+                        kind: BFErrorKind::UnknownInputLength,
+                    })
+                }
+                Instruct::OpenLoop => {
+                    let id = next_loop;
+                    next_loop += 1;
+                    open_loops.push(id);
+
+                    emitter.line(format!("br label %loop{}.cond", id));
+                    emitter.label(format!("loop{}.cond", id));
+                    let cell = emitter.cell_address();
+                    let value = emitter.load_cell(&cell);
+                    let nonzero = emitter.temp();
+                    emitter.line(format!("{} = icmp ne i8 {}, 0", nonzero, value));
+                    emitter.line(format!(
+                        "br i1 {}, label %loop{}.body, label %loop{}.end",
+                        nonzero, id, id
+                    ));
+                    emitter.label(format!("loop{}.body", id));
+                }
+                Instruct::CloseLoop => {
+                    // Already checked by cfg() that every `]` has a `[`.
+                    let id = open_loops.pop().expect("Brackets were already matched.");
+
+                    emitter.line(format!("br label %loop{}.cond", id));
+                    emitter.label(format!("loop{}.end", id));
+                }
+            }
+        }
+
+        emitter.line("ret i32 0".to_string());
+        let _ = writeln!(emitter.ir, "}}");
+
+        Ok(emitter.ir)
+    }
+}
+
+/// Keeps track of the IR being built and the numbering of SSA temporaries.
+#[derive(Default)]
+struct Emitter {
+    ir: String,
+    next_temp: usize,
+}
+
+impl Emitter {
+    /// Get a fresh SSA temporary name.
+    fn temp(&mut self) -> String {
+        self.next_temp += 1;
+        format!("%t{}", self.next_temp)
+    }
+
+    /// Write an indented instruction line.
+    fn line(&mut self, line: String) {
+        let _ = writeln!(self.ir, "  {}", line);
+    }
+
+    /// Start a new basic block.
+    fn label(&mut self, label: String) {
+        let _ = writeln!(self.ir, "{}:", label);
+    }
+
+    /// Load the current cell pointer, returning the temporary holding it.
+    fn load_ptr(&mut self) -> String {
+        let ptr = self.temp();
+        self.line(format!("{} = load i64, ptr %ptr", ptr));
+        ptr
+    }
+
+    /// Compute the address of the current cell, returning the temporary holding it.
+    fn cell_address(&mut self) -> String {
+        let ptr = self.load_ptr();
+        let cell = self.temp();
+        self.line(format!(
+            "{} = getelementptr inbounds [{} x i8], ptr @tape, i64 0, i64 {}",
+            cell, TAPE_SIZE, ptr
+        ));
+        cell
+    }
+
+    /// Add the current cell onto the cells at each of `offsets` from it, then clear it, using
+    /// `id` to name the blocks.
+    ///
+    /// The other cells are only touched if the current cell isn't 0, the same as the loop this
+    /// replaced, so a move that would go off the tape is as safe as the loop was.
+    fn move_cell(&mut self, offsets: &[isize], id: usize) {
+        let cell = self.cell_address();
+        let value = self.load_cell(&cell);
+        let nonzero = self.temp();
+        self.line(format!("{} = icmp ne i8 {}, 0", nonzero, value));
+        self.line(format!(
+            "br i1 {}, label %move{}.body, label %move{}.end",
+            nonzero, id, id
+        ));
+        self.label(format!("move{}.body", id));
+
+        let ptr = self.load_ptr();
+        for offset in offsets {
+            let target_ptr = self.temp();
+            self.line(format!("{} = add i64 {}, {}", target_ptr, ptr, offset));
+            let target = self.temp();
+            self.line(format!(
+                "{} = getelementptr inbounds [{} x i8], ptr @tape, i64 0, i64 {}",
+                target, TAPE_SIZE, target_ptr
+            ));
+            let old = self.load_cell(&target);
+            let added = self.temp();
+            self.line(format!("{} = add i8 {}, {}", added, old, value));
+            self.line(format!("store i8 {}, ptr {}", added, target));
+        }
+        self.line(format!("store i8 0, ptr {}", cell));
+        self.line(format!("br label %move{}.end", id));
+        self.label(format!("move{}.end", id));
+    }
+
+    /// Load the cell at `cell`, returning the temporary holding its value.
+    fn load_cell(&mut self, cell: &str) -> String {
+        let value = self.temp();
+        self.line(format!("{} = load i8, ptr {}", value, cell));
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn llvm_ir_structure() {
+        let ir = Instructions::from_string(",[.,]++[>+<-]")
+            .to_llvm_ir()
+            .unwrap();
+
+        assert!(ir.contains("@tape = internal global [30000 x i8] zeroinitializer"));
+        assert!(ir.contains("declare i32 @getchar()"));
+        assert!(ir.contains("declare i32 @putchar(i32)"));
+        assert_eq!(ir.matches("call i32 @getchar()").count(), 2);
+        assert_eq!(ir.matches("call i32 @putchar(").count(), 1);
+
+        // Each loop gets a condition, body and end block.
+        for id in 0..2 {
+            for block in ["cond", "body", "end"] {
+                assert_eq!(ir.matches(&format!("\nloop{}.{}:", id, block)).count(), 1);
+            }
+        }
+        assert!(ir.trim_end().ends_with("ret i32 0\n}"));
+    }
+
+    #[test]
+    fn llvm_ir_moves() {
+        let mut instructions = Instructions::from_string("+[->+<]>[->+>+<<]");
+        instructions.optimize();
+        let ir = instructions.to_llvm_ir().unwrap();
+
+        // The moves don't loop, they just skip everything on a 0 cell.
+        assert!(!ir.contains("loop0"));
+        for id in 0..2 {
+            for block in ["body", "end"] {
+                assert_eq!(ir.matches(&format!("\nmove{}.{}:", id, block)).count(), 1);
+            }
+        }
+        assert_eq!(ir.matches("store i8 0,").count(), 2);
+    }
+
+    #[test]
+    fn llvm_ir_unmatched_brackets() {
+        let result = Instructions::from_string("[")
+            .to_llvm_ir()
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingClose));
+
+        let result = Instructions::from_string("]")
+            .to_llvm_ir()
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::MissingOpen));
+    }
+}
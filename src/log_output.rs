@@ -0,0 +1,101 @@
+//! Sending program output to the `log` crate, enabled with the `log` feature.
+
+/// Output that goes to the `log` crate rather than stdout, one log record per line.
+///
+/// This is for when BF runs as part of a bigger system and its output belongs in the
+/// application's logs. Chars are buffered until a newline, then the line is logged without
+/// the newline at the chosen level, with `rbf` as the target. Whatever is left of an
+/// unfinished last line is logged when `flush()` is called or when the `LogOutput` is dropped.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::*;
+/// let mut prgm = Program::from_string("++++++++[>++++++++<-]>+.++++++++++++++++++++++++.");
+///
+/// let mut output = LogOutput::new(log::Level::Info);
+/// prgm.execute(|| '\0', |c| output.print_char(c)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct LogOutput {
+    level: log::Level,
+    line: String,
+}
+
+impl LogOutput {
+    /// Create new LogOutput struct logging each line at `level`.
+    pub fn new(level: log::Level) -> Self {
+        Self {
+            level,
+            line: String::new(),
+        }
+    }
+
+    /// Buffer a single char, logging the line if it's a newline.
+    pub fn print_char(&mut self, c: char) {
+        if c == '\n' {
+            log::log!(target: "rbf", self.level, "{}", self.line);
+            self.line.clear();
+        } else {
+            self.line.push(c);
+        }
+    }
+
+    /// Log the unfinished last line, if there is one.
+    pub fn flush(&mut self) {
+        if !self.line.is_empty() {
+            log::log!(target: "rbf", self.level, "{}", self.line);
+            self.line.clear();
+        }
+    }
+}
+
+impl Drop for LogOutput {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Every record logged with the `rbf` target, as its level and message.
+    static RECORDS: Mutex<Vec<(log::Level, String)>> = Mutex::new(vec![]);
+
+    struct TestLogger;
+
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            if record.target() == "rbf" {
+                let message = record.args().to_string();
+                RECORDS.lock().unwrap().push((record.level(), message));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn log_output_lines() {
+        log::set_logger(&TestLogger).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut output = LogOutput::new(log::Level::Warn);
+        for c in "first\n\nsecond\nrest".chars() {
+            output.print_char(c);
+        }
+        assert_eq!(RECORDS.lock().unwrap().len(), 3);
+        drop(output);
+
+        let messages: Vec<(log::Level, String)> = RECORDS.lock().unwrap().clone();
+        let expected = ["first", "", "second", "rest"].map(|m| (log::Level::Warn, m.to_string()));
+        assert_eq!(messages, expected);
+    }
+}
@@ -1,11 +1,43 @@
 use std::fs;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use clap::{ArgGroup, Parser};
+
+/// Exit code when the program can't even be started, e.g. the file couldn't be read.
+const EXIT_SETUP_ERROR: i32 = 1;
+/// Added to an error's `BFErrorKind::code()` to get the exit code when the BF program fails.
+const EXIT_BF_ERROR_BASE: i32 = 10;
+
+/// How many of the hottest instructions and loops --profile lists.
+const PROFILE_LIMIT: usize = 10;
+
+/// How many cells either side of the cell pointer --step shows.
+const STEP_WINDOW: usize = 8;
+
+/// Exit code listing shown at the end of `--help`.
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0   Success
+  1   The program couldn't be started, e.g. the file couldn't be read
+  11  A ] has no matching [
+  12  A [ has no matching ]
+  13  Invalid input
+  14  The cell pointer moved off the tape
+  15  The instruction pointer went out of bounds
+  16  An I/O error
+  17  The step limit was exceeded
+  18  Output wasn't ascii with --strict-output
+  19  The output limit was exceeded
+  24  The program used ? without input of a known length, with --extended
+  25  The program kept reading after its input ran out, with --max-eof-reads
+  26  The time limit was exceeded, with --timeout";
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = EXIT_CODES_HELP)]
+#[command(group(ArgGroup::new("writes_output").args(["input_dir", "emit"]).multiple(true)))]
 struct Cli {
     /// BF file to interpret.
     #[arg(short, long, value_name = "FILE")]
@@ -21,51 +53,314 @@ struct Cli {
     benchmark: bool,
 
     /// Repeat the program NUMBER times. Useful for benchmarking.
-    #[arg(short, long, value_name = "NUMBER", default_value_t = 1)]
-    repititions: usize,
+    // The flag used to be misspelled as --repititions, which still works for old scripts.
+    #[arg(
+        short,
+        long,
+        value_name = "NUMBER",
+        default_value_t = 1,
+        alias = "repititions"
+    )]
+    repetitions: usize,
+
+    /// Run the program once for each file in DIR, with the file's contents as input.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["bang_input", "random_input"])]
+    input_dir: Option<PathBuf>,
+
+    /// With --input-dir, write each run's output to a file of the same name in the directory
+    /// PATH instead of printing it. With --emit, write the compiled program to the file PATH.
+    #[arg(long, value_name = "PATH", requires = "writes_output")]
+    output: Option<PathBuf>,
 
     /// Use blank IO, useful for benchmarking.
     #[arg(long)]
     blank_io: bool,
 
-    /// Run internal optimization on the BF code.
+    /// Skip IO entirely, so , and . do nothing. Benchmarks the interpreter better than blank
+    /// IO, since no IO functions get called at all.
+    #[arg(long)]
+    no_io: bool,
+
+    /// Run internal optimization on the BF code. Optimized code can behave differently when the
+    /// pointer underflow policy is clamp or wrap, so it can't be used with those.
     #[arg(short, long)]
     optimize: bool,
+
+    /// Print the optimized BF code instead of running it.
+    #[arg(long)]
+    emit_optimized: bool,
+
+    /// Compile the BF code, optimized if -o, to TARGET and print it instead of running it:
+    /// LLVM IR, or the crate's binary bytecode format.
+    #[arg(long, value_name = "TARGET", value_parser = ["llvm", "bytecode"], conflicts_with_all = ["input_dir", "emit_optimized", "info"])]
+    emit: Option<String>,
+
+    /// Print a summary of the BF code, optimized if -o, instead of running it.
+    #[arg(long)]
+    info: bool,
+
+    /// Ignore any ] that has no matching [ instead of stopping with an error.
+    #[arg(long)]
+    lenient_brackets: bool,
+
+    /// Stop with an error if the program outputs a value that isn't ascii.
+    #[arg(long)]
+    strict_output: bool,
+
+    /// Treat everything after the first ! in the program as its input, instead of reading from
+    /// the terminal.
+    #[arg(long, conflicts_with = "random_input")]
+    bang_input: bool,
+
+    /// Parse the extended dialect, where ? sets the cell to how many bytes of input are left.
+    /// That's only known for --bang-input and --input-dir.
+    #[arg(long)]
+    extended: bool,
+
+    /// Read pseudo-random input generated from SEED instead of reading from the terminal.
+    #[arg(long, value_name = "SEED")]
+    random_input: Option<u64>,
+
+    /// Stop the program with an error if it outputs more than BYTES bytes.
+    #[arg(long, value_name = "BYTES")]
+    max_output: Option<usize>,
+
+    /// Stop the program with an error if it reads 0 more than READS times without making any
+    /// progress, e.g. by looping on input that has run out.
+    #[arg(long, value_name = "READS")]
+    max_eof_reads: Option<usize>,
+
+    /// Stop the program with an error if it runs for longer than MS milliseconds. If -r, each
+    /// repetition gets the full time.
+    #[arg(long, value_name = "MS", conflicts_with_all = ["input_dir", "no_io"])]
+    timeout: Option<u64>,
+
+    /// Start the cell pointer at cell INDEX instead of cell 0.
+    #[arg(long, value_name = "INDEX", default_value_t = 0)]
+    start_pointer: usize,
+
+    /// Use a fixed tape of CELLS cells instead of one that grows as needed.
+    #[arg(long, value_name = "CELLS")]
+    tape_len: Option<usize>,
+
+    /// What to do when the cell pointer moves left of cell 0. Wrapping needs --tape-len, and
+    /// growing needs the tape to not be fixed.
+    #[arg(long, value_name = "POLICY", default_value = "error", value_parser = ["error", "wrap", "clamp", "grow"])]
+    pointer_underflow: String,
+
+    /// Treat moving left of the starting cell as an error, whatever the underflow policy.
+    #[arg(long)]
+    strict_origin: bool,
+
+    /// Print each newline the program outputs as a carriage return and newline.
+    #[arg(long)]
+    crlf: bool,
+
+    /// How to print null bytes the program outputs: as they are, not at all, or as a visible
+    /// `␀`.
+    #[arg(long, value_name = "MODE", default_value = "raw", value_parser = ["raw", "skip", "visible"])]
+    nulls: String,
+
+    /// How to print each output cell: as a char, or on its own line as an unsigned (0 to 255)
+    /// or signed (-128 to 127) number.
+    #[arg(long, value_name = "FORMAT", default_value = "char", value_parser = ["char", "unsigned", "signed"])]
+    cell_format: String,
+
+    /// The base to write numbers in, for --cell-format and --dump-tape. Numbers are in decimal
+    /// and tape dumps in hex by default.
+    #[arg(long, value_name = "RADIX", value_parser = ["binary", "octal", "decimal", "hex"])]
+    radix: Option<String>,
+
+    /// Print PROMPT to stderr whenever the program waits for input.
+    #[arg(long, value_name = "PROMPT")]
+    prompt: Option<String>,
+
+    /// Print ranges of instructions that never executed. If -r, coverage includes all
+    /// repetitions.
+    #[arg(long)]
+    coverage: bool,
+
+    /// Print the hottest instructions and loops, with how many times each ran. If -r, counts
+    /// include all repetitions.
+    #[arg(long)]
+    profile: bool,
+
+    /// Run the program one instruction at a time, showing the next instruction and the cells
+    /// around the cell pointer, and waiting for enter before each step. With breakpoints,
+    /// watchpoints or a step limit, enter runs until the next place to stop instead.
+    #[arg(long, conflicts_with_all = ["input_dir", "no_io", "timeout", "repetitions"])]
+    step: bool,
+
+    /// With --step, stop before the instruction at INDEX. Can be given more than once.
+    #[arg(long = "break", value_name = "INDEX", requires = "step")]
+    breakpoints: Vec<usize>,
+
+    /// With --step, stop once cell CELL holds VALUE. Can be given more than once.
+    #[arg(long = "watch", value_name = "CELL=VALUE", value_parser = parse_watchpoint, requires = "step")]
+    watchpoints: Vec<(usize, u8)>,
+
+    /// With --step, run at most STEPS instructions each time enter is pressed. Must be at
+    /// least 1.
+    #[arg(long, value_name = "STEPS", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), requires = "step")]
+    step_limit: Option<usize>,
+
+    /// Load breakpoints, watchpoints and the step limit for --step from FILE, as saved by
+    /// --save-debug. Ones given as flags are added to them.
+    #[arg(long, value_name = "FILE", requires = "step")]
+    load_debug: Option<PathBuf>,
+
+    /// Save the breakpoints, watchpoints and step limit to FILE, for --load-debug.
+    #[arg(long, value_name = "FILE", requires = "step")]
+    save_debug: Option<PathBuf>,
+
+    /// Print a hex dump of the tape after the program finishes.
+    #[arg(long)]
+    dump_tape: bool,
+
+    /// Only print the program's output to stdout. Leaves out the message after the program
+    /// finishes, and prints -b timings, --coverage, --profile and --dump-tape to stderr
+    /// instead.
+    #[arg(short, long)]
+    quiet: bool,
 }
 
 fn main() {
     let cli = Cli::parse();
 
     let mut prgm: rbf::Program;
-    let mut instructions: rbf::Instructions;
+    let source: String;
 
     if let Some(program) = cli.program.as_deref() {
-        let program_contents: String;
-
-        match fs::read_to_string(program) {
-            Ok(program) => program_contents = program,
+        source = match fs::read_to_string(program) {
+            Ok(program) => program,
             Err(e) => {
-                println!("Error reading from file: {}", e);
-                return;
+                eprintln!("Error reading from file: {}", e);
+                process::exit(EXIT_SETUP_ERROR);
             }
-        }
-
-        instructions = rbf::Instructions::from_string(&program_contents);
+        };
     } else if let Some(code) = cli.code.as_deref() {
-        instructions = rbf::Instructions::from_string(code);
+        source = code.to_string();
     } else {
-        println!("Must pass code via code or program argument.");
-        return;
+        eprintln!("Must pass code via code or program argument.");
+        process::exit(EXIT_SETUP_ERROR);
+    }
+
+    let (mut instructions, bang_input) = match (cli.bang_input, cli.extended) {
+        (true, false) => {
+            let (instructions, input) = rbf::Instructions::from_source_with_input(&source);
+            (instructions, Some(input))
+        }
+        (true, true) => {
+            let (instructions, input) = rbf::Instructions::from_source_with_input_extended(&source);
+            (instructions, Some(input))
+        }
+        (false, false) => (rbf::Instructions::from_string(&source), None),
+        (false, true) => (rbf::Instructions::from_string_extended(&source), None),
+    };
+
+    let reads_input = instructions.uses_input();
+
+    // See `rbf::PointerUnderflow` for why optimized code can't clamp or wrap.
+    if cli.optimize && matches!(cli.pointer_underflow.as_str(), "clamp" | "wrap") {
+        eprintln!(
+            "Can't optimize with --pointer-underflow {}, since optimized code moves the cell \
+             pointer differently.",
+            cli.pointer_underflow
+        );
+        process::exit(EXIT_SETUP_ERROR);
     }
 
-    if cli.optimize {
+    if cli.optimize || cli.emit_optimized {
         instructions.optimize();
     }
 
-    prgm = rbf::Program::new(instructions);
+    if cli.emit_optimized {
+        println!("{}", instructions);
+        return;
+    }
+
+    if let Some(target) = cli.emit.as_deref() {
+        emit(&instructions, target, cli.output.as_deref());
+        return;
+    }
 
-    let input = rbf::BasicInput::new();
+    if cli.info {
+        print!("{}", instructions.describe());
+        return;
+    }
+
+    let debug = debug_config(&cli);
+
+    let mut builder = rbf::ProgramBuilder::new(instructions)
+        .lenient_brackets(cli.lenient_brackets)
+        .strict_output(cli.strict_output)
+        .start_pointer(cli.start_pointer)
+        .strict_origin(cli.strict_origin);
+    if let Some(bytes) = cli.max_output {
+        builder = builder.max_output(bytes);
+    }
+    if let Some(reads) = cli.max_eof_reads {
+        builder = builder.max_eof_reads(reads);
+    }
+    if let Some(cells) = cli.tape_len {
+        builder = builder.tape_len(cells);
+    }
+    builder = builder.pointer_underflow(match cli.pointer_underflow.as_str() {
+        "wrap" => rbf::PointerUnderflow::Wrap,
+        "clamp" => rbf::PointerUnderflow::Clamp,
+        "grow" => rbf::PointerUnderflow::Grow,
+        _ => rbf::PointerUnderflow::Error,
+    });
+    prgm = builder.build();
+
+    if cli.coverage || cli.profile {
+        prgm.enable_profiling();
+    }
+
+    if let Some(input_dir) = cli.input_dir.as_deref() {
+        let first_error = run_input_dir(
+            &mut prgm,
+            input_dir,
+            cli.output.as_deref(),
+            cli.benchmark,
+            cli.quiet,
+        );
+        print_profiling(&prgm, cli.coverage, cli.profile, cli.quiet);
+        exit_on_error(first_error);
+        return;
+    }
+
+    let mut input = match (bang_input, cli.random_input) {
+        (Some(bang_input), _) => rbf::BasicInput::from_string(&bang_input),
+        (None, Some(seed)) => rbf::BasicInput::random(seed),
+        // Programs that never read don't need the terminal for input.
+        (None, None) if !reads_input => rbf::BasicInput::from_string(""),
+        (None, None) => rbf::BasicInput::new(),
+    };
+    input.set_prompt(cli.prompt);
     let mut output = rbf::BasicOutput::new();
+    if cli.crlf {
+        output.set_newline_mode(rbf::NewlineMode::Crlf);
+    }
+    output.set_null_mode(match cli.nulls.as_str() {
+        "skip" => rbf::NullMode::Skip,
+        "visible" => rbf::NullMode::Replace('␀'),
+        _ => rbf::NullMode::Raw,
+    });
+    output.set_cell_format(match cli.cell_format.as_str() {
+        "unsigned" => rbf::CellFormat::Unsigned,
+        "signed" => rbf::CellFormat::Signed,
+        _ => rbf::CellFormat::Char,
+    });
+    let radix = cli.radix.as_deref().map(|radix| match radix {
+        "binary" => rbf::Radix::Binary,
+        "octal" => rbf::Radix::Octal,
+        "hex" => rbf::Radix::Hex,
+        _ => rbf::Radix::Decimal,
+    });
+    if let Some(radix) = radix {
+        output.set_radix(radix);
+    }
 
     let mut input_fn: Box<dyn FnMut() -> char>;
     let mut output_fn: Box<dyn FnMut(char)>;
@@ -79,23 +374,328 @@ fn main() {
     }
 
     let before = Instant::now();
+    let mut first_error = None;
 
-    for _ in 0..cli.repititions {
+    for _ in 0..cli.repetitions {
         prgm.reset();
-        match prgm.execute(|| input_fn(), |c| output_fn(c)) {
+        prgm.set_input_len(input.input_remaining());
+        let result = match cli.timeout {
+            _ if cli.no_io => prgm.execute_no_io(),
+            _ if cli.step => execute_stepping(&mut prgm, &debug, &mut input_fn, &mut output_fn),
+            Some(ms) => {
+                prgm.execute_with_timeout(Duration::from_millis(ms), &mut input_fn, &mut output_fn)
+            }
+            None => prgm.execute(&mut input_fn, &mut output_fn),
+        };
+        match result {
             Ok(()) => {}
-            Err(e) => eprintln!("\n{}", e),
+            Err(e) => {
+                eprintln!("\n{}", e);
+                first_error.get_or_insert(e);
+            }
         };
     }
 
-    if cli.repititions > 1 {
-        println!("\nFinished executing {} times.", cli.repititions);
-    } else {
-        println!("\nFinished program.");
+    if !cli.quiet {
+        if cli.repetitions > 1 {
+            println!("\nFinished executing {} times.", cli.repetitions);
+        } else {
+            println!("\nFinished program.");
+        }
     }
 
     if cli.benchmark {
         let elapsed = before.elapsed();
-        println!("Took: {:.2?}", elapsed);
+        status(cli.quiet, format!("Took: {:.2?}", elapsed));
+    }
+
+    print_profiling(&prgm, cli.coverage, cli.profile, cli.quiet);
+
+    if cli.dump_tape {
+        let dump = prgm.dump_tape(radix.unwrap_or(rbf::Radix::Hex));
+        status(cli.quiet, dump.trim_end().to_string());
+    }
+
+    exit_on_error(first_error);
+}
+
+/// Parse a --watch value, `CELL=VALUE`.
+fn parse_watchpoint(watch: &str) -> Result<(usize, u8), String> {
+    let (cell, value) = watch
+        .split_once('=')
+        .ok_or_else(|| "expected CELL=VALUE".to_string())?;
+    let cell = cell.parse().map_err(|e| format!("invalid cell: {}", e))?;
+    let value = value.parse().map_err(|e| format!("invalid value: {}", e))?;
+    Ok((cell, value))
+}
+
+/// Put together the debug config for --step from --load-debug and the flags, saving it if
+/// --save-debug was given.
+fn debug_config(cli: &Cli) -> rbf::DebugConfig {
+    let mut debug = match cli.load_debug.as_deref() {
+        Some(path) => {
+            let config = fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Error reading from file: {}", e);
+                process::exit(EXIT_SETUP_ERROR);
+            });
+            rbf::DebugConfig::from_string(&config).unwrap_or_else(|e| {
+                eprintln!("Error loading debug config: {}", e);
+                process::exit(EXIT_SETUP_ERROR);
+            })
+        }
+        None => rbf::DebugConfig::new(),
+    };
+    debug.breakpoints.extend(&cli.breakpoints);
+    debug.watchpoints.extend(&cli.watchpoints);
+    debug.step_limit = cli.step_limit.or(debug.step_limit);
+
+    if let Some(path) = cli.save_debug.as_deref() {
+        if let Err(e) = fs::write(path, debug.to_string()) {
+            eprintln!("Error writing to file: {}", e);
+            process::exit(EXIT_SETUP_ERROR);
+        }
+    }
+
+    debug
+}
+
+/// Run the program one step at a time for --step, showing where it is on stderr and waiting
+/// for enter before each step. If `debug` has anywhere to stop, each enter runs until the next
+/// one instead.
+fn execute_stepping(
+    prgm: &mut rbf::Program,
+    debug: &rbf::DebugConfig,
+    input: &mut dyn FnMut() -> char,
+    output: &mut dyn FnMut(char),
+) -> Result<(), rbf::errors::BFError> {
+    let term = console::Term::stderr();
+    let mut previous = prgm.tape().to_vec();
+
+    let single_step = rbf::DebugConfig {
+        step_limit: Some(1),
+        ..rbf::DebugConfig::new()
+    };
+    let debug = if *debug == rbf::DebugConfig::new() {
+        &single_step
+    } else {
+        debug
+    };
+
+    while let Some(op) = prgm.current_op() {
+        // The step display is only a convenience, so failing to show it doesn't stop the run.
+        let _ = term.write_line(&format!("{:>6}  {:?}", prgm.instruction_pointer(), op));
+        let _ = term.write_line(&tape_window(prgm, &previous));
+        if term.read_line().is_err() {
+            break;
+        }
+
+        previous = prgm.tape().to_vec();
+        let stopped = match prgm.run_debug(debug, &mut *input, &mut *output)? {
+            rbf::DebugStatus::Breakpoint(idx) => format!("Breakpoint at instruction {}", idx),
+            rbf::DebugStatus::Watchpoint(cell) => {
+                format!(
+                    "Cell {} is {}",
+                    cell,
+                    prgm.tape().get(cell).copied().unwrap_or(0)
+                )
+            }
+            rbf::DebugStatus::Finished | rbf::DebugStatus::StepLimit => continue,
+        };
+        let _ = term.write_line(&stopped);
+    }
+
+    prgm.done().map(|_| ())
+}
+
+/// Render the cells around the cell pointer for --step, highlighting the current cell, cells
+/// that changed since `previous`, and cells that are 0.
+///
+/// Colors are left out when stderr isn't a terminal, and the current cell is marked with a `>`
+/// either way.
+fn tape_window(prgm: &rbf::Program, previous: &[u8]) -> String {
+    let tape = prgm.tape();
+    let ptr = prgm.cell_pointer();
+    let start = ptr.saturating_sub(STEP_WINDOW);
+    let end = (ptr + STEP_WINDOW).min(tape.len().max(ptr + 1) - 1);
+
+    let mut window = format!("{:>6} ", start);
+    for idx in start..=end {
+        let value = tape.get(idx).copied().unwrap_or(0);
+        let mut cell = console::style(format!("{:>3}", value)).for_stderr();
+        if idx == ptr {
+            cell = cell.reverse().bold();
+        } else if previous.get(idx).copied().unwrap_or(0) != value {
+            cell = cell.yellow();
+        } else if value == 0 {
+            cell = cell.dim();
+        }
+
+        window.push(if idx == ptr { '>' } else { ' ' });
+        window += &cell.to_string();
+    }
+
+    window
+}
+
+/// Print a status message, to stderr with --quiet so it stays out of the program's output.
+fn status(quiet: bool, message: String) {
+    if quiet {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// Compile the instructions to `target` and write them to the file `output`, or to stdout if
+/// there isn't one.
+fn emit(instructions: &rbf::Instructions, target: &str, output: Option<&Path>) {
+    let compiled = match target {
+        "bytecode" => instructions.to_bytes(),
+        _ => match instructions.to_llvm_ir() {
+            Ok(ir) => ir.into_bytes(),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit_on_error(Some(e));
+                return;
+            }
+        },
+    };
+
+    let written = match output {
+        Some(path) => fs::write(path, compiled),
+        None => std::io::stdout().write_all(&compiled),
+    };
+    if let Err(e) = written {
+        eprintln!("Error writing compiled program: {}", e);
+        process::exit(EXIT_SETUP_ERROR);
+    }
+}
+
+/// Exit with the code for `error`, if there was one.
+fn exit_on_error(error: Option<rbf::errors::BFError>) {
+    if let Some(e) = error {
+        process::exit(EXIT_BF_ERROR_BASE + i32::from(e.kind.code()));
+    }
+}
+
+/// Run the program once for each file in `input_dir`, in order of file name, giving it the
+/// file's bytes as input.
+///
+/// Each run's output is either written to a file of the same name in `output_dir` or printed
+/// under a header with the file's name. Returns the first error any run hit.
+fn run_input_dir(
+    prgm: &mut rbf::Program,
+    input_dir: &Path,
+    output_dir: Option<&Path>,
+    benchmark: bool,
+    quiet: bool,
+) -> Option<rbf::errors::BFError> {
+    let setup_error = |message: String| -> ! {
+        eprintln!("{}", message);
+        process::exit(EXIT_SETUP_ERROR);
+    };
+
+    let entries = fs::read_dir(input_dir)
+        .unwrap_or_else(|e| setup_error(format!("Error reading input directory: {}", e)));
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+
+    if let Some(output_dir) = output_dir {
+        fs::create_dir_all(output_dir)
+            .unwrap_or_else(|e| setup_error(format!("Error creating output directory: {}", e)));
+    }
+
+    let mut first_error = None;
+
+    for file in files {
+        // Files come from read_dir, so they always have a name.
+        let name = file.file_name().unwrap_or_default();
+        let input = fs::read(&file)
+            .unwrap_or_else(|e| setup_error(format!("Error reading from file: {}", e)));
+
+        prgm.reset();
+        let before = Instant::now();
+        let result = prgm.run_bytes(&input);
+        let elapsed = before.elapsed();
+
+        match result {
+            Ok(output) => match output_dir {
+                Some(output_dir) => {
+                    if let Err(e) = fs::write(output_dir.join(name), output) {
+                        setup_error(format!("Error writing output file: {}", e));
+                    }
+                }
+                None => {
+                    println!("==> {} <==", name.to_string_lossy());
+                    println!("{}", String::from_utf8_lossy(&output));
+                }
+            },
+            Err(e) => {
+                eprintln!("{}: {}", name.to_string_lossy(), e);
+                first_error.get_or_insert(e);
+            }
+        }
+
+        if benchmark {
+            status(
+                quiet,
+                format!("{} took: {:.2?}", name.to_string_lossy(), elapsed),
+            );
+        }
     }
+
+    first_error
+}
+
+/// Print whichever of the coverage and profile summary were asked for, to stderr with --quiet.
+fn print_profiling(prgm: &rbf::Program, coverage: bool, profile: bool, quiet: bool) {
+    if let Some(covered) = prgm.coverage().filter(|_| coverage) {
+        print_uncovered(&covered, quiet);
+    }
+    if let Some(report) = prgm.profile_report(PROFILE_LIMIT).filter(|_| profile) {
+        status(quiet, report.trim_end().to_string());
+    }
+}
+
+/// Print the ranges of instructions that were never executed.
+fn print_uncovered(coverage: &[bool], quiet: bool) {
+    let mut ranges = vec![];
+    let mut start = None;
+
+    for (i, &covered) in coverage.iter().enumerate() {
+        match (covered, start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                ranges.push((s, i - 1));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        ranges.push((s, coverage.len() - 1));
+    }
+
+    if ranges.is_empty() {
+        status(quiet, "All instructions were executed.".to_string());
+        return;
+    }
+
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|&(s, e)| {
+            if s == e {
+                s.to_string()
+            } else {
+                format!("{}-{}", s, e)
+            }
+        })
+        .collect();
+    status(
+        quiet,
+        format!("Instructions never executed: {}", ranges.join(", ")),
+    );
 }
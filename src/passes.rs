@@ -0,0 +1,183 @@
+//! Optimization passes, for putting together a custom optimizer.
+//!
+//! Each of the built-in optimizations is a type implementing `OptimizationPass`, so they can be
+//! run in any order or combination with `Instructions::optimize_with()`, along with passes of
+//! your own. `Instructions::optimize()` just runs `STANDARD`.
+
+use crate::Instructions;
+
+/// A single optimization over a program's instructions.
+///
+/// A pass must leave the instructions doing the same thing as before, just faster or smaller.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::Instructions;
+/// # use rbf::passes::{self, OptimizationPass};
+/// // Drops everything after the last output, which can't change what the program prints.
+/// struct TrimAfterOutput;
+///
+/// impl OptimizationPass for TrimAfterOutput {
+///     fn run(&self, instructions: &mut Instructions) {
+///         let source = instructions.to_string();
+///         let end = source.rfind('.').map_or(0, |i| i + 1);
+///         *instructions = Instructions::from_string(&source[..end]);
+///     }
+/// }
+///
+/// let mut instructions = Instructions::from_string("+++.>++");
+/// instructions.optimize_with(&[&passes::CombineMultiples, &TrimAfterOutput]);
+/// assert_eq!(instructions.to_string(), "+++.");
+/// ```
+pub trait OptimizationPass {
+    /// Optimize the instructions in place.
+    fn run(&self, instructions: &mut Instructions);
+}
+
+/// The passes `Instructions::optimize()` runs, in the order it runs them.
+pub const STANDARD: &[&dyn OptimizationPass] = &[
+    &RemoveLeadingLoops,
+    &CombineMultiples,
+    &ClearLoops,
+    &MoveLoops,
+    &FoldKnownLoops,
+    &PropagateValues,
+    &FoldSetValues,
+];
+
+/// Removes loops at the start of the program that can never run. See
+/// `Instructions::optimize_remove_leading_loops()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveLeadingLoops;
+
+impl OptimizationPass for RemoveLeadingLoops {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_remove_leading_loops();
+    }
+}
+
+/// Combines runs of `+`/`-` and `>`/`<` into single instructions. See
+/// `Instructions::optimize_combine_multiples()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CombineMultiples;
+
+impl OptimizationPass for CombineMultiples {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_combine_multiples();
+    }
+}
+
+/// Folds the changes to each cell in straight-line code into one change per cell. See
+/// `Instructions::optimize_fold_constants()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FoldConstants;
+
+impl OptimizationPass for FoldConstants {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_fold_constants();
+    }
+}
+
+/// Tracks the value of each cell through straight-line code, folding changes into the sets
+/// before them. This does everything `FoldConstants` does too, so there's no need to run
+/// both. See `Instructions::optimize_propagate_values()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PropagateValues;
+
+impl OptimizationPass for PropagateValues {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_propagate_values();
+    }
+}
+
+/// Replaces loops that count the current cell down to 0 with a single clear. See
+/// `Instructions::optimize_clear_loops()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClearLoops;
+
+impl OptimizationPass for ClearLoops {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_clear_loops();
+    }
+}
+
+/// Replaces loops that move the current cell onto other cells with a single move. See
+/// `Instructions::optimize_move_loops()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MoveLoops;
+
+impl OptimizationPass for MoveLoops {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_move_loops();
+    }
+}
+
+/// Works out loops that run a known number of times ahead of time. See
+/// `Instructions::optimize_fold_known_loops()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FoldKnownLoops;
+
+impl OptimizationPass for FoldKnownLoops {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_fold_known_loops();
+    }
+}
+
+/// Folds changes to the current cell into the set next to them. See
+/// `Instructions::optimize_fold_set_values()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FoldSetValues;
+
+impl OptimizationPass for FoldSetValues {
+    fn run(&self, instructions: &mut Instructions) {
+        instructions.optimize_fold_set_values();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "[-]>>[<]+++--[->+<]>>><<.,+-";
+
+    #[test]
+    fn standard_passes() {
+        let mut optimized = Instructions::from_string(SOURCE);
+        optimized.optimize();
+
+        // This source is fully optimized by a single round.
+        let mut with_passes = Instructions::from_string(SOURCE);
+        with_passes.optimize_with(STANDARD);
+        assert_eq!(with_passes, optimized);
+
+        // Running no passes leaves the instructions alone.
+        let mut untouched = Instructions::from_string(SOURCE);
+        untouched.optimize_with(&[]);
+        assert_eq!(untouched, Instructions::from_string(SOURCE));
+    }
+
+    #[test]
+    fn single_passes() {
+        let check = |pass: &dyn OptimizationPass, method: fn(&mut Instructions)| {
+            let mut with_pass = Instructions::from_string(SOURCE);
+            with_pass.optimize_with(&[pass]);
+
+            let mut with_method = Instructions::from_string(SOURCE);
+            method(&mut with_method);
+            assert_eq!(with_pass, with_method);
+        };
+
+        check(
+            &RemoveLeadingLoops,
+            Instructions::optimize_remove_leading_loops,
+        );
+        check(&CombineMultiples, Instructions::optimize_combine_multiples);
+        check(&FoldConstants, Instructions::optimize_fold_constants);
+        check(&PropagateValues, Instructions::optimize_propagate_values);
+        check(&ClearLoops, Instructions::optimize_clear_loops);
+        check(&MoveLoops, Instructions::optimize_move_loops);
+        check(&FoldKnownLoops, Instructions::optimize_fold_known_loops);
+        check(&FoldSetValues, Instructions::optimize_fold_set_values);
+    }
+}
@@ -0,0 +1,348 @@
+//! Recording program executions so they can be replayed exactly.
+
+use std::fmt;
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::{Program, Result};
+
+/// First line of every serialized log, naming the format and its version.
+const HEADER: &str = "rbf-log 1";
+
+/// A record of every instruction a program executed and every byte of input it read.
+///
+/// Made by `Program::execute_logging()` and consumed by `Program::replay()`.
+///
+/// # Format
+///
+/// Logs serialize to and from text with `to_string()` and `ExecutionLog::from_string()`. The
+/// format is stable, so logs saved to a file keep working with later versions. It's three
+/// lines:
+///
+/// ```text
+/// rbf-log 1
+/// input 61 62
+/// steps 0-3 1-3 4
+/// ```
+///
+/// The `input` line lists the bytes read, in order, as two-digit hex. The `steps` line lists
+/// the index of each executed instruction, in order. Since instructions mostly run one after
+/// another, each run of consecutive indices is written as `first-last`, and a run of one
+/// instruction is just its index. Both lines are present even if they list nothing.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ExecutionLog {
+    /// Bytes read by `,` instructions, in order.
+    pub input: Vec<u8>,
+    /// Runs of consecutively executed instructions, as first and last index.
+    runs: Vec<(usize, usize)>,
+}
+
+impl ExecutionLog {
+    /// Create an empty log.
+    pub fn new() -> ExecutionLog {
+        ExecutionLog::default()
+    }
+
+    /// Get the index of every instruction executed, in order.
+    pub fn steps(&self) -> impl Iterator<Item = usize> + '_ {
+        self.runs.iter().flat_map(|&(first, last)| first..=last)
+    }
+
+    /// Add an executed instruction to the log.
+    fn record_step(&mut self, idx: usize) {
+        match self.runs.last_mut() {
+            Some((_, last)) if *last + 1 == idx => *last = idx,
+            _ => self.runs.push((idx, idx)),
+        }
+    }
+
+    /// Parse a log from its text format.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::InvalidFormat` if the text isn't a log in
+    /// the format described on `ExecutionLog`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::ExecutionLog;
+    /// let log = ExecutionLog::from_string("rbf-log 1\ninput 61\nsteps 0-2 0\n").unwrap();
+    ///
+    /// assert_eq!(log.input, b"a");
+    /// assert_eq!(log.steps().collect::<Vec<_>>(), vec![0, 1, 2, 0]);
+    /// ```
+    pub fn from_string(log: &str) -> Result<ExecutionLog> {
+        let invalid = || BFError {
+            kind: BFErrorKind::InvalidFormat,
+        };
+
+        let [input, steps] = parse_fields(log, HEADER, ["input", "steps"])?;
+        let input = input
+            .map(|byte| match byte.len() {
+                2 => u8::from_str_radix(byte, 16).map_err(|_| invalid()),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<Vec<u8>>>()?;
+
+        let parse_idx = |idx: &str| idx.parse::<usize>().map_err(|_| invalid());
+        let mut parsed = ExecutionLog {
+            input,
+            runs: vec![],
+        };
+        for run in steps {
+            let (first, last) = match run.split_once('-') {
+                Some((first, last)) => (parse_idx(first)?, parse_idx(last)?),
+                None => (parse_idx(run)?, parse_idx(run)?),
+            };
+            if last < first {
+                return Err(invalid());
+            }
+
+            for idx in first..=last {
+                parsed.record_step(idx);
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Split text in the line based formats of `ExecutionLog` and `DebugConfig` into the values of
+/// each field.
+///
+/// The text has to start with the `header` line, then have one line per field in the order of
+/// `names`, each starting with its name and followed by its values separated by whitespace.
+/// Blank lines are allowed at the end, but nothing else is.
+///
+/// # Errors
+///
+/// Will return an error with kind `BFErrorKind::InvalidFormat` if the header, any of the field
+/// names, or the number of lines doesn't match.
+pub(crate) fn parse_fields<'a, const N: usize>(
+    text: &'a str,
+    header: &str,
+    names: [&str; N],
+) -> Result<[std::str::SplitWhitespace<'a>; N]> {
+    let invalid = || BFError {
+        kind: BFErrorKind::InvalidFormat,
+    };
+
+    let mut lines = text.lines();
+    if lines.next() != Some(header) {
+        return Err(invalid());
+    }
+
+    let mut fields = Vec::with_capacity(N);
+    for name in names {
+        let mut words = lines.next().ok_or_else(invalid)?.split_whitespace();
+        if words.next() != Some(name) {
+            return Err(invalid());
+        }
+        fields.push(words);
+    }
+
+    if lines.any(|line| !line.trim().is_empty()) {
+        return Err(invalid());
+    }
+
+    Ok(fields
+        .try_into()
+        .unwrap_or_else(|_| unreachable!("There's one field per name.")))
+}
+
+impl fmt::Display for ExecutionLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+
+        write!(f, "input")?;
+        for byte in &self.input {
+            write!(f, " {:02x}", byte)?;
+        }
+        writeln!(f)?;
+
+        write!(f, "steps")?;
+        for &(first, last) in &self.runs {
+            if first == last {
+                write!(f, " {}", first)?;
+            } else {
+                write!(f, " {}-{}", first, last)?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+impl Program {
+    /// Reset and execute the program, recording everything needed to replay it into `log`.
+    ///
+    /// Every executed instruction and every byte of input read is added to `log`. The log is
+    /// filled in even if the program errors, which is usually exactly the run worth replaying.
+    /// Save it with `log.to_string()` and run it again later with `Program::replay()`.
+    ///
+    /// # Errors
+    ///
+    /// Will return the same runtime BF errors as `Program::execute()`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::{ExecutionLog, Program};
+    /// let mut prgm = Program::from_string(",[.,]");
+    /// let mut input = "hi\0".chars();
+    /// let mut log = ExecutionLog::new();
+    ///
+    /// prgm.execute_logging(&mut log, || input.next().unwrap(), |_| ())
+    ///     .unwrap();
+    ///
+    /// // The same output comes back out without needing the original input.
+    /// let mut output = String::new();
+    /// prgm.replay(&log, |c| output.push(c)).unwrap();
+    /// assert_eq!(output, "hi");
+    /// ```
+    pub fn execute_logging<Fin, Fout>(
+        &mut self,
+        log: &mut ExecutionLog,
+        mut input: Fin,
+        mut output: Fout,
+    ) -> Result<()>
+    where
+        Fin: FnMut() -> char,
+        Fout: FnMut(char),
+    {
+        self.reset();
+
+        loop {
+            if self.done()? {
+                break;
+            }
+
+            log.record_step(self.instruction_ptr);
+            let logged_input = || {
+                let c = input();
+                // Anything bigger than a byte is an InvalidInput error as soon as it's read.
+                log.input.push(c as u32 as u8);
+                c
+            };
+            self.step(logged_input, &mut output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reset the program and run it again exactly as recorded in `log`.
+    ///
+    /// Input comes from the log rather than a closure, so interactive programs run the same way
+    /// every time. Each executed instruction is checked against the log, so a program that has
+    /// changed since the log was recorded is caught rather than silently doing something else.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::ReplayMismatch` if the program executes
+    /// different instructions than the log recorded, or any runtime BF error the recorded run
+    /// also ran into.
+    pub fn replay<Fout>(&mut self, log: &ExecutionLog, mut output: Fout) -> Result<()>
+    where
+        Fout: FnMut(char),
+    {
+        let mismatch = || BFError {
+            kind: BFErrorKind::ReplayMismatch,
+        };
+
+        self.reset();
+        let mut steps = log.steps();
+        let mut input = log.input.iter();
+
+        loop {
+            let expected = steps.next();
+            if self.done()? {
+                return match expected {
+                    Some(_) => Err(mismatch()),
+                    None => Ok(()),
+                };
+            }
+            if expected != Some(self.instruction_ptr) {
+                return Err(mismatch());
+            }
+
+            // Running out of logged input also means the program went somewhere new.
+            let mut input_missing = false;
+            let logged_input = || match input.next() {
+                Some(&byte) => byte as char,
+                None => {
+                    input_missing = true;
+                    '\0'
+                }
+            };
+            self.step(logged_input, &mut output)?;
+
+            if input_missing {
+                return Err(mismatch());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_round_trip() {
+        let mut prgm = Program::from_string("+[>,.<-]>,");
+        let mut input = "xy".chars();
+        let mut log = ExecutionLog::new();
+
+        prgm.execute_logging(&mut log, || input.next().unwrap_or('\0'), |_| ())
+            .unwrap();
+
+        let text = log.to_string();
+        assert_eq!(text, "rbf-log 1\ninput 78 79\nsteps 0-7 1 8-9\n");
+        assert_eq!(ExecutionLog::from_string(&text).unwrap(), log);
+
+        let mut output = String::new();
+        prgm.replay(&log, |c| output.push(c)).unwrap();
+        assert_eq!(output, "x");
+    }
+
+    #[test]
+    fn replay_errors() {
+        // The recorded run's error comes back during replay.
+        let mut prgm = Program::from_string("+<");
+        let mut log = ExecutionLog::new();
+        let result = prgm
+            .execute_logging(&mut log, || ' ', |_| ())
+            .map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+
+        let result = prgm.replay(&log, |_| ()).map_err(|e| e.kind);
+        assert_eq!(result, Err(BFErrorKind::CellBoundsError));
+
+        // A different program doesn't follow the log.
+        let log = ExecutionLog::from_string("rbf-log 1\ninput\nsteps 0-3\n").unwrap();
+        for source in ["+++", "+++++", "+[-]"] {
+            let result = Program::from_string(source)
+                .replay(&log, |_| ())
+                .map_err(|e| e.kind);
+            assert_eq!(result, Err(BFErrorKind::ReplayMismatch));
+        }
+        assert!(Program::from_string("++++").replay(&log, |_| ()).is_ok());
+    }
+
+    #[test]
+    fn invalid_logs() {
+        for text in [
+            "",
+            "rbf-log 2\ninput\nsteps\n",
+            "rbf-log 1\nsteps\ninput\n",
+            "rbf-log 1\ninput 6\nsteps\n",
+            "rbf-log 1\ninput zz\nsteps\n",
+            "rbf-log 1\ninput\nsteps 3-1\n",
+            "rbf-log 1\ninput\nsteps -1\n",
+            "rbf-log 1\ninput\n",
+            "rbf-log 1\ninput\nsteps\nmore\n",
+        ] {
+            let result = ExecutionLog::from_string(text).map_err(|e| e.kind);
+            assert_eq!(result, Err(BFErrorKind::InvalidFormat), "{:?}", text);
+        }
+    }
+}
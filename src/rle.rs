@@ -0,0 +1,212 @@
+//! A compact run-length text format for storing instructions as they are, optimized or not.
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::{Instruct, Instructions, Result};
+
+impl Instructions {
+    /// Serialize the instructions into the crate's run-length format.
+    ///
+    /// Unlike `to_string()`, which expands everything back into plain BF, this keeps the
+    /// instructions exactly as they are, so optimized programs can be saved and loaded again with
+    /// `Instructions::from_rle()` without running the optimizer again. Only this crate
+    /// understands the format.
+    ///
+    /// Each instruction is written as its BF command, followed by the count for `+`, `-`, `>`
+    /// and `<` when it isn't 1, and instructions are separated by spaces. An instruction that
+    /// was collapsed down to nothing is kept with a count of 0. Setting a cell, which is what
+    /// loops like `[-]` are optimized into, is written as `=` followed by the value. Moving a
+    /// cell, which is what loops like `[->+<]` and `[->+>+<<]` are optimized into, is written
+    /// as `m` followed by the offsets of the cells it's moved onto, separated by a comma.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let mut instructions = Instructions::from_string("++++++++++++[->>>+<<<]>[-]+");
+    /// instructions.optimize();
+    ///
+    /// assert_eq!(instructions.to_rle(), "+12 m3 > =1");
+    /// ```
+    pub fn to_rle(&self) -> String {
+        let tokens: Vec<String> = self
+            .0
+            .iter()
+            .map(|instruction| match *instruction {
+                Instruct::MvPtr(n) => run_token(if n < 0 { '<' } else { '>' }, n),
+                Instruct::MvValue(n) => run_token(if n < 0 { '-' } else { '+' }, n),
+                Instruct::SetValue(n) => format!("={}", n),
+                Instruct::Move(to) => format!("m{}", to),
+                Instruct::Copy(a, b) => format!("m{},{}", a, b),
+                Instruct::Output => ".".to_string(),
+                Instruct::Input => ",".to_string(),
+                Instruct::InputLen => "?".to_string(),
+                Instruct::OpenLoop => "[".to_string(),
+                Instruct::CloseLoop => "]".to_string(),
+            })
+            .collect();
+
+        tokens.join(" ")
+    }
+
+    /// Parse instructions from the run-length format made by `Instructions::to_rle()`.
+    ///
+    /// Whitespace between instructions is optional, but unlike BF source, there are no
+    /// comments. A count can follow `+`, `-`, `>` and `<`, and is 1 if left out. A value must
+    /// follow `=`, and one or two offsets must follow `m`.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error with kind `BFErrorKind::InvalidFormat` if there's anything other
+    /// than instructions and whitespace, a count follows `.`, `,`, `[` or `]`, `=` has no value
+    /// or one over 255, `m` doesn't have one or two offsets, or a count is too big.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use rbf::Instructions;
+    /// let instructions = Instructions::from_rle("+3 >2 -").unwrap();
+    ///
+    /// assert_eq!(instructions.to_string(), "+++>>-");
+    /// ```
+    pub fn from_rle(rle: &str) -> Result<Instructions> {
+        let invalid = || BFError {
+            kind: BFErrorKind::InvalidFormat,
+        };
+
+        let mut instructions = vec![];
+        let mut chars = rle.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+
+            if c == 'm' {
+                let mut offsets = vec![read_offset(&mut chars).ok_or_else(invalid)?];
+                if chars.next_if_eq(&',').is_some() {
+                    offsets.push(read_offset(&mut chars).ok_or_else(invalid)?);
+                }
+                instructions.push(match offsets[..] {
+                    [to] => Instruct::Move(to),
+                    [a, b] => Instruct::Copy(a, b),
+                    _ => unreachable!("There's always one or two offsets."),
+                });
+                continue;
+            }
+
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            let count = match digits.as_str() {
+                "" => None,
+                digits => Some(digits.parse::<isize>().map_err(|_| invalid())?),
+            };
+
+            let instruction = match (c, count) {
+                ('>', n) => Instruct::MvPtr(n.unwrap_or(1)),
+                ('<', n) => Instruct::MvPtr(-n.unwrap_or(1)),
+                ('+', n) => Instruct::MvValue(n.unwrap_or(1)),
+                ('-', n) => Instruct::MvValue(-n.unwrap_or(1)),
+                ('=', Some(n)) => Instruct::SetValue(u8::try_from(n).map_err(|_| invalid())?),
+                ('.', None) => Instruct::Output,
+                (',', None) => Instruct::Input,
+                ('?', None) => Instruct::InputLen,
+                ('[', None) => Instruct::OpenLoop,
+                (']', None) => Instruct::CloseLoop,
+                _ => return Err(invalid()),
+            };
+            instructions.push(instruction);
+        }
+
+        Ok(Instructions(instructions, None))
+    }
+}
+
+/// Read a signed offset off the front of `chars`, or None if there isn't a valid one.
+fn read_offset(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<isize> {
+    let mut offset = String::new();
+    if let Some(sign) = chars.next_if_eq(&'-') {
+        offset.push(sign);
+    }
+    while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+        offset.push(d);
+    }
+    offset.parse().ok()
+}
+
+/// Format a run of `+`, `-`, `>` or `<`, leaving the count out when it's 1.
+fn run_token(command: char, n: isize) -> String {
+    match n.unsigned_abs() {
+        1 => command.to_string(),
+        count => format!("{}{}", command, count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rle_round_trip() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.,+-<>";
+
+        let plain = Instructions::from_string(source);
+        assert_eq!(Instructions::from_rle(&plain.to_rle()).unwrap(), plain);
+
+        // Collapsed instructions come back exactly, including ones that cancelled out.
+        let mut combined = Instructions::from_string(source);
+        combined.optimize_combine_multiples();
+        assert!(combined.to_rle().ends_with(", +0 >0"));
+        assert_eq!(
+            Instructions::from_rle(&combined.to_rle()).unwrap(),
+            combined
+        );
+
+        let mut optimized = Instructions::from_string(source);
+        optimized.optimize();
+        assert_eq!(
+            Instructions::from_rle(&optimized.to_rle()).unwrap(),
+            optimized
+        );
+    }
+
+    #[test]
+    fn rle_parsing() {
+        assert_eq!(
+            Instructions::from_rle("+2>3[-]<\n.,").unwrap(),
+            Instructions::from_rle("+2 >3 [ - ] < . ,").unwrap()
+        );
+        assert_eq!(
+            Instructions::from_rle("").unwrap(),
+            Instructions::from_string("")
+        );
+
+        assert_eq!(
+            Instructions::from_rle("=0 + =255").unwrap().to_string(),
+            "[-]+[-]-"
+        );
+        assert_eq!(
+            Instructions::from_rle("m-2m1,3").unwrap().to_string(),
+            "[-<<+>>][->+>>+<<<]"
+        );
+
+        for rle in [
+            "+ comment",
+            ".2",
+            "[0",
+            "+99999999999999999999",
+            "3",
+            "=",
+            "=256",
+            "m",
+            "m-",
+            "m1,",
+            "m1,2,3",
+        ] {
+            let result = Instructions::from_rle(rle).map_err(|e| e.kind);
+            assert_eq!(result, Err(BFErrorKind::InvalidFormat), "{:?}", rle);
+        }
+    }
+}
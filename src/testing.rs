@@ -0,0 +1,101 @@
+//! Fixtures for testing how code handles BF errors, enabled with the `testing` feature.
+//!
+//! Each fixture is a minimal program that reliably fails with one kind of error when run
+//! through `testing::run()`, so tests of error handling don't need to hand-write their own.
+
+use crate::errors::{BFError, BFErrorKind};
+use crate::{Instructions, Program, ProgramBuilder};
+
+/// Steps `testing::run()` allows before giving up. The step limit fixture never finishes, and
+/// every other fixture fails well within this.
+pub const STEP_LIMIT: usize = 1000;
+
+/// Input `testing::run()` gives to every `,`. It doesn't fit in a cell, so reading it is always
+/// an error.
+pub const INVALID_INPUT: char = '\u{100}';
+
+/// Get a program that fails with `kind` when run with `testing::run()`.
+///
+/// Returns None for kinds that a program can't cause on its own. `IoError` needs IO that fails,
+/// `InstructionBoundsError` needs stepping past the end of a program, which `run()` never does,
+/// `InputExhausted` needs input that runs out, `Timeout` needs a time limit rather than a step
+/// limit, `InvalidFormat`, `ReplayMismatch` and `ProgramTooLarge` come from parsing and
+/// replaying rather than running, and `NotInLoop` comes from `Program::break_loop()`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use rbf::errors::BFErrorKind;
+/// use rbf::testing;
+///
+/// let mut prgm = testing::program_for(&BFErrorKind::CellBoundsError).unwrap();
+/// let error = testing::run(&mut prgm).unwrap_err();
+///
+/// assert_eq!(error.kind, BFErrorKind::CellBoundsError);
+/// ```
+pub fn program_for(kind: &BFErrorKind) -> Option<Program> {
+    let program = |source| ProgramBuilder::new(Instructions::from_string(source));
+
+    let prgm = match kind {
+        BFErrorKind::MissingOpen => program("]").build(),
+        BFErrorKind::MissingClose => program("[").build(),
+        BFErrorKind::InvalidInput => program(",").build(),
+        BFErrorKind::CellBoundsError => program("<").build(),
+        BFErrorKind::StepLimitExceeded => program("+[]").build(),
+        BFErrorKind::InvalidOutput => program("-.").strict_output(true).build(),
+        BFErrorKind::OutputLimitExceeded => program(".").max_output(0).build(),
+        BFErrorKind::SkipLimitExceeded => program("[++]").max_skip_distance(1).build(),
+        BFErrorKind::UnknownInputLength => {
+            ProgramBuilder::new(Instructions::from_string_extended("?")).build()
+        }
+        BFErrorKind::InstructionBoundsError
+        | BFErrorKind::IoError
+        | BFErrorKind::InputExhausted
+        | BFErrorKind::Timeout
+        | BFErrorKind::NotInLoop
+        | BFErrorKind::InvalidFormat
+        | BFErrorKind::ReplayMismatch
+        | BFErrorKind::ProgramTooLarge => return None,
+    };
+
+    Some(prgm)
+}
+
+/// Run a program the way the fixtures from `testing::program_for()` expect.
+///
+/// The program is limited to `STEP_LIMIT` steps, input is always `INVALID_INPUT`, and output
+/// is thrown away.
+///
+/// # Errors
+///
+/// Will return whatever error the program runs into.
+pub fn run(prgm: &mut Program) -> Result<(), BFError> {
+    prgm.execute_limited(STEP_LIMIT, || INVALID_INPUT, |_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_fail_as_promised() {
+        let kinds = [
+            BFErrorKind::MissingOpen,
+            BFErrorKind::MissingClose,
+            BFErrorKind::InvalidInput,
+            BFErrorKind::CellBoundsError,
+            BFErrorKind::StepLimitExceeded,
+            BFErrorKind::InvalidOutput,
+            BFErrorKind::OutputLimitExceeded,
+            BFErrorKind::SkipLimitExceeded,
+            BFErrorKind::UnknownInputLength,
+        ];
+
+        for kind in kinds {
+            let mut prgm = program_for(&kind).unwrap();
+            assert_eq!(run(&mut prgm).map_err(|e| e.kind), Err(kind));
+        }
+
+        assert!(program_for(&BFErrorKind::IoError).is_none());
+    }
+}